@@ -1,4 +1,4 @@
-use crate::commands::{CreateCommand, ExtractCommand, ValidateCommand, PreviewCommand};
+use crate::commands::{CreateCommand, ExtractCommand, ValidateCommand, PreviewCommand, DiagramsCommand, ConvertCommand, EmailCommand, ViewCommand, ImportCommand};
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 
@@ -21,6 +21,18 @@ pub enum Commands {
     Validate(ValidateCommand),
     /// Preview an MDZ file in browser
     Preview(PreviewCommand),
+    /// Pre-render ```dot diagrams into img/
+    Diagrams(DiagramsCommand),
+    /// Convert an MDZ file to another format
+    Convert(ConvertCommand),
+    /// Write an MDZ file as an email (.eml) with inlined styles
+    Email(EmailCommand),
+    /// Show an MDZ file in the terminal, through a pager
+    View(ViewCommand),
+    /// Print an MDZ file to the terminal with ANSI styling
+    Cat(ViewCommand),
+    /// Create an MDZ file from Markdown with embedded (data URL) images
+    Import(ImportCommand),
 }
 
 impl Cli {
@@ -30,6 +42,15 @@ impl Cli {
             Commands::Extract(cmd) => cmd.execute().await,
             Commands::Validate(cmd) => cmd.execute().await,
             Commands::Preview(cmd) => cmd.execute().await,
+            Commands::Diagrams(cmd) => cmd.execute().await,
+            Commands::Convert(cmd) => cmd.execute().await,
+            Commands::Email(cmd) => cmd.execute().await,
+            Commands::View(cmd) => cmd.execute().await,
+            Commands::Cat(mut cmd) => {
+                cmd.no_pager = true;
+                cmd.execute().await
+            }
+            Commands::Import(cmd) => cmd.execute().await,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,196 @@
+use anyhow::{Result, Context};
+use clap::Args;
+use colored::*;
+use mdz_core::{EmbeddedImages, Margins, MarkdownDialect, MdzParser, MdzRenderer, PageSize, PdfOptions, RenderOptions, TextOptions};
+use std::fs;
+use std::path::PathBuf;
+
+/// Output format of `mdz convert`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvertFormat {
+    Pdf,
+    Epub,
+    Docx,
+    Mhtml,
+    /// Directory with `index.html` and its assets
+    Site,
+    /// Plain text
+    Text,
+    /// Single Markdown file with embedded images
+    MarkdownInline,
+}
+
+impl ConvertFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Epub => "epub",
+            Self::Docx => "docx",
+            Self::Mhtml => "mht",
+            Self::Site => "",
+            Self::Text => "txt",
+            Self::MarkdownInline => "md",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Pdf => "PDF",
+            Self::Epub => "EPUB",
+            Self::Docx => "DOCX",
+            Self::Mhtml => "MHTML",
+            Self::Site => "static site",
+            Self::Text => "plain text",
+            Self::MarkdownInline => "standalone Markdown",
+        }
+    }
+}
+
+fn parse_format(value: &str) -> std::result::Result<ConvertFormat, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "pdf" => Ok(ConvertFormat::Pdf),
+        "epub" => Ok(ConvertFormat::Epub),
+        "docx" => Ok(ConvertFormat::Docx),
+        "mhtml" | "mht" => Ok(ConvertFormat::Mhtml),
+        "site" => Ok(ConvertFormat::Site),
+        "text" | "txt" => Ok(ConvertFormat::Text),
+        "md-inline" => Ok(ConvertFormat::MarkdownInline),
+        _ => Err(format!("unknown format '{}' (expected pdf, epub, docx, mhtml, site, text or md-inline)", value)),
+    }
+}
+
+#[derive(Args)]
+pub struct ConvertCommand {
+    /// MDZ file to convert
+    pub input: PathBuf,
+
+    /// Output format: pdf, epub, docx, mhtml, site, text or md-inline
+    #[arg(long, value_parser = parse_format)]
+    pub to: ConvertFormat,
+
+    /// Output file path (defaults to the input name with the format's extension;
+    /// for sites, a directory named after the input)
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Markdown dialect: mdz or commonmark (defaults to the document's declared dialect)
+    #[arg(long)]
+    pub dialect: Option<MarkdownDialect>,
+
+    /// PDF page size such as A4, Letter or "A4 landscape" (overrides css/print.css)
+    #[arg(long)]
+    pub page_size: Option<PageSize>,
+
+    /// PDF page margins as CSS lengths, e.g. 20mm or "2cm 1.5cm" (overrides css/print.css)
+    #[arg(long)]
+    pub margin: Option<Margins>,
+
+    /// PDF body font size in points
+    #[arg(long)]
+    pub font_size: Option<f32>,
+
+    /// PDF page header; {page}, {pages} and {title} are replaced, `|` separates left, center and right
+    #[arg(long)]
+    pub header: Option<String>,
+
+    /// TrueType or OpenType font to embed in PDFs for text the standard fonts cannot show
+    #[arg(long)]
+    pub font: Option<PathBuf>,
+
+    /// PDF page footer in the same format as --header
+    #[arg(long, default_value = "{page} / {pages}")]
+    pub footer: String,
+
+    /// Omit the PDF page footer
+    #[arg(long)]
+    pub no_footer: bool,
+
+    /// Add content hashes to site asset file names for long-term caching
+    #[arg(long)]
+    pub hash_assets: bool,
+
+    /// Text line width; 0 keeps each paragraph on one line
+    #[arg(long, default_value_t = 72)]
+    pub width: usize,
+
+    /// Put text link targets in parentheses instead of numbered references
+    #[arg(long)]
+    pub inline_links: bool,
+
+    /// Define md-inline images once at the end of the file instead of in place
+    #[arg(long)]
+    pub image_references: bool,
+}
+
+impl ConvertCommand {
+    pub async fn execute(self) -> Result<()> {
+        println!("{} Converting to {}...", "●".cyan(), self.to.label());
+
+        if !self.input.exists() {
+            anyhow::bail!("Input file does not exist: {}", self.input.display());
+        }
+
+        let document = MdzParser::parse_file(&self.input)
+            .context("Failed to parse MDZ file")?;
+
+        let options = RenderOptions {
+            dialect: self.dialect,
+            pdf: PdfOptions {
+                page_size: self.page_size,
+                margins: self.margin,
+                font_size: self.font_size,
+                header: self.header.clone(),
+                footer: (!self.no_footer).then(|| self.footer.clone()),
+                font: self.font.clone(),
+            },
+            hash_asset_names: self.hash_assets,
+            text: TextOptions {
+                width: (self.width > 0).then_some(self.width),
+                link_references: !self.inline_links,
+            },
+            embedded_images: if self.image_references { EmbeddedImages::References } else { EmbeddedImages::Inline },
+            ..Default::default()
+        };
+        let renderer = MdzRenderer::with_options(options);
+
+        let output_path = self.output.clone()
+            .unwrap_or_else(|| self.input.with_extension(self.to.extension()));
+
+        let output = match self.to {
+            ConvertFormat::Pdf => {
+                let pdf = renderer.render_pdf(&document)
+                    .context("Failed to render PDF")?;
+                for warning in &pdf.warnings {
+                    println!("  {} {}", "!".yellow(), warning);
+                }
+                pdf.data
+            }
+            ConvertFormat::Epub => renderer.render_epub(&document)
+                .context("Failed to build EPUB")?,
+            ConvertFormat::Docx => renderer.render_docx(&document)
+                .context("Failed to build DOCX")?,
+            ConvertFormat::Mhtml => renderer.render_mhtml(&document)
+                .context("Failed to build MHTML")?,
+            ConvertFormat::Text => renderer.render_text(&document)
+                .context("Failed to render text")?
+                .into_bytes(),
+            ConvertFormat::MarkdownInline => renderer.render_standalone_markdown(&document)
+                .context("Failed to embed images")?
+                .into_bytes(),
+            ConvertFormat::Site => {
+                let site = renderer.render_site(&document)
+                    .context("Failed to render site")?;
+                site.write_to(&output_path)
+                    .context("Failed to write site")?;
+                println!("{} Converted: {} ({} files)", "✓".green(), output_path.display(), site.files.len());
+                return Ok(());
+            }
+        };
+
+        fs::write(&output_path, output)
+            .context("Failed to write output file")?;
+
+        println!("{} Converted: {}", "✓".green(), output_path.display());
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use anyhow::{Result, Context};
 use clap::Args;
 use colored::*;
+use mdz_core::MarkdownDialect;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::io::Write;
@@ -19,6 +20,10 @@ pub struct CreateCommand {
     #[arg(long, default_value = "6")]
     pub compression: u8,
     
+    /// Record the Markdown dialect (mdz or commonmark) in metadata.json
+    #[arg(long)]
+    pub dialect: Option<MarkdownDialect>,
+
     /// Verbose output
     #[arg(long, short)]
     pub verbose: bool,
@@ -63,6 +68,29 @@ impl CreateCommand {
             println!("  {} Added main.md", "✓".green());
         }
 
+        // Add metadata.json, recording the dialect if requested
+        let metadata_path = self.from.join("metadata.json");
+        if self.dialect.is_some() || metadata_path.exists() {
+            let mut metadata: serde_json::Value = if metadata_path.exists() {
+                let content = fs::read_to_string(&metadata_path)
+                    .context("Failed to read metadata.json")?;
+                serde_json::from_str(&content).context("Failed to parse metadata.json")?
+            } else {
+                serde_json::json!({ "version": "1.1" })
+            };
+            if let Some(dialect) = self.dialect {
+                metadata["dialect"] = serde_json::to_value(dialect)?;
+            }
+
+            zip.start_file("metadata.json", options)
+                .context("Failed to start ZIP file entry")?;
+            zip.write_all(serde_json::to_string_pretty(&metadata)?.as_bytes())
+                .context("Failed to write metadata.json")?;
+            if self.verbose {
+                println!("  {} Added metadata.json", "✓".green());
+            }
+        }
+
         // Add img/ directory if exists
         let img_dir = self.from.join("img");
         if img_dir.exists() && img_dir.is_dir() {
@@ -81,6 +109,15 @@ impl CreateCommand {
             }
         }
 
+        // Add fonts/ directory if exists
+        let fonts_dir = self.from.join("fonts");
+        if fonts_dir.exists() && fonts_dir.is_dir() {
+            self.add_directory_to_zip(&mut zip, &fonts_dir, "fonts", &options)?;
+            if self.verbose {
+                println!("  {} Added fonts/ directory", "✓".green());
+            }
+        }
+
         zip.finish().context("Failed to finalize ZIP file")?;
 
         println!("{} MDZ file created: {}", "✓".green(), self.output.display());
@@ -0,0 +1,83 @@
+use anyhow::{Result, Context};
+use clap::Args;
+use colored::*;
+use mdz_core::diagram;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use zip::{ZipArchive, ZipWriter, write::FileOptions};
+
+#[derive(Args)]
+pub struct DiagramsCommand {
+    /// MDZ file containing ```dot diagrams
+    pub input: PathBuf,
+
+    /// Output MDZ file path (defaults to updating the input in place)
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Verbose output
+    #[arg(long, short)]
+    pub verbose: bool,
+}
+
+impl DiagramsCommand {
+    pub async fn execute(self) -> Result<()> {
+        println!("{} Pre-rendering diagrams...", "●".cyan());
+
+        if !self.input.exists() {
+            anyhow::bail!("Input file does not exist: {}", self.input.display());
+        }
+
+        let file = fs::File::open(&self.input)
+            .context("Failed to open input file")?;
+        let mut archive = ZipArchive::new(file)
+            .context("Failed to read ZIP archive")?;
+
+        let mut markdown = String::new();
+        archive.by_name("main.md")
+            .context("Required file 'main.md' not found")?
+            .read_to_string(&mut markdown)
+            .context("Failed to read main.md")?;
+
+        let rendered = diagram::prerender(&markdown)
+            .context("Failed to render diagram")?;
+        if rendered.images.is_empty() {
+            println!("{} No diagrams found", "✓".green());
+            return Ok(());
+        }
+
+        // Write to a temporary file first so the input can be replaced safely
+        let output = self.output.clone().unwrap_or_else(|| self.input.clone());
+        let temp = output.with_extension("mdz.tmp");
+        let mut zip = ZipWriter::new(fs::File::create(&temp).context("Failed to create output file")?);
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+        let replaced: HashSet<&str> = rendered.images.iter()
+            .map(|(path, _)| path.as_str())
+            .chain(["main.md"])
+            .collect();
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i).context("Failed to read ZIP entry")?;
+            if !replaced.contains(entry.name()) {
+                zip.raw_copy_file(entry).context("Failed to copy ZIP entry")?;
+            }
+        }
+
+        zip.start_file("main.md", options)?;
+        zip.write_all(rendered.markdown.as_bytes())?;
+        for (path, svg) in &rendered.images {
+            zip.start_file(path.as_str(), options)?;
+            zip.write_all(svg.as_bytes())?;
+            if self.verbose {
+                println!("  {} {}", "·".blue(), path);
+            }
+        }
+        zip.finish().context("Failed to finalize ZIP file")?;
+        fs::rename(&temp, &output).context("Failed to write output file")?;
+
+        println!("{} Rendered {} diagram(s) into {}", "✓".green(), rendered.images.len(), output.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::{Result, Context};
+use clap::Args;
+use colored::*;
+use mdz_core::{EmailAddresses, MarkdownDialect, MdzParser, MdzRenderer, RenderOptions, SvgMode};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct EmailCommand {
+    /// MDZ file to turn into an email
+    pub input: PathBuf,
+
+    /// Output .eml file path (defaults to the input name with .eml)
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Sender, e.g. "Release Bot <releases@example.com>"
+    #[arg(long)]
+    pub from: String,
+
+    /// Recipient (repeatable)
+    #[arg(long)]
+    pub to: Vec<String>,
+
+    /// Carbon-copy recipient (repeatable)
+    #[arg(long)]
+    pub cc: Vec<String>,
+
+    /// Subject line (defaults to the document title)
+    #[arg(long)]
+    pub subject: Option<String>,
+
+    /// Send SVG images as PNG, which more mail clients display
+    #[arg(long)]
+    pub rasterize_svg: bool,
+
+    /// Markdown dialect: mdz or commonmark (defaults to the document's declared dialect)
+    #[arg(long)]
+    pub dialect: Option<MarkdownDialect>,
+}
+
+impl EmailCommand {
+    pub async fn execute(self) -> Result<()> {
+        println!("{} Building email...", "●".cyan());
+
+        if !self.input.exists() {
+            anyhow::bail!("Input file does not exist: {}", self.input.display());
+        }
+
+        let document = MdzParser::parse_file(&self.input)
+            .context("Failed to parse MDZ file")?;
+
+        let options = RenderOptions {
+            dialect: self.dialect,
+            svg_mode: if self.rasterize_svg { SvgMode::Rasterize } else { SvgMode::Embed },
+            ..Default::default()
+        };
+        let renderer = MdzRenderer::with_options(options);
+        let mut message = renderer.render_email(&document)
+            .context("Failed to render email")?;
+        if let Some(subject) = &self.subject {
+            message.subject = subject.clone();
+        }
+
+        let addresses = EmailAddresses {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            cc: self.cc.clone(),
+        };
+        let output_path = self.output.clone()
+            .unwrap_or_else(|| self.input.with_extension("eml"));
+        fs::write(&output_path, message.to_eml(&addresses))
+            .context("Failed to write email file")?;
+
+        println!("{} Email written: {}", "✓".green(), output_path.display());
+        println!("  Subject: {}", message.subject);
+        println!("  Inline images: {}", message.attachments.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::{Result, Context};
+use clap::Args;
+use colored::*;
+use mdz_core::{standalone, MarkdownDialect};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use zip::{ZipWriter, write::FileOptions};
+
+#[derive(Args)]
+pub struct ImportCommand {
+    /// Markdown file with images embedded as data URLs
+    pub input: PathBuf,
+
+    /// Output MDZ file path (defaults to the input name with .mdz)
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Markdown dialect: mdz or commonmark, also recorded in metadata.json
+    #[arg(long)]
+    pub dialect: Option<MarkdownDialect>,
+
+    /// Verbose output
+    #[arg(long, short)]
+    pub verbose: bool,
+}
+
+impl ImportCommand {
+    pub async fn execute(self) -> Result<()> {
+        println!("{} Importing Markdown...", "●".cyan());
+
+        if !self.input.exists() {
+            anyhow::bail!("Input file does not exist: {}", self.input.display());
+        }
+
+        let markdown = fs::read_to_string(&self.input)
+            .context("Failed to read Markdown file")?;
+        let extracted = standalone::extract_images(&markdown, self.dialect.unwrap_or_default())
+            .context("Failed to extract embedded images")?;
+
+        let output = self.output.clone()
+            .unwrap_or_else(|| self.input.with_extension("mdz"));
+        let mut zip = ZipWriter::new(fs::File::create(&output).context("Failed to create output file")?);
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+        zip.start_file("main.md", options)?;
+        zip.write_all(extracted.markdown.as_bytes())?;
+        if let Some(dialect) = self.dialect {
+            let metadata = serde_json::json!({ "version": "1.1", "dialect": dialect });
+            zip.start_file("metadata.json", options)?;
+            zip.write_all(serde_json::to_string_pretty(&metadata)?.as_bytes())?;
+        }
+        for (path, data) in &extracted.images {
+            zip.start_file(path.as_str(), options)?;
+            zip.write_all(data)?;
+            if self.verbose {
+                println!("  {} {} ({} bytes)", "·".blue(), path, data.len());
+            }
+        }
+        zip.finish().context("Failed to finalize ZIP file")?;
+
+        println!("{} MDZ file created: {} ({} image(s))", "✓".green(), output.display(), extracted.images.len());
+        Ok(())
+    }
+}
@@ -2,8 +2,18 @@ mod create;
 mod extract;
 mod validate;
 mod preview;
+mod diagrams;
+mod convert;
+mod email;
+mod view;
+mod import;
 
 pub use create::CreateCommand;
 pub use extract::ExtractCommand;
 pub use validate::ValidateCommand;
-pub use preview::PreviewCommand;
\ No newline at end of file
+pub use preview::PreviewCommand;
+pub use diagrams::DiagramsCommand;
+pub use convert::ConvertCommand;
+pub use email::EmailCommand;
+pub use view::ViewCommand;
+pub use import::ImportCommand;
\ No newline at end of file
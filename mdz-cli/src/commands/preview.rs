@@ -1,10 +1,12 @@
 use anyhow::{Result, Context};
 use clap::Args;
 use colored::*;
-use mdz_core::{MdzParser, MdzRenderer, RenderOptions};
+use mdz_core::highlight::{self, DEFAULT_THEME};
+use mdz_core::{HtmlAllowlist, HtmlPolicy, MarkdownDialect, MdzError, MdzParser, MdzRenderer, RenderOptions, SvgMode};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::str::FromStr;
 
 #[derive(Args)]
 pub struct PreviewCommand {
@@ -26,6 +28,98 @@ pub struct PreviewCommand {
     /// Disable CSS styling
     #[arg(long)]
     pub no_css: bool,
+
+    /// Embed images and fonts referenced from document CSS as data URLs
+    #[arg(long)]
+    pub inline_css_urls: bool,
+
+    /// SVG handling: embed, sanitize or rasterize
+    #[arg(long, default_value = "embed")]
+    pub svg: SvgMode,
+
+    /// Inline HTML handling: allow, safe (allowlisted tags only) or escape
+    #[arg(long, default_value = "allow")]
+    pub html: HtmlMode,
+
+    /// Additional tag permitted with --html safe (repeatable)
+    #[arg(long = "allow-tag")]
+    pub allow_tags: Vec<String>,
+
+    /// Additional attribute permitted with --html safe (repeatable)
+    #[arg(long = "allow-attribute")]
+    pub allow_attributes: Vec<String>,
+
+    /// Emit a strict Content-Security-Policy and no-referrer links
+    #[arg(long)]
+    pub hardened: bool,
+
+    /// Syntax highlighting theme for code blocks
+    #[arg(long, default_value = DEFAULT_THEME)]
+    pub theme: String,
+
+    /// Disable syntax highlighting
+    #[arg(long)]
+    pub no_highlight: bool,
+
+    /// Show line numbers in code blocks
+    #[arg(long)]
+    pub line_numbers: bool,
+
+    /// Insert a table of contents (a `[TOC]` paragraph marks its position)
+    #[arg(long)]
+    pub toc: bool,
+
+    /// Deepest heading level listed in the table of contents
+    #[arg(long, default_value_t = 3)]
+    pub toc_depth: u32,
+
+    /// Add hover permalinks to headings
+    #[arg(long)]
+    pub permalinks: bool,
+
+    /// Markdown dialect: mdz or commonmark (defaults to the document's declared dialect)
+    #[arg(long)]
+    pub dialect: Option<MarkdownDialect>,
+
+    /// Enable smart punctuation (curly quotes, dashes, ellipses)
+    #[arg(long)]
+    pub smart: bool,
+
+    /// Enable heading attributes such as `{#id .class}`
+    #[arg(long)]
+    pub heading_attributes: bool,
+
+    /// Leave `$...$` math as text instead of converting it to MathML
+    #[arg(long)]
+    pub no_math: bool,
+
+    /// Show ```dot diagrams as code instead of rendering them
+    #[arg(long)]
+    pub no_diagrams: bool,
+}
+
+/// Value of `--html`; `safe` is combined with `--allow-tag`/`--allow-attribute`
+#[derive(Clone, Copy)]
+pub enum HtmlMode {
+    Allow,
+    Safe,
+    Escape,
+}
+
+impl FromStr for HtmlMode {
+    type Err = MdzError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Ok(Self::Allow),
+            "safe" => Ok(Self::Safe),
+            "escape" => Ok(Self::Escape),
+            _ => Err(MdzError::Parse(format!(
+                "Unknown HTML policy '{}' (expected allow, safe or escape)",
+                s
+            ))),
+        }
+    }
 }
 
 impl PreviewCommand {
@@ -40,10 +134,53 @@ impl PreviewCommand {
         let document = MdzParser::parse_file(&self.input)
             .context("Failed to parse MDZ file")?;
 
+        if !self.no_highlight && !highlight::available_themes().is_empty() && !highlight::has_theme(&self.theme) {
+            println!(
+                "  {} Unknown highlight theme '{}', using {}",
+                "!".yellow(),
+                self.theme,
+                DEFAULT_THEME
+            );
+        }
+
         // Prepare render options
-        let mut options = RenderOptions::default();
-        options.include_css = !self.no_css;
+        let mut options = RenderOptions {
+            include_css: !self.no_css,
+            inline_css_urls: self.inline_css_urls,
+            svg_mode: self.svg,
+            hardened: self.hardened,
+            highlight_theme: (!self.no_highlight).then(|| self.theme.clone()),
+            line_numbers: self.line_numbers,
+            heading_permalinks: self.permalinks,
+            toc: self.toc,
+            toc_depth: self.toc_depth,
+            diagrams: !self.no_diagrams,
+            ..Default::default()
+        };
         
+        options.html_policy = match self.html {
+            HtmlMode::Allow => HtmlPolicy::AllowAll,
+            HtmlMode::Escape => HtmlPolicy::EscapeAll,
+            HtmlMode::Safe => {
+                let mut allowlist = HtmlAllowlist::default();
+                for tag in &self.allow_tags {
+                    allowlist.allow_tag(tag);
+                }
+                for attribute in &self.allow_attributes {
+                    allowlist.allow_attribute(attribute);
+                }
+                HtmlPolicy::SafeSubset(allowlist)
+            }
+        };
+
+        let mut dialect = self.dialect
+            .or(document.metadata.dialect)
+            .unwrap_or_default();
+        dialect.smart_punctuation |= self.smart;
+        dialect.heading_attributes |= self.heading_attributes;
+        dialect.math &= !self.no_math;
+        options.dialect = Some(dialect);
+
         if let Some(css_path) = &self.css {
             let custom_css = fs::read_to_string(css_path)
                 .context("Failed to read custom CSS file")?;
@@ -77,13 +214,13 @@ impl PreviewCommand {
         if self.browser {
             self.open_in_browser(&output_path)?;
         } else {
-            println!("  {} Use --browser to open automatically", "💡".to_string());
+            println!("  💡 Use --browser to open automatically");
         }
 
         Ok(())
     }
 
-    fn open_in_browser(&self, path: &PathBuf) -> Result<()> {
+    fn open_in_browser(&self, path: &Path) -> Result<()> {
         let path_str = path.to_string_lossy();
         
         #[cfg(target_os = "macos")]
@@ -110,7 +247,7 @@ impl PreviewCommand {
                 .context("Failed to open browser on Linux")?;
         }
 
-        println!("  {} Opened in browser", "🌐".to_string());
+        println!("  🌐 Opened in browser");
         Ok(())
     }
 }
\ No newline at end of file
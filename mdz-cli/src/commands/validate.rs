@@ -1,7 +1,7 @@
 use anyhow::{Result, Context};
 use clap::Args;
 use colored::*;
-use mdz_core::{MdzValidator, ValidationResult};
+use mdz_core::{MdzValidator, ValidationProfile, ValidationResult};
 use std::fs;
 use std::path::PathBuf;
 
@@ -17,6 +17,10 @@ pub struct ValidateCommand {
     /// Treat warnings as errors
     #[arg(long)]
     pub strict: bool,
+
+    /// Validation profile (v1.0-strict, v1.0-lenient, v1.1); defaults to the document's declared version
+    #[arg(long)]
+    pub profile: Option<ValidationProfile>,
 }
 
 impl ValidateCommand {
@@ -31,7 +35,12 @@ impl ValidateCommand {
         let file = fs::File::open(&self.input)
             .context("Failed to open input file")?;
 
-        let result = MdzValidator::validate(file)
+        let mut validator = MdzValidator::new();
+        if let Some(profile) = self.profile {
+            validator.set_profile(profile);
+        }
+
+        let result = validator.validate(file)
             .context("Failed to validate MDZ file")?;
 
         self.print_validation_result(&result);
@@ -50,27 +59,35 @@ impl ValidateCommand {
 
     fn print_validation_result(&self, result: &ValidationResult) {
         // Print structure status
-        println!("\n{} Structure Check:", "📋".to_string());
+        println!("\n📋 Structure Check:");
         self.print_check("main.md present", result.has_main_md);
         self.print_check("img/ directory", result.has_img_dir);
         self.print_check("css/ directory", result.has_css_dir);
         self.print_check("css/style.css", result.has_main_css);
 
+        println!("\n📐 Profile: {}", result.profile);
+        if let Some(version) = &result.declared_version {
+            println!("  Declared spec version: {}", version);
+        }
+        for feature in &result.unsupported_features {
+            println!("  {} Not understood by v{} readers: {}", "!".yellow(), result.profile.spec_version(), feature);
+        }
+
         // Print file counts
         if self.detailed {
-            println!("\n{} File Summary:", "📁".to_string());
+            println!("\n📁 File Summary:");
             println!("  Image files: {}", result.image_files.len());
             println!("  CSS files: {}", result.css_files.len());
 
             if !result.image_files.is_empty() {
-                println!("\n  {} Images:", "🖼️".to_string());
+                println!("\n  🖼️ Images:");
                 for img in &result.image_files {
                     println!("    {}", img);
                 }
             }
 
             if !result.css_files.is_empty() {
-                println!("\n  {} CSS Files:", "🎨".to_string());
+                println!("\n  🎨 CSS Files:");
                 for css in &result.css_files {
                     println!("    {}", css);
                 }
@@ -79,7 +96,7 @@ impl ValidateCommand {
 
         // Print errors
         if !result.errors.is_empty() {
-            println!("\n{} Errors:", "❌".to_string());
+            println!("\n❌ Errors:");
             for error in &result.errors {
                 println!("  {} {}", "✗".red(), error);
             }
@@ -87,14 +104,14 @@ impl ValidateCommand {
 
         // Print warnings
         if !result.warnings.is_empty() {
-            println!("\n{} Warnings:", "⚠️".to_string());
+            println!("\n⚠️ Warnings:");
             for warning in &result.warnings {
                 println!("  {} {}", "!".yellow(), warning);
             }
         }
 
         // Overall status
-        println!("\n{} Overall Status:", "📊".to_string());
+        println!("\n📊 Overall Status:");
         if result.is_valid() {
             println!("  {} Valid MDZ format", "✓".green());
         } else {
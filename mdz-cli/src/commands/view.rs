@@ -0,0 +1,133 @@
+use anyhow::{Result, Context};
+use clap::Args;
+use mdz_core::{MarkdownDialect, MdzParser, MdzRenderer, RenderOptions, TerminalOptions, TextOptions};
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Widest layout used when the width is taken from the terminal
+const MAX_WIDTH: usize = 100;
+
+#[derive(Args)]
+pub struct ViewCommand {
+    /// MDZ file to show
+    pub input: PathBuf,
+
+    /// Line width (defaults to the terminal width, at most 100 columns)
+    #[arg(long, short)]
+    pub width: Option<usize>,
+
+    /// Print directly instead of through $PAGER
+    #[arg(long)]
+    pub no_pager: bool,
+
+    /// Styling: auto (when writing to a terminal), always or never
+    #[arg(long, default_value = "auto", value_parser = ["auto", "always", "never"])]
+    pub color: String,
+
+    /// Number links instead of making them clickable with OSC 8 escapes
+    #[arg(long)]
+    pub no_hyperlinks: bool,
+
+    /// Syntax highlighting theme for code blocks
+    #[arg(long, default_value = "base16-ocean.dark")]
+    pub theme: String,
+
+    /// Disable syntax highlighting
+    #[arg(long)]
+    pub no_highlight: bool,
+
+    /// Markdown dialect: mdz or commonmark (defaults to the document's declared dialect)
+    #[arg(long)]
+    pub dialect: Option<MarkdownDialect>,
+}
+
+impl ViewCommand {
+    pub async fn execute(self) -> Result<()> {
+        if !self.input.exists() {
+            anyhow::bail!("Input file does not exist: {}", self.input.display());
+        }
+
+        let document = MdzParser::parse_file(&self.input)
+            .context("Failed to parse MDZ file")?;
+
+        let interactive = io::stdout().is_terminal();
+        let styled = match self.color.as_str() {
+            "always" => true,
+            "never" => false,
+            _ => interactive,
+        };
+        let width = self.width
+            .unwrap_or_else(|| terminal_width().unwrap_or(80).min(MAX_WIDTH))
+            .max(20);
+
+        let options = RenderOptions {
+            dialect: self.dialect,
+            text: TextOptions {
+                width: Some(width),
+                ..Default::default()
+            },
+            terminal: TerminalOptions {
+                width,
+                hyperlinks: !self.no_hyperlinks,
+                theme: (!self.no_highlight).then(|| self.theme.clone()),
+            },
+            ..Default::default()
+        };
+        let renderer = MdzRenderer::with_options(options);
+        let output = if styled {
+            renderer.render_terminal(&document)
+                .context("Failed to render document")?
+        } else {
+            renderer.render_text(&document)
+                .context("Failed to render document")?
+        };
+
+        if interactive && !self.no_pager && page(&output).is_ok() {
+            return Ok(());
+        }
+        let mut stdout = io::stdout().lock();
+        // A closed pipe, as in `mdz cat doc.mdz | head`, is not an error
+        match stdout.write_all(output.as_bytes()) {
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result.context("Failed to write output"),
+        }
+    }
+}
+
+/// Show output through `$PAGER`, or `less` that exits when it fits on one screen
+fn page(output: &str) -> io::Result<()> {
+    let pager = env::var("PAGER").ok().filter(|pager| !pager.trim().is_empty());
+    let pager = pager.as_deref().unwrap_or("less");
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or("less");
+
+    let mut command = Command::new(program);
+    command.args(words).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        // Quit if one screen, pass colors and hyperlinks, keep the screen on exit
+        command.env("LESS", "FRX");
+    }
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may be quit before reading everything
+        match stdin.write_all(output.as_bytes()) {
+            Err(error) if error.kind() != io::ErrorKind::BrokenPipe => return Err(error),
+            _ => {}
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// Columns of the terminal, from `$COLUMNS` or `stty`
+fn terminal_width() -> Option<usize> {
+    if let Some(columns) = env::var("COLUMNS").ok().and_then(|value| value.parse().ok()).filter(|&columns| columns > 0) {
+        return Some(columns);
+    }
+    let tty = std::fs::File::open("/dev/tty").ok()?;
+    let output = Command::new("stty").arg("size").stdin(tty).output().ok()?;
+    let size = String::from_utf8(output.stdout).ok()?;
+    size.split_whitespace().nth(1)?.parse().ok().filter(|&columns| columns > 0)
+}
@@ -8,5 +8,15 @@ use cli::Cli;
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
-    cli.run().await
+
+    // The diagram layout engine asserts on some malformed graphs. Run the
+    // command as a task so such a panic ends in an error rather than a crash;
+    // with `panic = "abort"` it still aborts the process.
+    match tokio::spawn(cli.run()).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => {
+            anyhow::bail!("Internal error while processing the document (a ```dot diagram may be malformed)")
+        }
+        Err(e) => Err(e.into()),
+    }
 }
@@ -0,0 +1,89 @@
+use std::str::FromStr;
+
+/// GitHub alert type, written as `> [!NOTE]` on the first line of a blockquote
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AdmonitionKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl AdmonitionKind {
+    /// Marker name in lowercase, also used as CSS class suffix
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Tip => "tip",
+            Self::Important => "important",
+            Self::Warning => "warning",
+            Self::Caution => "caution",
+        }
+    }
+
+    /// Heading shown above the content
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Note => "Note",
+            Self::Tip => "Tip",
+            Self::Important => "Important",
+            Self::Warning => "Warning",
+            Self::Caution => "Caution",
+        }
+    }
+
+    /// Parse a `[!TYPE]` marker
+    pub fn from_marker(marker: &str) -> Option<Self> {
+        marker.trim().strip_prefix("[!")?.strip_suffix(']')?.parse().ok()
+    }
+
+    /// Inline SVG icon drawn with `currentColor`
+    fn icon(&self) -> &'static str {
+        match self {
+            Self::Note => {
+                r#"<circle cx="8" cy="8" r="6.5"/><path d="M8 7.25v4"/><circle cx="8" cy="4.75" r="0.5" fill="currentColor"/>"#
+            }
+            Self::Tip => {
+                r#"<path d="M8 1.75a4.25 4.25 0 0 0-2.5 7.7v1.8h5v-1.8A4.25 4.25 0 0 0 8 1.75z"/><path d="M6 13.75h4"/>"#
+            }
+            Self::Important => {
+                r#"<path d="M2 2.25h12v8.5H7l-3.5 3v-3H2z"/><path d="M8 4.5v3"/><circle cx="8" cy="9" r="0.5" fill="currentColor"/>"#
+            }
+            Self::Warning => {
+                r#"<path d="M8 1.75l6.5 12H1.5z"/><path d="M8 6v3.5"/><circle cx="8" cy="11.5" r="0.5" fill="currentColor"/>"#
+            }
+            Self::Caution => {
+                r#"<path d="M5.25 1.5h5.5l3.75 3.75v5.5l-3.75 3.75h-5.5L1.5 10.75v-5.5z"/><path d="M8 4.75v3.75"/><circle cx="8" cy="10.75" r="0.5" fill="currentColor"/>"#
+            }
+        }
+    }
+
+    /// Opening markup: container and title with icon
+    pub(crate) fn open_html(&self) -> String {
+        format!(
+            "<div class=\"admonition admonition-{name}\" role=\"note\">\n\
+             <p class=\"admonition-title\"><svg class=\"admonition-icon\" viewBox=\"0 0 16 16\" width=\"16\" height=\"16\" \
+             fill=\"none\" stroke=\"currentColor\" stroke-width=\"1.5\" stroke-linecap=\"round\" \
+             stroke-linejoin=\"round\" aria-hidden=\"true\">{icon}</svg>{title}</p>\n",
+            name = self.name(),
+            icon = self.icon(),
+            title = self.title(),
+        )
+    }
+}
+
+impl FromStr for AdmonitionKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "note" => Ok(Self::Note),
+            "tip" => Ok(Self::Tip),
+            "important" => Ok(Self::Important),
+            "warning" => Ok(Self::Warning),
+            "caution" => Ok(Self::Caution),
+            _ => Err(()),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::template::push_escaped;
+use pulldown_cmark::escape::escape_href;
+use pulldown_cmark::{Event, Tag};
+use std::collections::HashMap;
+
+/// Heading slug generator
+///
+/// Produces GitHub-style anchor IDs: lowercase, alphanumerics, hyphens and
+/// underscores kept, whitespace turned into hyphens, everything else dropped.
+/// Repeated slugs receive a numeric suffix (`intro`, `intro-1`, `intro-2`).
+/// The renderer and the validator share this so that `[Chapter 1](#chapter-1)`
+/// resolves the same way in both.
+#[derive(Debug, Default, Clone)]
+pub struct Slugger {
+    seen: HashMap<String, usize>,
+}
+
+impl Slugger {
+    /// Create a new slug generator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generate a unique slug for heading text
+    ///
+    /// The suffix is bumped until it gives a slug not yet taken, so a heading
+    /// literally named `Intro 1` does not collide with the second `Intro`.
+    pub fn slug(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let mut slug = base.clone();
+        while self.seen.contains_key(&slug) {
+            let count = self.seen.entry(base.clone()).or_default();
+            *count += 1;
+            slug = format!("{}-{}", base, count);
+        }
+        self.seen.insert(slug.clone(), 0);
+        slug
+    }
+
+    /// Anchor ID for a heading: its custom `{#id}`, which later slugs then
+    /// avoid, or a slug of its text
+    pub fn heading_id(&mut self, id: Option<&str>, text: &str) -> String {
+        match id {
+            Some(id) => {
+                self.seen.entry(id.to_string()).or_default();
+                id.to_string()
+            }
+            None => self.slug(text),
+        }
+    }
+}
+
+/// Convert heading text to an anchor slug (without de-duplication)
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter_map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                Some(c)
+            } else if c.is_whitespace() {
+                Some('-')
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Heading in the document outline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: u32,
+    pub id: String,
+    pub text: String,
+}
+
+/// Collect headings with their anchor IDs, in document order
+pub fn collect_headings<'a, 'e: 'a, I>(events: I) -> Vec<Heading>
+where
+    I: IntoIterator<Item = &'a Event<'e>>,
+{
+    let mut slugger = Slugger::new();
+    let mut headings = Vec::new();
+    let mut current: Option<(u32, Option<String>, String)> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading(level, id, _)) => {
+                current = Some((*level as u32, id.map(str::to_string), String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, heading)) = current.as_mut() {
+                    heading.push_str(text);
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                if let Some((level, id, text)) = current.take() {
+                    let id = slugger.heading_id(id.as_deref(), &text);
+                    headings.push(Heading { level, id, text });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    headings
+}
+
+/// Render a nested table of contents for headings up to `max_level`
+pub fn render_toc(headings: &[Heading], max_level: u32) -> String {
+    let mut html = String::from("<nav class=\"toc\">\n");
+    let mut stack: Vec<u32> = Vec::new();
+
+    for heading in headings.iter().filter(|h| h.level <= max_level) {
+        while stack.last().is_some_and(|&level| level > heading.level) {
+            html.push_str("</li>\n</ul>\n");
+            stack.pop();
+        }
+        match stack.last() {
+            Some(&level) if level == heading.level => html.push_str("</li>\n"),
+            _ => {
+                html.push_str("<ul>\n");
+                stack.push(heading.level);
+            }
+        }
+
+        html.push_str("<li><a href=\"#");
+        escape_href(&mut html, &heading.id).unwrap();
+        html.push_str("\">");
+        push_escaped(&mut html, &heading.text);
+        html.push_str("</a>");
+    }
+
+    for _ in stack {
+        html.push_str("</li>\n</ul>\n");
+    }
+    html.push_str("</nav>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{Options, Parser};
+
+    #[test]
+    fn slugify_keeps_word_characters() {
+        assert_eq!(slugify("  Hello, World! "), "hello-world");
+        assert_eq!(slugify("snake_case & kebab-case"), "snake_case--kebab-case");
+        assert_eq!(slugify("Überschrift 2"), "überschrift-2");
+    }
+
+    #[test]
+    fn repeated_slugs_are_numbered() {
+        let mut slugger = Slugger::new();
+        let slugs: Vec<String> = ["Intro", "Intro", "Intro"].iter().map(|text| slugger.slug(text)).collect();
+        assert_eq!(slugs, ["intro", "intro-1", "intro-2"]);
+    }
+
+    #[test]
+    fn numbered_slugs_skip_taken_ones() {
+        let mut slugger = Slugger::new();
+        let slugs: Vec<String> = ["Intro 1", "Intro", "Intro"].iter().map(|text| slugger.slug(text)).collect();
+        assert_eq!(slugs, ["intro-1", "intro", "intro-2"]);
+    }
+
+    #[test]
+    fn custom_ids_are_not_reused() {
+        let events: Vec<Event> = Parser::new_ext("# A {#b}\n\n# B\n", Options::ENABLE_HEADING_ATTRIBUTES).collect();
+        let ids: Vec<String> = collect_headings(&events).into_iter().map(|heading| heading.id).collect();
+        assert_eq!(ids, ["b", "b-1"]);
+    }
+
+    #[test]
+    fn toc_nests_by_level() {
+        let events: Vec<Event> = Parser::new("# One\n\n## Two\n\n# Three\n").collect();
+        let toc = render_toc(&collect_headings(&events), 3);
+        assert_eq!(
+            toc,
+            "<nav class=\"toc\">\n<ul>\n<li><a href=\"#one\">One</a><ul>\n<li><a href=\"#two\">Two</a></li>\n</ul>\n\
+             </li>\n<li><a href=\"#three\">Three</a></li>\n</ul>\n</nav>\n"
+        );
+    }
+}
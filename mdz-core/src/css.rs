@@ -0,0 +1,417 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Result of scanning a stylesheet
+#[derive(Debug, Clone, Default)]
+pub struct CssAnalysis {
+    pub references: Vec<CssReference>,
+    pub errors: Vec<CssSyntaxError>,
+}
+
+/// Resource referenced from a stylesheet
+#[derive(Debug, Clone)]
+pub struct CssReference {
+    pub kind: CssReferenceKind,
+    pub url: String,
+    pub line: usize,
+    /// Byte range of the whole `url(...)` token or `@import` target
+    pub span: Range<usize>,
+}
+
+/// How a resource is referenced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CssReferenceKind {
+    Import,
+    Url,
+}
+
+/// Stylesheet syntax error
+#[derive(Debug, Clone)]
+pub struct CssSyntaxError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Classification of a referenced URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CssTarget {
+    /// `data:` URL or fragment, nothing to load
+    Inline,
+    /// Remote resource, fetched by the viewer
+    External(String),
+    /// Archive path, resolved against the stylesheet location
+    Archive(String),
+}
+
+impl CssReference {
+    /// Classify the reference relative to the stylesheet's archive path
+    pub fn target(&self, stylesheet_path: &str) -> CssTarget {
+        classify_url(&self.url, stylesheet_path)
+    }
+}
+
+/// Scan a stylesheet for `@import` and `url()` references and syntax errors
+pub fn analyze(css: &str) -> CssAnalysis {
+    let mut analysis = CssAnalysis::default();
+    let bytes = css.as_bytes();
+    let mut pos = 0;
+    let mut line = 1;
+    let mut depth = 0usize;
+
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\n' => {
+                line += 1;
+                pos += 1;
+            }
+            b'/' if bytes.get(pos + 1) == Some(&b'*') => match css[pos + 2..].find("*/") {
+                Some(end) => {
+                    line += css[pos..pos + 2 + end].matches('\n').count();
+                    pos += end + 4;
+                }
+                None => {
+                    analysis.errors.push(CssSyntaxError { line, message: "Unterminated comment".to_string() });
+                    pos = bytes.len();
+                }
+            },
+            quote @ (b'"' | b'\'') => {
+                let (_, end, terminated) = scan_string(css, pos, quote);
+                if !terminated {
+                    analysis.errors.push(CssSyntaxError { line, message: "Unterminated string".to_string() });
+                }
+                line += css[pos..end].matches('\n').count();
+                pos = end;
+            }
+            b'{' => {
+                depth += 1;
+                pos += 1;
+            }
+            b'}' => {
+                if depth == 0 {
+                    analysis.errors.push(CssSyntaxError { line, message: "Unexpected '}'".to_string() });
+                } else {
+                    depth -= 1;
+                }
+                pos += 1;
+            }
+            b'@' if starts_with_ignore_case(&css[pos..], "@import") => {
+                let mut cursor = pos + "@import".len();
+                while cursor < bytes.len() && bytes[cursor].is_ascii_whitespace() {
+                    if bytes[cursor] == b'\n' {
+                        line += 1;
+                    }
+                    cursor += 1;
+                }
+                match bytes.get(cursor) {
+                    Some(&quote) if quote == b'"' || quote == b'\'' => {
+                        let (url, end, terminated) = scan_string(css, cursor, quote);
+                        if terminated {
+                            analysis.references.push(CssReference {
+                                kind: CssReferenceKind::Import,
+                                url,
+                                line,
+                                span: cursor..end,
+                            });
+                        } else {
+                            analysis.errors.push(CssSyntaxError { line, message: "Unterminated string".to_string() });
+                        }
+                        pos = end;
+                    }
+                    _ if starts_with_ignore_case(&css[cursor..], "url(") => {
+                        pos = scan_url(css, cursor, line, CssReferenceKind::Import, &mut analysis);
+                    }
+                    _ => {
+                        analysis.errors.push(CssSyntaxError { line, message: "Malformed @import".to_string() });
+                        pos = cursor;
+                    }
+                }
+            }
+            b'u' | b'U' if starts_with_ignore_case(&css[pos..], "url(") && !is_ident_byte(bytes, pos) => {
+                pos = scan_url(css, pos, line, CssReferenceKind::Url, &mut analysis);
+            }
+            _ => pos += 1,
+        }
+    }
+
+    if depth > 0 {
+        analysis.errors.push(CssSyntaxError { line, message: format!("{} unclosed block(s)", depth) });
+    }
+
+    analysis
+}
+
+/// Replace `url()` references using `replace`, which returns the new URL or `None` to keep it
+pub fn rewrite_urls<F>(css: &str, mut replace: F) -> String
+where
+    F: FnMut(&CssReference) -> Option<String>,
+{
+    rewrite_references(css, |r| if r.kind == CssReferenceKind::Url { replace(r) } else { None })
+}
+
+/// Replace `url()` and `@import` references using `replace`
+pub fn rewrite_references<F>(css: &str, mut replace: F) -> String
+where
+    F: FnMut(&CssReference) -> Option<String>,
+{
+    let analysis = analyze(css);
+    let mut output = String::with_capacity(css.len());
+    let mut last = 0;
+
+    for reference in &analysis.references {
+        if let Some(url) = replace(reference) {
+            output.push_str(&css[last..reference.span.start]);
+            output.push_str(&format!("url(\"{}\")", url.replace('"', "%22")));
+            last = reference.span.end;
+        }
+    }
+
+    output.push_str(&css[last..]);
+    output
+}
+
+/// Stylesheet with its comments removed
+pub(crate) fn strip_comments(css: &str) -> Cow<'_, str> {
+    static COMMENTS: OnceLock<regex::Regex> = OnceLock::new();
+    COMMENTS
+        .get_or_init(|| regex::Regex::new(r"(?s)/\*.*?\*/").unwrap())
+        .replace_all(css, "")
+}
+
+/// Position of the `}` closing the block opened at `open`
+pub(crate) fn matching_brace(css: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in css[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `(property, value, important)` for each declaration in a block, properties lowercased
+pub(crate) fn declarations(block: &str) -> impl Iterator<Item = (String, &str, bool)> {
+    block.split(';').filter_map(|declaration| {
+        let (property, value) = declaration.split_once(':')?;
+        let value = value.trim();
+        let (value, important) = match value.strip_suffix("!important") {
+            Some(value) => (value.trim_end(), true),
+            None => (value, false),
+        };
+        let property = property.trim().to_ascii_lowercase();
+        (!property.is_empty()).then_some((property, value, important))
+    })
+}
+
+/// Classify a URL found in a stylesheet located at `stylesheet_path`
+pub fn classify_url(url: &str, stylesheet_path: &str) -> CssTarget {
+    let url = url.trim();
+    let lower = url.to_ascii_lowercase();
+
+    if url.is_empty() || url.starts_with('#') || lower.starts_with("data:") {
+        return CssTarget::Inline;
+    }
+    if url.starts_with("//") || lower.contains("://") || lower.starts_with("mailto:") {
+        return CssTarget::External(url.to_string());
+    }
+
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let base = stylesheet_path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    CssTarget::Archive(resolve_path(base, path))
+}
+
+/// Resolve a relative path against an archive directory
+fn resolve_path(base: &str, path: &str) -> String {
+    let mut parts: Vec<&str> = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        base.split('/').filter(|p| !p.is_empty()).collect()
+    };
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            other => parts.push(other),
+        }
+    }
+
+    parts.join("/")
+}
+
+/// Scan a `url(...)` token starting at `start`, returning the position after it
+fn scan_url(css: &str, start: usize, line: usize, kind: CssReferenceKind, analysis: &mut CssAnalysis) -> usize {
+    let bytes = css.as_bytes();
+    let mut cursor = start + "url(".len();
+    while cursor < bytes.len() && bytes[cursor].is_ascii_whitespace() {
+        cursor += 1;
+    }
+
+    let url = match bytes.get(cursor) {
+        Some(&quote) if quote == b'"' || quote == b'\'' => {
+            let (url, end, terminated) = scan_string(css, cursor, quote);
+            if !terminated {
+                analysis.errors.push(CssSyntaxError { line, message: "Unterminated string".to_string() });
+                return end;
+            }
+            cursor = end;
+            url
+        }
+        _ => {
+            let mut url = String::new();
+            while cursor < bytes.len() && bytes[cursor] != b')' && bytes[cursor] != b'\n' {
+                let c = if bytes[cursor] == b'\\' && !matches!(bytes.get(cursor + 1), None | Some(b'\n')) {
+                    let (c, end) = scan_escape(css, cursor);
+                    cursor = end;
+                    c
+                } else {
+                    let c = css[cursor..].chars().next().unwrap();
+                    cursor += c.len_utf8();
+                    c
+                };
+                url.push(c);
+            }
+            url.trim_end().to_string()
+        }
+    };
+
+    while cursor < bytes.len() && bytes[cursor].is_ascii_whitespace() && bytes[cursor] != b'\n' {
+        cursor += 1;
+    }
+    if bytes.get(cursor) != Some(&b')') {
+        analysis.errors.push(CssSyntaxError { line, message: "Unterminated url()".to_string() });
+        return cursor;
+    }
+
+    analysis.references.push(CssReference { kind, url, line, span: start..cursor + 1 });
+    cursor + 1
+}
+
+/// Scan a quoted string, returning its unescaped value, end position and whether it was closed
+fn scan_string(css: &str, start: usize, quote: u8) -> (String, usize, bool) {
+    let bytes = css.as_bytes();
+    let mut value = String::new();
+    let mut cursor = start + 1;
+
+    while cursor < bytes.len() {
+        match bytes[cursor] {
+            b if b == quote => return (value, cursor + 1, true),
+            b'\n' => return (value, cursor, false),
+            b'\\' if cursor + 1 < bytes.len() => {
+                if bytes[cursor + 1] == b'\n' {
+                    cursor += 2;
+                } else {
+                    let (c, end) = scan_escape(css, cursor);
+                    value.push(c);
+                    cursor = end;
+                }
+            }
+            _ => {
+                let c = css[cursor..].chars().next().unwrap();
+                value.push(c);
+                cursor += c.len_utf8();
+            }
+        }
+    }
+
+    (value, cursor, false)
+}
+
+/// Decode the escape whose backslash is at `start`, returning the character and the position after it
+///
+/// Hex escapes (`\3a`) take up to six digits and one following whitespace
+/// character; any other escaped character stands for itself.
+fn scan_escape(css: &str, start: usize) -> (char, usize) {
+    let rest = &css[start + 1..];
+    let digits = rest.bytes().take(6).take_while(|b| b.is_ascii_hexdigit()).count();
+    if digits == 0 {
+        let c = rest.chars().next().unwrap();
+        return (c, start + 1 + c.len_utf8());
+    }
+
+    let code = u32::from_str_radix(&rest[..digits], 16).unwrap_or(0);
+    let c = char::from_u32(code).filter(|&c| c != '\0').unwrap_or('\u{FFFD}');
+    let mut end = start + 1 + digits;
+    if css[end..].starts_with("\r\n") {
+        end += 2;
+    } else if css[end..].starts_with([' ', '\t', '\n', '\r', '\x0c']) {
+        end += 1;
+    }
+    (c, end)
+}
+
+fn starts_with_ignore_case(haystack: &str, prefix: &str) -> bool {
+    haystack.len() >= prefix.len()
+        && haystack.as_bytes()[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+}
+
+/// Check if the byte before `pos` continues an identifier (e.g. `myurl(`)
+fn is_ident_byte(bytes: &[u8], pos: usize) -> bool {
+    pos > 0 && (bytes[pos - 1].is_ascii_alphanumeric() || bytes[pos - 1] == b'-' || bytes[pos - 1] == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(css: &str) -> Vec<CssTarget> {
+        analyze(css).references.iter().map(|reference| reference.target("css/style.css")).collect()
+    }
+
+    #[test]
+    fn classifies_references() {
+        let css = "@import \"print.css\";\nbody { background: url(../img/a.png?v=1) }\n\
+                   h1 { background: url('data:image/png;base64,AA') }\n@font-face { src: url(//cdn.example.com/f.woff) }";
+        assert_eq!(
+            targets(css),
+            [
+                CssTarget::Archive("css/print.css".to_string()),
+                CssTarget::Archive("img/a.png".to_string()),
+                CssTarget::Inline,
+                CssTarget::External("//cdn.example.com/f.woff".to_string()),
+            ]
+        );
+        assert_eq!(analyze(css).references[3].line, 4);
+    }
+
+    #[test]
+    fn decodes_escapes_before_classifying() {
+        for css in [
+            "a { b: url(\"https\\3a //evil.com/x\") }",
+            "a { b: url(https:\\/\\/evil.com/x) }",
+            "@import '\\68 ttps://evil.com/x';",
+        ] {
+            assert_eq!(targets(css), [CssTarget::External("https://evil.com/x".to_string())], "{}", css);
+        }
+        assert_eq!(targets("a { b: url(img\\29.png) }"), [CssTarget::Archive("css/img).png".to_string())]);
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let messages: Vec<String> =
+            analyze("a { b: \"open\n}\n}\n@import;\nc { d: url(x").errors.into_iter().map(|e| e.message).collect();
+        assert_eq!(
+            messages,
+            ["Unterminated string", "Unexpected '}'", "Malformed @import", "Unterminated url()", "1 unclosed block(s)"]
+        );
+    }
+
+    #[test]
+    fn rewrites_only_replaced_urls() {
+        let css = "a { b: url(x.png) } c { d: url('https://example.com/y.png') }";
+        let rewritten = rewrite_urls(css, |reference| match reference.target("css/style.css") {
+            CssTarget::External(_) => Some(String::new()),
+            _ => None,
+        });
+        assert_eq!(rewritten, "a { b: url(x.png) } c { d: url(\"\") }");
+    }
+}
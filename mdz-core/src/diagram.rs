@@ -0,0 +1,187 @@
+use crate::highlight::FenceInfo;
+use crate::{MdzError, Result};
+use std::path::{Component, Path};
+
+/// Fence attribute pointing at a pre-rendered copy of the diagram
+pub const RENDERED_ATTRIBUTE: &str = "rendered";
+
+/// Check if a fenced code block language is a Graphviz diagram
+pub fn is_diagram_language(language: &str) -> bool {
+    matches!(language.to_ascii_lowercase().as_str(), "dot" | "graphviz")
+}
+
+/// Archive path of a pre-rendered diagram, if the fence has one
+pub fn rendered_path(fence: &FenceInfo) -> Option<&str> {
+    fence
+        .attributes
+        .iter()
+        .find(|(key, _)| key == RENDERED_ATTRIBUTE)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Render Graphviz `dot` source to a standalone SVG document
+///
+/// Graphs without nodes are rejected up front, since the layout engine
+/// panics on them. It asserts rather than returning errors, so other
+/// malformed graphs may still panic; applications rendering untrusted
+/// diagrams in a `panic = "abort"` build should expect to be aborted.
+#[cfg(feature = "diagrams")]
+pub fn render_dot(source: &str) -> Result<String> {
+    use layout::backends::svg::SVGWriter;
+    use layout::gv::{DotParser, GraphBuilder};
+
+    let graph = DotParser::new(source)
+        .process()
+        .map_err(|e| MdzError::Render(format!("Invalid dot diagram: {}", e)))?;
+
+    let mut builder = GraphBuilder::new();
+    builder.visit_graph(&graph);
+    let mut visual = builder.get();
+    if visual.num_nodes() == 0 {
+        return Err(MdzError::Render("Diagram has no nodes".to_string()));
+    }
+    let mut writer = SVGWriter::new();
+    visual.do_it(false, false, false, &mut writer);
+    Ok(writer.finalize())
+}
+
+/// Render Graphviz `dot` source to a standalone SVG document
+#[cfg(not(feature = "diagrams"))]
+pub fn render_dot(_source: &str) -> Result<String> {
+    Err(MdzError::Render(
+        "Diagram rendering requires the `diagrams` feature".to_string(),
+    ))
+}
+
+/// Prepare a rendered SVG for inlining into HTML
+///
+/// Drops the XML declaration and prefixes element IDs with `prefix` so that
+/// markers and clip paths of several diagrams on one page do not collide.
+pub(crate) fn inline_svg(svg: &str, prefix: &str) -> String {
+    let svg = match svg.find("<svg") {
+        Some(start) => &svg[start..],
+        None => svg,
+    };
+    svg.replace("id=\"", &format!("id=\"{}", prefix))
+        .replace("href=\"#", &format!("href=\"#{}", prefix))
+        .replace("url(#", &format!("url(#{}", prefix))
+}
+
+/// Markdown with diagrams rendered to archive images
+#[derive(Debug, Clone, Default)]
+pub struct PrerenderedDiagrams {
+    /// Rewritten `main.md`
+    pub markdown: String,
+    /// `(archive path, SVG)` for every diagram
+    pub images: Vec<(String, String)>,
+}
+
+/// Render every `dot` block to `img/diagram-N.svg` for viewers without diagram support
+///
+/// The source block stays in place and is tagged with `rendered="img/..."`;
+/// an image reference is added after it the first time. The MDZ renderer shows
+/// the image instead of rendering tagged blocks again.
+pub fn prerender(markdown: &str) -> Result<PrerenderedDiagrams> {
+    let mut result = PrerenderedDiagrams::default();
+    let mut lines = markdown.split_inclusive('\n');
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start_matches(' ');
+        let Some((marker, info)) = opening_fence(trimmed).filter(|_| line.len() - trimmed.len() < 4) else {
+            result.markdown.push_str(line);
+            continue;
+        };
+
+        let fence = FenceInfo::parse(info.trim());
+        let is_diagram = fence.language.as_deref().is_some_and(is_diagram_language);
+
+        let mut source = String::new();
+        let mut closing = None;
+        for body_line in lines.by_ref() {
+            if is_closing_fence(body_line, &marker) {
+                closing = Some(body_line);
+                break;
+            }
+            source.push_str(body_line);
+        }
+
+        if !is_diagram {
+            result.markdown.push_str(line);
+            result.markdown.push_str(&source);
+            result.markdown.push_str(closing.unwrap_or(""));
+            continue;
+        }
+
+        let number = result.images.len() + 1;
+        let existing = rendered_path(&fence).map(str::to_string);
+        if let Some(path) = existing.as_deref().filter(|path| !is_image_path(path)) {
+            return Err(MdzError::InvalidFormat(format!(
+                "Diagram {}: rendered path must be a file in img/: {}",
+                number, path
+            )));
+        }
+        let path = existing.clone().unwrap_or_else(|| format!("img/diagram-{}.svg", number));
+        let svg = render_dot(&source).map_err(|e| match e {
+            MdzError::Render(message) => MdzError::Render(format!("Diagram {}: {}", number, message)),
+            other => other,
+        })?;
+        result.images.push((path.clone(), svg));
+
+        if existing.is_some() {
+            result.markdown.push_str(line);
+        } else {
+            result.markdown.push_str(&tag_fence(line, &path));
+        }
+        result.markdown.push_str(&source);
+        let closing = closing.unwrap_or("");
+        result.markdown.push_str(closing);
+        if existing.is_none() {
+            if !closing.ends_with('\n') {
+                result.markdown.push('\n');
+            }
+            result.markdown.push_str(&format!("\n![Diagram {}]({})\n", number, path));
+        }
+    }
+
+    Ok(result)
+}
+
+/// Whether a path is a plain relative file path below `img/`, safe as an archive entry name
+fn is_image_path(path: &str) -> bool {
+    let components: Vec<Component> = Path::new(path).components().collect();
+    !path.contains('\\')
+        && components.len() > 1
+        && components[0] == Component::Normal("img".as_ref())
+        && components.iter().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Fence marker and info string of an opening code fence
+fn opening_fence(line: &str) -> Option<(String, &str)> {
+    let first = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let count = line.len() - line.trim_start_matches(first).len();
+    (count >= 3).then(|| (first.to_string().repeat(count), &line[count..]))
+}
+
+fn is_closing_fence(line: &str, marker: &str) -> bool {
+    let trimmed = line.trim_start_matches(' ');
+    let first = marker.chars().next().unwrap();
+    line.len() - trimmed.len() < 4
+        && trimmed.starts_with(marker)
+        && trimmed.trim_end().trim_start_matches(first).is_empty()
+}
+
+/// Add the `rendered` attribute to an opening fence line
+fn tag_fence(line: &str, path: &str) -> String {
+    let (content, newline) = match line.strip_suffix('\n') {
+        Some(content) => (content.trim_end_matches('\r'), "\n"),
+        None => (line, ""),
+    };
+    let attribute = format!("{}=\"{}\"", RENDERED_ATTRIBUTE, path);
+
+    match content.rfind('}') {
+        Some(end) if content.contains('{') => {
+            format!("{} {}{}{}", content[..end].trim_end(), attribute, &content[end..], newline)
+        }
+        _ => format!("{} {{{}}}{}", content.trim_end(), attribute, newline),
+    }
+}
@@ -0,0 +1,97 @@
+use crate::MdzError;
+use pulldown_cmark::Options;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Markdown syntax extensions used to read `main.md`
+///
+/// Recorded as `dialect` in metadata.json so that every renderer reads the
+/// document the way its author wrote it. Missing fields take their default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkdownDialect {
+    /// `~~deleted~~`
+    pub strikethrough: bool,
+    /// GitHub-style pipe tables
+    pub tables: bool,
+    /// `[^note]` footnotes
+    pub footnotes: bool,
+    /// `- [x]` task lists
+    pub tasklists: bool,
+    /// Curly quotes, en/em dashes and ellipses
+    pub smart_punctuation: bool,
+    /// `# Heading {#id .class}`
+    pub heading_attributes: bool,
+    /// `$...$` and `$$...$$` LaTeX math
+    pub math: bool,
+    /// `> [!NOTE]` GitHub alerts rendered as admonitions
+    pub admonitions: bool,
+    /// Figure, table and listing captions with `@fig:label` cross-references
+    pub figures: bool,
+}
+
+impl Default for MarkdownDialect {
+    fn default() -> Self {
+        Self {
+            strikethrough: true,
+            tables: true,
+            footnotes: true,
+            tasklists: true,
+            smart_punctuation: false,
+            heading_attributes: false,
+            math: true,
+            admonitions: true,
+            figures: true,
+        }
+    }
+}
+
+impl MarkdownDialect {
+    /// Strict CommonMark without any extensions
+    pub fn commonmark() -> Self {
+        Self {
+            strikethrough: false,
+            tables: false,
+            footnotes: false,
+            tasklists: false,
+            smart_punctuation: false,
+            heading_attributes: false,
+            math: false,
+            admonitions: false,
+            figures: false,
+        }
+    }
+
+    /// Check if no extensions are enabled
+    pub fn is_commonmark(&self) -> bool {
+        *self == Self::commonmark()
+    }
+
+    /// pulldown-cmark options for this dialect
+    pub fn parser_options(&self) -> Options {
+        let mut options = Options::empty();
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        options.set(Options::ENABLE_TABLES, self.tables);
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(Options::ENABLE_TASKLISTS, self.tasklists);
+        options.set(Options::ENABLE_SMART_PUNCTUATION, self.smart_punctuation);
+        options.set(Options::ENABLE_HEADING_ATTRIBUTES, self.heading_attributes);
+        options
+    }
+}
+
+impl FromStr for MarkdownDialect {
+    type Err = MdzError;
+
+    /// Parse a preset name: `mdz` (the default) or `commonmark`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mdz" | "default" => Ok(Self::default()),
+            "commonmark" | "strict" => Ok(Self::commonmark()),
+            _ => Err(MdzError::Parse(format!(
+                "Unknown Markdown dialect '{}' (expected mdz or commonmark)",
+                s
+            ))),
+        }
+    }
+}
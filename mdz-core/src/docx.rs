@@ -0,0 +1,973 @@
+use crate::anchor::Slugger;
+use crate::pdf::{is_page_break, jpeg_info};
+use crate::template::push_escaped;
+use crate::{svg, MarkdownDialect, MdzDocument, Result};
+use pulldown_cmark::{Alignment, Event, HeadingLevel, Parser, Tag};
+use regex::Regex;
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use std::sync::OnceLock;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// A4 page with one-inch margins, in twentieths of a point
+const PAGE_WIDTH: u32 = 11906;
+const PAGE_HEIGHT: u32 = 16838;
+const PAGE_MARGIN: u32 = 1440;
+/// English Metric Units per twip and per pixel at 96 DPI
+const EMU_PER_TWIP: u64 = 635;
+const EMU_PER_PIXEL: u64 = 9525;
+/// Indentation per list or blockquote level, in twips
+const INDENT: u32 = 720;
+
+const STYLES_REL: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles";
+const NUMBERING_REL: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering";
+const FOOTNOTES_REL: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/footnotes";
+const SETTINGS_REL: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/settings";
+const HYPERLINK_REL: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink";
+const IMAGE_REL: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/image";
+
+/// Render a document to a Word (OOXML) file
+///
+/// Headings, quotes and code use named paragraph styles so the result can be
+/// restyled in Word. Raw HTML is skipped apart from page-break directives;
+/// math is shown as its LaTeX source.
+pub(crate) fn render(document: &MdzDocument, dialect: MarkdownDialect, title: Option<&str>) -> Result<Vec<u8>> {
+    let mut writer = Writer::new(document);
+    for event in Parser::new_ext(&document.content, dialect.parser_options()) {
+        writer.event(event);
+    }
+    writer.flush();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("[Content_Types].xml", options)?;
+    zip.write_all(writer.content_types().as_bytes())?;
+
+    zip.start_file("_rels/.rels", options)?;
+    zip.write_all(PACKAGE_RELS.as_bytes())?;
+
+    zip.start_file("docProps/core.xml", options)?;
+    zip.write_all(core_properties(document, title).as_bytes())?;
+
+    zip.start_file("word/document.xml", options)?;
+    zip.write_all(writer.document_xml().as_bytes())?;
+
+    zip.start_file("word/_rels/document.xml.rels", options)?;
+    zip.write_all(relationships(&writer.document_rels).as_bytes())?;
+
+    zip.start_file("word/footnotes.xml", options)?;
+    zip.write_all(writer.footnotes_xml().as_bytes())?;
+
+    zip.start_file("word/_rels/footnotes.xml.rels", options)?;
+    zip.write_all(relationships(&writer.footnote_rels).as_bytes())?;
+
+    zip.start_file("word/numbering.xml", options)?;
+    zip.write_all(writer.numbering_xml().as_bytes())?;
+
+    zip.start_file("word/styles.xml", options)?;
+    zip.write_all(STYLES_XML.as_bytes())?;
+
+    zip.start_file("word/settings.xml", options)?;
+    zip.write_all(SETTINGS_XML.as_bytes())?;
+
+    for media in &writer.media {
+        zip.start_file(format!("word/media/{}", media.name), options)?;
+        zip.write_all(&media.data)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Package relationship
+struct Relationship {
+    id: String,
+    kind: &'static str,
+    target: String,
+    external: bool,
+}
+
+/// Image file stored under `word/media`
+struct Media {
+    name: String,
+    data: Vec<u8>,
+    /// Display size in EMU
+    width: u64,
+    height: u64,
+}
+
+/// Heading being collected
+struct HeadingState {
+    level: HeadingLevel,
+    id: Option<String>,
+    text: String,
+}
+
+/// Table being written
+struct TableState {
+    alignments: Vec<Alignment>,
+    column: usize,
+    header: bool,
+}
+
+/// Image being collected; its alt text arrives as text events
+struct ImageState {
+    dest: String,
+    title: String,
+    alt: String,
+}
+
+/// Translates the Markdown event stream into WordprocessingML
+struct Writer<'d> {
+    document: &'d MdzDocument,
+    /// Body XML; footnote definitions are written to a buffer of their own
+    buffers: Vec<String>,
+    /// Runs of the paragraph being collected
+    runs: String,
+    bold: usize,
+    italic: usize,
+    strike: usize,
+    /// Depth of open hyperlinks
+    links: usize,
+    heading: Option<HeadingState>,
+    slugger: Slugger,
+    quotes: u32,
+    /// Numbering instance of each open list
+    lists: Vec<u32>,
+    /// Numbering for the first paragraph of the current list item
+    pending_number: Option<(usize, u32)>,
+    /// Abstract numbering and start value of each numbering instance
+    numbering: Vec<(u32, Option<u64>)>,
+    code: Option<String>,
+    table: Option<TableState>,
+    image: Option<ImageState>,
+    document_rels: Vec<Relationship>,
+    footnote_rels: Vec<Relationship>,
+    media: Vec<Media>,
+    media_indices: HashMap<String, Option<usize>>,
+    footnote_ids: HashMap<String, usize>,
+    footnote_bodies: HashMap<String, String>,
+    /// Whether the next paragraph opens a footnote and carries its reference mark
+    footnote_mark: bool,
+    next_id: u32,
+}
+
+impl<'d> Writer<'d> {
+    fn new(document: &'d MdzDocument) -> Self {
+        let fixed = |id: &str, kind, target: &str| Relationship {
+            id: id.to_string(),
+            kind,
+            target: target.to_string(),
+            external: false,
+        };
+        Self {
+            document,
+            buffers: vec![String::new()],
+            runs: String::new(),
+            bold: 0,
+            italic: 0,
+            strike: 0,
+            links: 0,
+            heading: None,
+            slugger: Slugger::new(),
+            quotes: 0,
+            lists: Vec::new(),
+            pending_number: None,
+            // Instance 1 is shared by all bullet lists
+            numbering: vec![(0, None)],
+            code: None,
+            table: None,
+            image: None,
+            document_rels: vec![
+                fixed("rId1", STYLES_REL, "styles.xml"),
+                fixed("rId2", NUMBERING_REL, "numbering.xml"),
+                fixed("rId3", FOOTNOTES_REL, "footnotes.xml"),
+                fixed("rId4", SETTINGS_REL, "settings.xml"),
+            ],
+            footnote_rels: Vec::new(),
+            media: Vec::new(),
+            media_indices: HashMap::new(),
+            footnote_ids: HashMap::new(),
+            footnote_bodies: HashMap::new(),
+            footnote_mark: false,
+            next_id: 1,
+        }
+    }
+
+    fn out(&mut self) -> &mut String {
+        self.buffers.last_mut().unwrap()
+    }
+
+    fn in_footnote(&self) -> bool {
+        self.buffers.len() > 1
+    }
+
+    /// Unique id for bookmarks and drawings
+    fn id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Relationship id from the part currently being written
+    fn relationship(&mut self, kind: &'static str, target: &str, external: bool) -> String {
+        let rels = if self.in_footnote() { &mut self.footnote_rels } else { &mut self.document_rels };
+        if let Some(rel) = rels.iter().find(|rel| rel.kind == kind && rel.target == target) {
+            return rel.id.clone();
+        }
+        let id = format!("rId{}", rels.len() + 1);
+        rels.push(Relationship { id: id.clone(), kind, target: target.to_string(), external });
+        id
+    }
+
+    fn footnote_id(&mut self, label: &str) -> usize {
+        let next = self.footnote_ids.len() + 1;
+        *self.footnote_ids.entry(label.to_string()).or_insert(next)
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if let Some(code) = &mut self.code {
+            code.push_str(text);
+            return;
+        }
+        if let Some(image) = &mut self.image {
+            image.alt.push_str(text);
+            return;
+        }
+        if let Some(heading) = &mut self.heading {
+            heading.text.push_str(text);
+        }
+        self.push_run(text, None);
+    }
+
+    /// Append a text run in the current character formatting
+    fn push_run(&mut self, text: &str, style: Option<&str>) {
+        if text.is_empty() {
+            return;
+        }
+        let mut properties = String::new();
+        if let Some(style) = style.or((self.links > 0).then_some("Hyperlink")) {
+            properties.push_str(&format!("<w:rStyle w:val=\"{}\"/>", style));
+        }
+        if self.bold > 0 || self.table.as_ref().is_some_and(|table| table.header) {
+            properties.push_str("<w:b/>");
+        }
+        if self.italic > 0 {
+            properties.push_str("<w:i/>");
+        }
+        if self.strike > 0 {
+            properties.push_str("<w:strike/>");
+        }
+
+        let mut run = String::from("<w:r>");
+        if !properties.is_empty() {
+            run.push_str(&format!("<w:rPr>{}</w:rPr>", properties));
+        }
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                run.push_str("<w:br/>");
+            }
+            if !line.is_empty() {
+                run.push_str(&format!("<w:t xml:space=\"preserve\">{}</w:t>", escaped(line)));
+            }
+        }
+        run.push_str("</w:r>");
+        self.runs.push_str(&run);
+    }
+
+    /// Indentation for paragraphs inside lists and blockquotes that carry no number
+    fn indentation(&self) -> u32 {
+        (self.lists.len() as u32 + self.quotes) * INDENT
+    }
+
+    /// Write the collected runs as a paragraph
+    fn flush(&mut self) {
+        if !self.runs.is_empty() {
+            self.paragraph();
+        }
+    }
+
+    /// Close a list item or footnote, keeping its number or mark even when it has no text
+    fn finish_block(&mut self) {
+        if !self.runs.is_empty() || self.pending_number.is_some() || self.footnote_mark {
+            self.paragraph();
+        }
+    }
+
+    fn paragraph(&mut self) {
+        let style = if self.in_footnote() {
+            Some("FootnoteText")
+        } else if self.quotes > 0 {
+            Some("Quote")
+        } else {
+            None
+        };
+        let mut properties = String::new();
+        if let Some(style) = style {
+            properties.push_str(&format!("<w:pStyle w:val=\"{}\"/>", style));
+        }
+        match self.pending_number.take() {
+            Some((level, instance)) => {
+                properties.push_str(&format!(
+                    "<w:numPr><w:ilvl w:val=\"{}\"/><w:numId w:val=\"{}\"/></w:numPr>",
+                    level, instance
+                ));
+                if self.quotes > 0 {
+                    properties.push_str(&format!(
+                        "<w:ind w:left=\"{}\" w:hanging=\"360\"/>",
+                        self.indentation()
+                    ));
+                }
+            }
+            None if self.indentation() > 0 => {
+                properties.push_str(&format!("<w:ind w:left=\"{}\"/>", self.indentation()));
+            }
+            None => {}
+        }
+
+        let mut runs = std::mem::take(&mut self.runs);
+        if std::mem::take(&mut self.footnote_mark) {
+            runs.insert_str(
+                0,
+                "<w:r><w:rPr><w:rStyle w:val=\"FootnoteReference\"/></w:rPr><w:footnoteRef/></w:r><w:r><w:t xml:space=\"preserve\"> </w:t></w:r>",
+            );
+        }
+        write_paragraph(self.out(), &properties, &runs);
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.push_text(&text),
+            Event::Code(code) => {
+                if let Some(heading) = &mut self.heading {
+                    heading.text.push_str(&code);
+                }
+                self.push_run(&code, Some("VerbatimChar"));
+            }
+            Event::SoftBreak => self.push_text(" "),
+            Event::HardBreak => self.push_text("\n"),
+            Event::Html(html) => {
+                if is_page_break(&html) {
+                    self.flush();
+                    self.out().push_str("<w:p><w:r><w:br w:type=\"page\"/></w:r></w:p>");
+                } else if html.trim_start().to_ascii_lowercase().starts_with("<br") {
+                    self.push_text("\n");
+                }
+            }
+            Event::Rule => {
+                self.flush();
+                self.out().push_str(
+                    "<w:p><w:pPr><w:pBdr><w:bottom w:val=\"single\" w:sz=\"6\" w:space=\"1\" w:color=\"CCCCCC\"/></w:pBdr></w:pPr></w:p>",
+                );
+            }
+            Event::FootnoteReference(label) => {
+                let id = self.footnote_id(&label);
+                self.runs.push_str(&format!(
+                    "<w:r><w:rPr><w:rStyle w:val=\"FootnoteReference\"/></w:rPr><w:footnoteReference w:id=\"{}\"/></w:r>",
+                    id
+                ));
+            }
+            Event::TaskListMarker(checked) => self.push_text(if checked { "\u{2612} " } else { "\u{2610} " }),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.flush(),
+            Tag::Heading(level, id, _) => {
+                self.flush();
+                self.heading = Some(HeadingState { level, id: id.map(str::to_string), text: String::new() });
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                self.quotes += 1;
+            }
+            Tag::CodeBlock(_) => {
+                self.flush();
+                self.code = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.flush();
+                let instance = match start {
+                    Some(start) => {
+                        // Each ordered list restarts its numbering
+                        self.numbering.push((1, Some(start)));
+                        self.numbering.len() as u32
+                    }
+                    None => 1,
+                };
+                self.lists.push(instance);
+            }
+            Tag::Item => {
+                self.flush();
+                if let Some(&instance) = self.lists.last() {
+                    self.pending_number = Some((self.lists.len() - 1, instance));
+                }
+            }
+            Tag::FootnoteDefinition(_) => {
+                self.flush();
+                self.buffers.push(String::new());
+                self.footnote_mark = true;
+            }
+            Tag::Table(alignments) => {
+                self.flush();
+                let columns = alignments.len().max(1) as u32;
+                let width = (PAGE_WIDTH - 2 * PAGE_MARGIN) / columns;
+                let mut xml = String::from(
+                    "<w:tbl><w:tblPr><w:tblStyle w:val=\"Table\"/><w:tblW w:w=\"5000\" w:type=\"pct\"/></w:tblPr><w:tblGrid>",
+                );
+                for _ in 0..columns {
+                    xml.push_str(&format!("<w:gridCol w:w=\"{}\"/>", width));
+                }
+                xml.push_str("</w:tblGrid>");
+                self.out().push_str(&xml);
+                self.table = Some(TableState { alignments, column: 0, header: false });
+            }
+            Tag::TableHead => {
+                self.out().push_str("<w:tr><w:trPr><w:tblHeader/></w:trPr>");
+                if let Some(table) = &mut self.table {
+                    table.header = true;
+                    table.column = 0;
+                }
+            }
+            Tag::TableRow => {
+                self.out().push_str("<w:tr>");
+                if let Some(table) = &mut self.table {
+                    table.column = 0;
+                }
+            }
+            Tag::TableCell => {
+                self.runs.clear();
+                self.out().push_str("<w:tc>");
+            }
+            Tag::Emphasis => self.italic += 1,
+            Tag::Strong => self.bold += 1,
+            Tag::Strikethrough => self.strike += 1,
+            Tag::Link(_, dest, _) => {
+                let link = match dest.strip_prefix('#') {
+                    Some(anchor) => format!("<w:hyperlink w:anchor=\"{}\">", bookmark_name(anchor)),
+                    None => format!("<w:hyperlink r:id=\"{}\">", self.relationship(HYPERLINK_REL, &dest, true)),
+                };
+                self.runs.push_str(&link);
+                self.links += 1;
+            }
+            Tag::Image(_, dest, title) => {
+                self.image = Some(ImageState { dest: dest.to_string(), title: title.to_string(), alt: String::new() });
+            }
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                if matches!(plain_text(&self.runs).trim(), "\\newpage" | "\\pagebreak") {
+                    self.runs.clear();
+                    self.out().push_str("<w:p><w:r><w:br w:type=\"page\"/></w:r></w:p>");
+                    return;
+                }
+                self.flush();
+            }
+            Tag::Heading(..) => {
+                let Some(heading) = self.heading.take() else {
+                    return;
+                };
+                let id = self.slugger.heading_id(heading.id.as_deref(), &heading.text);
+                let bookmark = self.id();
+                let properties = format!("<w:pStyle w:val=\"Heading{}\"/>", heading.level as usize);
+                let runs = format!(
+                    "<w:bookmarkStart w:id=\"{id}\" w:name=\"{name}\"/>{runs}<w:bookmarkEnd w:id=\"{id}\"/>",
+                    id = bookmark,
+                    name = bookmark_name(&id),
+                    runs = std::mem::take(&mut self.runs),
+                );
+                write_paragraph(self.out(), &properties, &runs);
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                self.quotes -= 1;
+            }
+            Tag::CodeBlock(_) => {
+                let code = self.code.take().unwrap_or_default();
+                let mut runs = String::new();
+                for (i, line) in code.strip_suffix('\n').unwrap_or(&code).split('\n').enumerate() {
+                    if i > 0 {
+                        runs.push_str("<w:r><w:br/></w:r>");
+                    }
+                    runs.push_str(&format!("<w:r><w:t xml:space=\"preserve\">{}</w:t></w:r>", escaped(line)));
+                }
+                let mut properties = String::from("<w:pStyle w:val=\"SourceCode\"/>");
+                if self.indentation() > 0 {
+                    properties.push_str(&format!("<w:ind w:left=\"{}\"/>", self.indentation()));
+                }
+                write_paragraph(self.out(), &properties, &runs);
+            }
+            Tag::List(_) => {
+                self.flush();
+                self.lists.pop();
+            }
+            Tag::Item => self.finish_block(),
+            Tag::FootnoteDefinition(label) => {
+                self.finish_block();
+                if let Some(body) = self.buffers.pop() {
+                    self.footnote_bodies.insert(label.to_string(), body);
+                }
+            }
+            Tag::TableHead => {
+                self.out().push_str("</w:tr>");
+                if let Some(table) = &mut self.table {
+                    table.header = false;
+                }
+            }
+            Tag::TableRow => self.out().push_str("</w:tr>"),
+            Tag::TableCell => {
+                // Every cell needs at least one paragraph
+                self.pending_number = None;
+                let mut properties = String::new();
+                if let Some(table) = &self.table {
+                    match table.alignments.get(table.column) {
+                        Some(Alignment::Center) => properties.push_str("<w:jc w:val=\"center\"/>"),
+                        Some(Alignment::Right) => properties.push_str("<w:jc w:val=\"right\"/>"),
+                        _ => {}
+                    }
+                }
+                let runs = std::mem::take(&mut self.runs);
+                write_paragraph(self.out(), &properties, &runs);
+                self.out().push_str("</w:tc>");
+                if let Some(table) = &mut self.table {
+                    table.column += 1;
+                }
+            }
+            Tag::Table(_) => {
+                self.out().push_str("</w:tbl>");
+                self.table = None;
+                // Keeps adjacent tables from merging
+                self.out().push_str("<w:p/>");
+            }
+            Tag::Emphasis => self.italic -= 1,
+            Tag::Strong => self.bold -= 1,
+            Tag::Strikethrough => self.strike -= 1,
+            Tag::Link(..) => {
+                self.runs.push_str("</w:hyperlink>");
+                self.links -= 1;
+            }
+            Tag::Image(..) => {
+                if let Some(image) = self.image.take() {
+                    self.image_run(image);
+                }
+            }
+        }
+    }
+
+    /// Append an inline picture, or its alt text when the image cannot be embedded
+    fn image_run(&mut self, image: ImageState) {
+        let Some(index) = self.load_image(&image.dest) else {
+            let text = if image.alt.is_empty() { image.dest.clone() } else { image.alt.clone() };
+            self.italic += 1;
+            self.push_run(&format!("[{}]", text), None);
+            self.italic -= 1;
+            return;
+        };
+
+        let target = format!("media/{}", self.media[index].name);
+        let rel = self.relationship(IMAGE_REL, &target, false);
+        let id = self.id();
+        let media = &self.media[index];
+        // Keep pictures within the text area
+        let max_width = (PAGE_WIDTH - 2 * PAGE_MARGIN - self.indentation()) as u64 * EMU_PER_TWIP;
+        let max_height = (PAGE_HEIGHT - 2 * PAGE_MARGIN) as u64 * EMU_PER_TWIP;
+        let scale = (max_width as f64 / media.width as f64).min(max_height as f64 / media.height as f64).min(1.0);
+        let width = (media.width as f64 * scale) as u64;
+        let height = (media.height as f64 * scale) as u64;
+
+        self.runs.push_str(&format!(
+            "<w:r><w:drawing><wp:inline distT=\"0\" distB=\"0\" distL=\"0\" distR=\"0\">\
+             <wp:extent cx=\"{width}\" cy=\"{height}\"/>\
+             <wp:effectExtent l=\"0\" t=\"0\" r=\"0\" b=\"0\"/>\
+             <wp:docPr id=\"{id}\" name=\"Picture {id}\" descr=\"{alt}\" title=\"{title}\"/>\
+             <wp:cNvGraphicFramePr><a:graphicFrameLocks noChangeAspect=\"1\"/></wp:cNvGraphicFramePr>\
+             <a:graphic><a:graphicData uri=\"http://schemas.openxmlformats.org/drawingml/2006/picture\">\
+             <pic:pic><pic:nvPicPr><pic:cNvPr id=\"{id}\" name=\"{name}\"/><pic:cNvPicPr/></pic:nvPicPr>\
+             <pic:blipFill><a:blip r:embed=\"{rel}\"/><a:stretch><a:fillRect/></a:stretch></pic:blipFill>\
+             <pic:spPr><a:xfrm><a:off x=\"0\" y=\"0\"/><a:ext cx=\"{width}\" cy=\"{height}\"/></a:xfrm>\
+             <a:prstGeom prst=\"rect\"><a:avLst/></a:prstGeom></pic:spPr></pic:pic>\
+             </a:graphicData></a:graphic></wp:inline></w:drawing></w:r>",
+            width = width,
+            height = height,
+            id = id,
+            alt = escaped(&image.alt),
+            title = escaped(&image.title),
+            name = escaped(&media.name),
+            rel = rel,
+        ));
+    }
+
+    fn load_image(&mut self, dest: &str) -> Option<usize> {
+        let path = dest.strip_prefix("./").unwrap_or(dest);
+        if let Some(index) = self.media_indices.get(path) {
+            return *index;
+        }
+        let index = self.document.images.get(path).and_then(|data| {
+            let (extension, data) = if path.to_ascii_lowercase().ends_with(".svg") {
+                ("png", svg::rasterize(data).ok()?)
+            } else {
+                let extension = path.rsplit('.').next()?.to_ascii_lowercase();
+                (if extension == "jpg" { "jpeg" } else { image_extension(&extension)? }, data.clone())
+            };
+            let (width, height) = image_size(&data)?;
+            self.media.push(Media {
+                name: format!("image{}.{}", self.media.len() + 1, extension),
+                data,
+                width,
+                height,
+            });
+            Some(self.media.len() - 1)
+        });
+        self.media_indices.insert(path.to_string(), index);
+        index
+    }
+
+    fn content_types(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+             <Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+             <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+             <Default Extension=\"xml\" ContentType=\"application/xml\"/>",
+        );
+        let mut extensions: Vec<&str> = self.media.iter().filter_map(|media| media.name.rsplit('.').next()).collect();
+        extensions.sort_unstable();
+        extensions.dedup();
+        for extension in extensions {
+            xml.push_str(&format!("<Default Extension=\"{0}\" ContentType=\"image/{0}\"/>", extension));
+        }
+        for (part, kind) in [
+            ("/word/document.xml", "wordprocessingml.document.main"),
+            ("/word/styles.xml", "wordprocessingml.styles"),
+            ("/word/numbering.xml", "wordprocessingml.numbering"),
+            ("/word/footnotes.xml", "wordprocessingml.footnotes"),
+            ("/word/settings.xml", "wordprocessingml.settings"),
+        ] {
+            xml.push_str(&format!(
+                "<Override PartName=\"{}\" ContentType=\"application/vnd.openxmlformats-officedocument.{}+xml\"/>",
+                part, kind
+            ));
+        }
+        xml.push_str(
+            "<Override PartName=\"/docProps/core.xml\" ContentType=\"application/vnd.openxmlformats-package.core-properties+xml\"/>\
+             </Types>",
+        );
+        xml
+    }
+
+    fn document_xml(&self) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+             <w:document {namespaces}><w:body>{body}\
+             <w:sectPr><w:pgSz w:w=\"{width}\" w:h=\"{height}\"/>\
+             <w:pgMar w:top=\"{margin}\" w:right=\"{margin}\" w:bottom=\"{margin}\" w:left=\"{margin}\" \
+             w:header=\"708\" w:footer=\"708\" w:gutter=\"0\"/></w:sectPr>\
+             </w:body></w:document>",
+            namespaces = NAMESPACES,
+            body = self.buffers[0],
+            width = PAGE_WIDTH,
+            height = PAGE_HEIGHT,
+            margin = PAGE_MARGIN,
+        )
+    }
+
+    fn footnotes_xml(&self) -> String {
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+             <w:footnotes {}>\
+             <w:footnote w:type=\"separator\" w:id=\"-1\"><w:p><w:r><w:separator/></w:r></w:p></w:footnote>\
+             <w:footnote w:type=\"continuationSeparator\" w:id=\"0\"><w:p><w:r><w:continuationSeparator/></w:r></w:p></w:footnote>",
+            NAMESPACES
+        );
+        let mut footnotes: Vec<(&String, &usize)> = self.footnote_ids.iter().collect();
+        footnotes.sort_by_key(|(_, id)| **id);
+        for (label, id) in footnotes {
+            let body = self.footnote_bodies.get(label).map(String::as_str).unwrap_or(
+                "<w:p><w:pPr><w:pStyle w:val=\"FootnoteText\"/></w:pPr>\
+                 <w:r><w:rPr><w:rStyle w:val=\"FootnoteReference\"/></w:rPr><w:footnoteRef/></w:r></w:p>",
+            );
+            xml.push_str(&format!("<w:footnote w:id=\"{}\">{}</w:footnote>", id, body));
+        }
+        xml.push_str("</w:footnotes>");
+        xml
+    }
+
+    fn numbering_xml(&self) -> String {
+        let mut xml = format!("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:numbering {}>", NAMESPACES);
+        for (abstract_id, format) in [(0, "bullet"), (1, "decimal")] {
+            xml.push_str(&format!(
+                "<w:abstractNum w:abstractNumId=\"{}\"><w:multiLevelType w:val=\"hybridMultilevel\"/>",
+                abstract_id
+            ));
+            for level in 0..9u32 {
+                let text = match format {
+                    "bullet" => ["\u{2022}", "\u{25E6}", "\u{25AA}"][level as usize % 3].to_string(),
+                    _ => format!("%{}.", level + 1),
+                };
+                xml.push_str(&format!(
+                    "<w:lvl w:ilvl=\"{level}\"><w:start w:val=\"1\"/><w:numFmt w:val=\"{format}\"/>\
+                     <w:lvlText w:val=\"{text}\"/><w:lvlJc w:val=\"left\"/>\
+                     <w:pPr><w:ind w:left=\"{left}\" w:hanging=\"360\"/></w:pPr></w:lvl>",
+                    level = level,
+                    format = format,
+                    text = text,
+                    left = (level + 1) * INDENT,
+                ));
+            }
+            xml.push_str("</w:abstractNum>");
+        }
+        for (index, (abstract_id, start)) in self.numbering.iter().enumerate() {
+            xml.push_str(&format!("<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"{}\"/>", index + 1, abstract_id));
+            if let Some(start) = start {
+                for level in 0..9 {
+                    xml.push_str(&format!(
+                        "<w:lvlOverride w:ilvl=\"{}\"><w:startOverride w:val=\"{}\"/></w:lvlOverride>",
+                        level, start
+                    ));
+                }
+            }
+            xml.push_str("</w:num>");
+        }
+        xml.push_str("</w:numbering>");
+        xml
+    }
+}
+
+fn write_paragraph(output: &mut String, properties: &str, runs: &str) {
+    output.push_str("<w:p>");
+    if !properties.is_empty() {
+        output.push_str(&format!("<w:pPr>{}</w:pPr>", properties));
+    }
+    output.push_str(runs);
+    output.push_str("</w:p>");
+}
+
+/// Text content of collected runs
+fn plain_text(runs: &str) -> String {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| Regex::new(r"<w:t[^>]*>([^<]*)</w:t>").unwrap());
+    pattern.captures_iter(runs).map(|cap| cap[1].to_string()).collect()
+}
+
+/// XML-escape text, dropping characters XML cannot represent
+fn escaped(text: &str) -> String {
+    let text: String = text.chars().filter(|&c| c >= ' ' || matches!(c, '\t' | '\n' | '\r')).collect();
+    let mut output = String::new();
+    push_escaped(&mut output, &text);
+    output
+}
+
+/// Word bookmark name for an anchor id: letters, digits and underscores, at most 40 characters
+fn bookmark_name(id: &str) -> String {
+    let name: String = id.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+    format!("h_{}", name).chars().take(40).collect()
+}
+
+fn image_extension(extension: &str) -> Option<&'static str> {
+    match extension {
+        "png" => Some("png"),
+        "jpeg" => Some("jpeg"),
+        "gif" => Some("gif"),
+        "bmp" => Some("bmp"),
+        _ => None,
+    }
+}
+
+/// Natural size of a PNG, JPEG, GIF or BMP image in EMU
+///
+/// PNG images honour their `pHYs` resolution; everything else is taken to be 96 DPI.
+fn image_size(data: &[u8]) -> Option<(u64, u64)> {
+    let (width, height) = pixel_size(data)?;
+    if let Ok(reader) = png::Decoder::new(Cursor::new(data)).read_info() {
+        if let Some(png::PixelDimensions { xppu, yppu, unit: png::Unit::Meter }) = reader.info().pixel_dims {
+            if xppu > 0 && yppu > 0 {
+                // 914400 EMU per inch, 39.37 inches per metre
+                let emu = |size: u32, ppu: u32| (size as f64 / ppu as f64 * 36_000_000.0) as u64;
+                return Some((emu(width, xppu), emu(height, yppu)));
+            }
+        }
+    }
+    Some((width as u64 * EMU_PER_PIXEL, height as u64 * EMU_PER_PIXEL))
+}
+
+/// Size of a PNG, JPEG, GIF or BMP image in pixels
+pub(crate) fn pixel_size(data: &[u8]) -> Option<(u32, u32)> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        let (width, height, _) = jpeg_info(data)?;
+        return Some((width, height));
+    }
+    if data.starts_with(b"GIF8") && data.len() >= 10 {
+        return Some((
+            u16::from_le_bytes([data[6], data[7]]) as u32,
+            u16::from_le_bytes([data[8], data[9]]) as u32,
+        ));
+    }
+    if data.starts_with(b"BM") && data.len() >= 26 {
+        let width = i32::from_le_bytes([data[18], data[19], data[20], data[21]]);
+        let height = i32::from_le_bytes([data[22], data[23], data[24], data[25]]);
+        return Some((width.unsigned_abs(), height.unsigned_abs()));
+    }
+
+    let reader = png::Decoder::new(Cursor::new(data)).read_info().ok()?;
+    Some((reader.info().width, reader.info().height))
+}
+
+fn relationships(rels: &[Relationship]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    );
+    for rel in rels {
+        xml.push_str(&format!(
+            "<Relationship Id=\"{}\" Type=\"{}\" Target=\"{}\"{}/>",
+            rel.id,
+            rel.kind,
+            escaped(&rel.target),
+            if rel.external { " TargetMode=\"External\"" } else { "" }
+        ));
+    }
+    xml.push_str("</Relationships>");
+    xml
+}
+
+fn core_properties(document: &MdzDocument, title: Option<&str>) -> String {
+    let metadata = &document.metadata;
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <cp:coreProperties xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:dcterms=\"http://purl.org/dc/terms/\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">",
+    );
+    if let Some(title) = title {
+        xml.push_str(&format!("<dc:title>{}</dc:title>", escaped(title)));
+    }
+    if let Some(author) = &metadata.author {
+        xml.push_str(&format!("<dc:creator>{}</dc:creator>", escaped(author)));
+    }
+    if let Some(description) = &metadata.description {
+        xml.push_str(&format!("<dc:description>{}</dc:description>", escaped(description)));
+    }
+    if let Some(language) = &metadata.language {
+        xml.push_str(&format!("<dc:language>{}</dc:language>", escaped(language)));
+    }
+    for (element, date) in [("created", metadata.created_at), ("modified", metadata.modified_at)] {
+        if let Some(date) = date {
+            xml.push_str(&format!(
+                "<dcterms:{0} xsi:type=\"dcterms:W3CDTF\">{1}</dcterms:{0}>",
+                element,
+                date.format("%Y-%m-%dT%H:%M:%SZ")
+            ));
+        }
+    }
+    xml.push_str("</cp:coreProperties>");
+    xml
+}
+
+const NAMESPACES: &str = "xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\" \
+    xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\" \
+    xmlns:wp=\"http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing\" \
+    xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\" \
+    xmlns:pic=\"http://schemas.openxmlformats.org/drawingml/2006/picture\"";
+
+const PACKAGE_RELS: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"word/document.xml\"/>\
+<Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties\" Target=\"docProps/core.xml\"/>\
+</Relationships>";
+
+const SETTINGS_XML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+<w:settings xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">\
+<w:footnotePr><w:footnote w:id=\"-1\"/><w:footnote w:id=\"0\"/></w:footnotePr>\
+</w:settings>";
+
+const STYLES_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:docDefaults>
+<w:rPrDefault><w:rPr><w:rFonts w:ascii="Calibri" w:hAnsi="Calibri" w:eastAsia="Calibri" w:cs="Calibri"/><w:color w:val="333333"/><w:sz w:val="22"/><w:szCs w:val="22"/></w:rPr></w:rPrDefault>
+<w:pPrDefault><w:pPr><w:spacing w:after="160" w:line="276" w:lineRule="auto"/></w:pPr></w:pPrDefault>
+</w:docDefaults>
+<w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/><w:qFormat/></w:style>
+<w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="360" w:after="120"/><w:outlineLvl w:val="0"/></w:pPr><w:rPr><w:b/><w:color w:val="1A1A1A"/><w:sz w:val="40"/><w:szCs w:val="40"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading2"><w:name w:val="heading 2"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="320" w:after="120"/><w:outlineLvl w:val="1"/></w:pPr><w:rPr><w:b/><w:color w:val="1A1A1A"/><w:sz w:val="32"/><w:szCs w:val="32"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading3"><w:name w:val="heading 3"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="280" w:after="80"/><w:outlineLvl w:val="2"/></w:pPr><w:rPr><w:b/><w:color w:val="1A1A1A"/><w:sz w:val="28"/><w:szCs w:val="28"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading4"><w:name w:val="heading 4"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="240" w:after="80"/><w:outlineLvl w:val="3"/></w:pPr><w:rPr><w:b/><w:color w:val="1A1A1A"/><w:sz w:val="24"/><w:szCs w:val="24"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading5"><w:name w:val="heading 5"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="240" w:after="80"/><w:outlineLvl w:val="4"/></w:pPr><w:rPr><w:b/><w:color w:val="1A1A1A"/><w:sz w:val="22"/><w:szCs w:val="22"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading6"><w:name w:val="heading 6"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="240" w:after="80"/><w:outlineLvl w:val="5"/></w:pPr><w:rPr><w:b/><w:color w:val="595959"/><w:sz w:val="20"/><w:szCs w:val="20"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Quote"><w:name w:val="Quote"/><w:basedOn w:val="Normal"/><w:qFormat/><w:pPr><w:pBdr><w:left w:val="single" w:sz="18" w:space="8" w:color="DDDDDD"/></w:pBdr></w:pPr><w:rPr><w:color w:val="666666"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="SourceCode"><w:name w:val="Source Code"/><w:basedOn w:val="Normal"/><w:pPr><w:shd w:val="clear" w:color="auto" w:fill="F6F8FA"/><w:spacing w:before="80" w:after="160" w:line="240" w:lineRule="auto"/></w:pPr><w:rPr><w:rFonts w:ascii="Consolas" w:hAnsi="Consolas" w:cs="Consolas"/><w:sz w:val="19"/><w:szCs w:val="19"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="FootnoteText"><w:name w:val="footnote text"/><w:basedOn w:val="Normal"/><w:pPr><w:spacing w:after="0" w:line="240" w:lineRule="auto"/></w:pPr><w:rPr><w:sz w:val="18"/><w:szCs w:val="18"/></w:rPr></w:style>
+<w:style w:type="character" w:default="1" w:styleId="DefaultParagraphFont"><w:name w:val="Default Paragraph Font"/></w:style>
+<w:style w:type="character" w:styleId="VerbatimChar"><w:name w:val="Verbatim Char"/><w:rPr><w:rFonts w:ascii="Consolas" w:hAnsi="Consolas" w:cs="Consolas"/><w:sz w:val="20"/><w:shd w:val="clear" w:color="auto" w:fill="F3F4F4"/></w:rPr></w:style>
+<w:style w:type="character" w:styleId="Hyperlink"><w:name w:val="Hyperlink"/><w:rPr><w:color w:val="2A6BB8"/><w:u w:val="single"/></w:rPr></w:style>
+<w:style w:type="character" w:styleId="FootnoteReference"><w:name w:val="footnote reference"/><w:rPr><w:vertAlign w:val="superscript"/></w:rPr></w:style>
+<w:style w:type="table" w:default="1" w:styleId="TableNormal"><w:name w:val="Normal Table"/><w:tblPr><w:tblInd w:w="0" w:type="dxa"/><w:tblCellMar><w:top w:w="0" w:type="dxa"/><w:left w:w="108" w:type="dxa"/><w:bottom w:w="0" w:type="dxa"/><w:right w:w="108" w:type="dxa"/></w:tblCellMar></w:tblPr></w:style>
+<w:style w:type="table" w:styleId="Table"><w:name w:val="Table"/><w:basedOn w:val="TableNormal"/><w:pPr><w:spacing w:after="0"/></w:pPr><w:tblPr><w:tblBorders><w:top w:val="single" w:sz="4" w:space="0" w:color="DFE2E5"/><w:left w:val="single" w:sz="4" w:space="0" w:color="DFE2E5"/><w:bottom w:val="single" w:sz="4" w:space="0" w:color="DFE2E5"/><w:right w:val="single" w:sz="4" w:space="0" w:color="DFE2E5"/><w:insideH w:val="single" w:sz="4" w:space="0" w:color="DFE2E5"/><w:insideV w:val="single" w:sz="4" w:space="0" w:color="DFE2E5"/></w:tblBorders><w:tblCellMar><w:top w:w="60" w:type="dxa"/><w:left w:w="115" w:type="dxa"/><w:bottom w:w="60" w:type="dxa"/><w:right w:w="115" w:type="dxa"/></w:tblCellMar></w:tblPr></w:style>
+</w:styles>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn read_part(docx: &[u8], name: &str) -> String {
+        let mut archive = zip::ZipArchive::new(Cursor::new(docx)).unwrap();
+        let mut part = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut part).unwrap();
+        part
+    }
+
+    #[test]
+    fn maps_markdown_to_styles_links_and_footnotes() {
+        let document = MdzDocument::new(
+            "# Intro & Setup\n\nSee [site](https://example.com/?a=1&b=2) and [back](#intro--setup).[^n]\n\n\
+             - one\n\n| a | b |\n|---|--:|\n| 1 | 2 |\n\n[^n]: A note.\n"
+                .to_string(),
+        );
+        let docx = render(&document, MarkdownDialect::default(), Some("Title")).unwrap();
+
+        let body = read_part(&docx, "word/document.xml");
+        assert!(body.contains("<w:pStyle w:val=\"Heading1\"/></w:pPr><w:bookmarkStart w:id=\"1\" w:name=\"h_intro__setup\"/>"));
+        assert!(body.contains("<w:t xml:space=\"preserve\">Intro &amp; Setup</w:t>"));
+        assert!(body.contains("<w:hyperlink w:anchor=\"h_intro__setup\">"));
+        assert!(body.contains("<w:footnoteReference w:id=\"1\"/>"));
+        assert!(body.contains("<w:numId w:val=\"1\"/>"));
+        assert!(body.contains("<w:tblHeader/>") && body.contains("<w:jc w:val=\"right\"/>"));
+
+        let rels = read_part(&docx, "word/_rels/document.xml.rels");
+        assert!(rels.contains(&format!(
+            "Type=\"{}\" Target=\"https://example.com/?a=1&amp;b=2\" TargetMode=\"External\"",
+            HYPERLINK_REL
+        )));
+        assert!(read_part(&docx, "word/footnotes.xml").contains("A note."));
+        assert!(read_part(&docx, "docProps/core.xml").contains("<dc:title>Title</dc:title>"));
+    }
+
+    #[test]
+    fn reads_image_sizes_from_headers() {
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend([0x40, 0x01, 0xF0, 0x00]);
+        assert_eq!(pixel_size(&gif), Some((320, 240)));
+        assert_eq!(image_size(&gif), Some((320 * EMU_PER_PIXEL, 240 * EMU_PER_PIXEL)));
+
+        let mut bmp = vec![0; 26];
+        bmp[..2].copy_from_slice(b"BM");
+        bmp[18..22].copy_from_slice(&16i32.to_le_bytes());
+        bmp[22..26].copy_from_slice(&(-8i32).to_le_bytes());
+        assert_eq!(pixel_size(&bmp), Some((16, 8)));
+        assert_eq!(pixel_size(b"not an image"), None);
+    }
+
+    #[test]
+    fn escapes_text_and_limits_bookmark_names() {
+        assert_eq!(escaped("a\u{1}<b>\t"), "a&lt;b&gt;\t");
+        assert_eq!(bookmark_name("café-menu"), "h_café_menu");
+        assert_eq!(bookmark_name(&"x".repeat(60)).chars().count(), 40);
+        assert_eq!(plain_text("<w:r><w:t xml:space=\"preserve\">a </w:t></w:r><w:r><w:t>b</w:t></w:r>"), "a b");
+    }
+}
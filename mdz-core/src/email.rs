@@ -0,0 +1,557 @@
+use crate::css::{self, declarations, matching_brace, strip_comments, CssTarget};
+use crate::markup::{tokenize, Token, VOID_ELEMENTS};
+use crate::mime::{encode_header, Body, Entity};
+use crate::renderer::ImageLinks;
+use crate::template::push_escaped;
+use crate::{MdzDocument, MdzRenderer, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Content-Security-Policy for hardened messages, which load images from their own parts
+const HARDENED_EMAIL_CSP: &str = "default-src 'none'; style-src 'unsafe-inline'; img-src cid:";
+
+/// Email built by [`MdzRenderer::render_email`]
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub subject: String,
+    /// HTML body with inline `style` attributes and `cid:` image references
+    pub html: String,
+    /// Plain-text alternative
+    pub text: String,
+    /// Images referenced from the HTML, to attach inline
+    pub attachments: Vec<EmailAttachment>,
+}
+
+/// Inline attachment referenced as `cid:<content_id>`
+#[derive(Debug, Clone)]
+pub struct EmailAttachment {
+    pub content_id: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Addresses written to the message headers
+#[derive(Debug, Clone, Default)]
+pub struct EmailAddresses {
+    pub from: String,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+}
+
+impl EmailMessage {
+    /// Serialize as an RFC 5322 message with the HTML and text bodies as alternatives
+    pub fn to_eml(&self, addresses: &EmailAddresses) -> Vec<u8> {
+        let seed = hex_digest(self.html.as_bytes(), 6);
+
+        let mut related = vec![Entity::new(Body::Text("text/html", self.html.clone()))];
+        for attachment in &self.attachments {
+            related.push(
+                Entity::new(Body::Binary(attachment.mime_type.clone(), attachment.data.clone()))
+                    .header("Content-ID", format!("<{}>", attachment.content_id))
+                    .header(
+                        "Content-Disposition",
+                        format!("inline; filename=\"{}\"", attachment.filename.replace('"', "")),
+                    ),
+            );
+        }
+        let html = if related.len() > 1 {
+            Entity::new(Body::Multipart("related", "type=\"text/html\"".to_string(), related))
+        } else {
+            related.remove(0)
+        };
+        let text = Entity::new(Body::Text("text/plain", self.text.clone()));
+
+        let (_, from) = parse_address(&addresses.from);
+        let domain = from
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .filter(|domain| !domain.is_empty() && domain.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.')))
+            .unwrap_or("localhost");
+        let mut message = Entity::new(Body::Multipart("alternative", String::new(), vec![text, html]))
+            .header("From", encode_address(&addresses.from));
+        if !addresses.to.is_empty() {
+            message = message.header("To", encode_addresses(&addresses.to));
+        }
+        if !addresses.cc.is_empty() {
+            message = message.header("Cc", encode_addresses(&addresses.cc));
+        }
+        message
+            .header("Subject", encode_header(&self.subject))
+            .header("Date", chrono::Utc::now().to_rfc2822())
+            .header("Message-ID", format!("<{}.{}@{}>", seed, chrono::Utc::now().timestamp(), domain))
+            .header("MIME-Version", "1.0")
+            .to_bytes(&seed)
+    }
+}
+
+/// Render a document for email clients
+///
+/// Rules the inliner understands (type, class, id and attribute selectors with
+/// descendant and child combinators) become `style` attributes; everything
+/// else, such as `@media` blocks and pseudo-classes, stays in a `<style>`
+/// element for the clients that honour it. `body` rules apply to a wrapper
+/// `<div>`, as many clients drop the attributes of `<body>`.
+pub(crate) fn render(renderer: &MdzRenderer, document: &MdzDocument) -> Result<EmailMessage> {
+    let options = renderer.options();
+    let seed = hex_digest(document.content.as_bytes(), 6);
+
+    let mut paths: Vec<&String> = document.images.keys().collect();
+    paths.sort();
+    let names: HashMap<String, String> = paths
+        .iter()
+        .enumerate()
+        .map(|(index, path)| ((*path).clone(), format!("cid:image{}.{}@mdz", index + 1, seed)))
+        .collect();
+
+    let body = renderer.render_body(document, ImageLinks::Rename(&names), 0)?;
+    let content = body.sections.concat();
+
+    let mut stylesheet = Stylesheet::default();
+    if options.include_css {
+        let mut css = renderer.get_css_content(document);
+        for extra in &body.extra_css {
+            css.push('\n');
+            css.push_str(extra);
+        }
+        let css = css::rewrite_urls(&css, |reference| match reference.target("css/style.css") {
+            CssTarget::Archive(path) => names.get(&path).cloned(),
+            _ => None,
+        });
+        stylesheet = Stylesheet::parse(&css);
+    }
+
+    let mut shell = renderer.html_shell(document, HARDENED_EMAIL_CSP);
+    if !stylesheet.remaining.trim().is_empty() {
+        shell.style(&stylesheet.remaining);
+    }
+    let html = shell.render(&stylesheet.inline(&content));
+
+    let mut attachments = Vec::new();
+    for path in paths {
+        let content_id = &names[path.as_str()][4..];
+        if !html.contains(content_id) {
+            continue;
+        }
+        let (mime_type, data) = renderer.prepare_image(path, &document.images[path.as_str()])?;
+        let mut filename = path.rsplit('/').next().unwrap_or(path).to_string();
+        if mime_type == "image/png" && filename.to_ascii_lowercase().ends_with(".svg") {
+            filename.replace_range(filename.len() - 3.., "png");
+        }
+        attachments.push(EmailAttachment {
+            content_id: content_id.to_string(),
+            filename,
+            mime_type: mime_type.to_string(),
+            data: data.into_owned(),
+        });
+    }
+
+    Ok(EmailMessage {
+        subject: shell.title.clone(),
+        html,
+        text: renderer.render_text(document)?,
+        attachments,
+    })
+}
+
+fn hex_digest(data: &[u8], bytes: usize) -> String {
+    Sha256::digest(data)[..bytes].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn encode_addresses(addresses: &[String]) -> String {
+    addresses.iter().map(|address| encode_address(address)).collect::<Vec<_>>().join(",\r\n ")
+}
+
+/// Encode the display name of `Name <address>`, leaving the address itself readable
+///
+/// Control characters are dropped, so an address cannot break the header
+/// into several lines and add headers of its own.
+fn encode_address(address: &str) -> String {
+    let address: String = address.chars().filter(|c| !c.is_control()).collect();
+    match parse_address(&address) {
+        (Some(name), addr_spec) => format!("{} <{}>", encode_header(name), addr_spec),
+        (None, addr_spec) => addr_spec.to_string(),
+    }
+}
+
+/// Split `Name <local@domain>` into the display name, if any, and the addr-spec
+fn parse_address(address: &str) -> (Option<&str>, &str) {
+    let address = address.trim();
+    let Some((name, rest)) = address.rsplit_once('<') else {
+        return (None, address);
+    };
+    let addr_spec = rest.split('>').next().unwrap_or(rest).trim();
+    let name = name.trim().trim_matches('"').trim();
+    ((!name.is_empty()).then_some(name), addr_spec)
+}
+
+/// Element as seen by selectors
+struct Element {
+    name: String,
+    id: Option<String>,
+    classes: Vec<String>,
+    attributes: Vec<(String, String)>,
+}
+
+impl Element {
+    fn new(name: &str) -> Self {
+        Self { name: name.to_string(), id: None, classes: Vec::new(), attributes: Vec::new() }
+    }
+}
+
+/// Type, id, class and attribute conditions on one element
+#[derive(Debug, Default)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    /// Attribute name with the exact value required, if any
+    attributes: Vec<(String, Option<String>)>,
+}
+
+impl Compound {
+    fn parse(text: &str) -> Option<Self> {
+        let mut compound = Self::default();
+        let mut rest = text;
+        let name_len = |s: &str| s.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_')).unwrap_or(s.len());
+
+        let tag_len = name_len(rest);
+        if tag_len > 0 {
+            compound.tag = Some(rest[..tag_len].to_ascii_lowercase());
+            rest = &rest[tag_len..];
+        } else if let Some(after) = rest.strip_prefix('*') {
+            rest = after;
+        }
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let len = name_len(after);
+                compound.classes.push(after[..len].to_string());
+                rest = &after[len..];
+            } else if let Some(after) = rest.strip_prefix('#') {
+                let len = name_len(after);
+                compound.id = Some(after[..len].to_string());
+                rest = &after[len..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let close = after.find(']')?;
+                let attribute = &after[..close];
+                let condition = match attribute.split_once('=') {
+                    // Operators such as `^=` or `~=` are not supported
+                    Some((name, _)) if name.ends_with(|c: char| "~|^$*".contains(c)) => return None,
+                    Some((name, value)) => {
+                        (name.trim().to_ascii_lowercase(), Some(value.trim().trim_matches(['"', '\'']).to_string()))
+                    }
+                    None => (attribute.trim().to_ascii_lowercase(), None),
+                };
+                compound.attributes.push(condition);
+                rest = &after[close + 1..];
+            } else {
+                // Pseudo-classes, pseudo-elements and anything else unknown
+                return None;
+            }
+        }
+        Some(compound)
+    }
+
+    fn matches(&self, element: &Element) -> bool {
+        self.tag.as_ref().is_none_or(|tag| *tag == element.name)
+            && self.id.as_ref().is_none_or(|id| element.id.as_ref() == Some(id))
+            && self.classes.iter().all(|class| element.classes.contains(class))
+            && self.attributes.iter().all(|(name, value)| {
+                element
+                    .attributes
+                    .iter()
+                    .any(|(attribute, actual)| attribute == name && value.as_ref().is_none_or(|v| v == actual))
+            })
+    }
+}
+
+/// Selector specificity as (ids, classes and attributes, types)
+type Specificity = (usize, usize, usize);
+
+/// Selector made of compounds joined by descendant or child combinators
+#[derive(Debug)]
+struct Selector {
+    compounds: Vec<Compound>,
+    /// Whether each compound after the first must be a direct child of the previous one
+    child: Vec<bool>,
+    specificity: Specificity,
+}
+
+impl Selector {
+    fn parse(text: &str) -> Option<Self> {
+        let mut compounds = Vec::new();
+        let mut child = Vec::new();
+        let mut pending_child = false;
+        for token in text.replace('>', " > ").split_whitespace() {
+            if token == ">" {
+                if compounds.is_empty() || pending_child {
+                    return None;
+                }
+                pending_child = true;
+                continue;
+            }
+            if token.contains(['+', '~']) {
+                return None;
+            }
+            if !compounds.is_empty() {
+                child.push(pending_child);
+            }
+            pending_child = false;
+            compounds.push(Compound::parse(token)?);
+        }
+        if compounds.is_empty() || pending_child {
+            return None;
+        }
+
+        let specificity = compounds.iter().fold((0, 0, 0), |(a, b, c), compound| {
+            (
+                a + compound.id.is_some() as usize,
+                b + compound.classes.len() + compound.attributes.len(),
+                c + compound.tag.is_some() as usize,
+            )
+        });
+        Some(Self { compounds, child, specificity })
+    }
+
+    fn matches(&self, element: &Element, ancestors: &[Element]) -> bool {
+        self.matches_at(self.compounds.len() - 1, element, ancestors)
+    }
+
+    fn matches_at(&self, index: usize, element: &Element, ancestors: &[Element]) -> bool {
+        if !self.compounds[index].matches(element) {
+            return false;
+        }
+        if index == 0 {
+            return true;
+        }
+        if self.child[index - 1] {
+            ancestors
+                .split_last()
+                .is_some_and(|(parent, rest)| self.matches_at(index - 1, parent, rest))
+        } else {
+            (0..ancestors.len()).rev().any(|i| self.matches_at(index - 1, &ancestors[i], &ancestors[..i]))
+        }
+    }
+}
+
+/// Rule whose selector the inliner understands
+struct Rule {
+    selector: Selector,
+    declarations: Vec<(String, String, bool)>,
+}
+
+/// Stylesheet split into inlinable rules and the CSS that has to stay in `<style>`
+#[derive(Default)]
+struct Stylesheet {
+    rules: Vec<Rule>,
+    remaining: String,
+}
+
+impl Stylesheet {
+    fn parse(css: &str) -> Self {
+        let css = strip_comments(css);
+        let mut stylesheet = Self::default();
+        let mut rest: &str = &css;
+
+        while let Some(open) = rest.find('{') {
+            let Some(close) = matching_brace(rest, open) else {
+                break;
+            };
+            let prelude = rest[..open].rsplit(';').next().unwrap_or("").trim();
+            let block = &rest[open + 1..close];
+
+            if prelude.starts_with("@media") {
+                stylesheet.remaining.push_str(&format!("{} {{{}}}\n", prelude, block));
+            } else if !prelude.starts_with('@') {
+                // `@font-face` and the like are dropped: mail clients cannot load archive fonts
+                let parsed: Vec<(String, String, bool)> =
+                    declarations(block).map(|(p, v, i)| (p, v.to_string(), i)).collect();
+                let mut unsupported = Vec::new();
+                for text in prelude.split(',') {
+                    match Selector::parse(text) {
+                        Some(selector) => stylesheet.rules.push(Rule { selector, declarations: parsed.clone() }),
+                        None => unsupported.push(text.trim()),
+                    }
+                }
+                if !unsupported.is_empty() {
+                    stylesheet.remaining.push_str(&format!("{} {{{}}}\n", unsupported.join(", "), block));
+                }
+            }
+            rest = &rest[close + 1..];
+        }
+        stylesheet
+    }
+
+    /// Declarations applying to `element`, cascaded by importance, specificity and order
+    fn computed(&self, element: &Element, ancestors: &[Element], inline: &str) -> Vec<(String, String)> {
+        let mut matched: Vec<(bool, Specificity, usize, &str, &str)> = Vec::new();
+        for (order, rule) in self.rules.iter().enumerate() {
+            if rule.selector.matches(element, ancestors) {
+                for (property, value, important) in &rule.declarations {
+                    matched.push((*important, rule.selector.specificity, order, property, value));
+                }
+            }
+        }
+        // Inline styles beat every selector but lose to `!important` rules
+        let order = self.rules.len();
+        let inline: Vec<(String, &str, bool)> = declarations(inline).collect();
+        for (property, value, important) in &inline {
+            matched.push((*important, (usize::MAX, 0, 0), order, property, value));
+        }
+        matched.sort_by_key(|(important, specificity, order, ..)| (*important, *specificity, *order));
+
+        let mut styles: Vec<(String, String)> = Vec::new();
+        for (_, _, _, property, value) in matched {
+            styles.retain(|(existing, _)| existing != property);
+            styles.push((property.to_string(), value.to_string()));
+        }
+        styles
+    }
+
+    /// Apply the rules to an HTML fragment wrapped in a `<div>` standing in for `<body>`
+    fn inline(&self, html: &str) -> String {
+        let mut ancestors = vec![Element::new("html")];
+        let mut output = String::with_capacity(html.len() * 2);
+
+        let body = Element::new("body");
+        output.push_str("<div");
+        push_style(&mut output, &self.computed(&body, &ancestors, ""));
+        output.push_str(">\n");
+        ancestors.push(body);
+
+        for token in tokenize(html) {
+            let tag = match token {
+                Token::Tag(tag) => tag,
+                Token::Text(text) | Token::Other(text) => {
+                    output.push_str(text);
+                    continue;
+                }
+            };
+            let name = tag.name.to_ascii_lowercase();
+            if tag.closing {
+                // Close back to the matching element, leaving the wrapper open
+                if let Some(index) = ancestors.iter().rposition(|element| element.name == name) {
+                    if index > 1 {
+                        ancestors.truncate(index);
+                    }
+                }
+                output.push_str(tag.raw);
+                continue;
+            }
+
+            let mut element = Element::new(&name);
+            let mut inline = String::new();
+            for attribute in &tag.attrs {
+                let value = unescape(attribute.value);
+                match attribute.name.to_ascii_lowercase().as_str() {
+                    "id" => element.id = Some(value.clone()),
+                    "class" => element.classes = value.split_whitespace().map(str::to_string).collect(),
+                    "style" => inline = value.clone(),
+                    _ => {}
+                }
+                element.attributes.push((attribute.name.to_ascii_lowercase(), value));
+            }
+
+            let styles = self.computed(&element, &ancestors, &inline);
+            output.push('<');
+            output.push_str(tag.name);
+            for attribute in tag.attrs.iter().filter(|a| !a.name.eq_ignore_ascii_case("style")) {
+                output.push(' ');
+                output.push_str(attribute.raw);
+            }
+            push_style(&mut output, &styles);
+            output.push_str(if tag.self_closing { " />" } else { ">" });
+
+            if !tag.self_closing && !VOID_ELEMENTS.contains(&name.as_str()) {
+                ancestors.push(element);
+            }
+        }
+
+        output.push_str("\n</div>");
+        output
+    }
+}
+
+fn push_style(output: &mut String, styles: &[(String, String)]) {
+    if styles.is_empty() {
+        return;
+    }
+    let style: Vec<String> = styles.iter().map(|(property, value)| format!("{}: {}", property, value)).collect();
+    output.push_str(" style=\"");
+    push_escaped(output, &style.join("; "));
+    output.push('"');
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inlines_rules_by_importance_specificity_and_order() {
+        let stylesheet = Stylesheet::parse(
+            "body { color: #333 } p { color: red; margin: 0 !important } .note { color: blue }\n\
+             div > p { margin: 1em } a:hover { color: green } @media (max-width: 600px) { p { margin: 0 } }",
+        );
+        assert_eq!(
+            stylesheet.inline("<p class=\"note\" style=\"margin: 2em; font-weight: bold\">x</p><br>"),
+            "<div style=\"color: #333\">\n\
+             <p class=\"note\" style=\"color: blue; font-weight: bold; margin: 0\">x</p><br>\n</div>"
+        );
+        assert!(stylesheet.remaining.contains("a:hover {"));
+        assert!(stylesheet.remaining.contains("@media (max-width: 600px) {"));
+    }
+
+    #[test]
+    fn selectors_match_descendants_and_children() {
+        let list = Element::new("ul");
+        let item = Element::new("li");
+        let mut link = Element::new("a");
+        link.attributes.push(("href".to_string(), "x".to_string()));
+
+        let descendant = Selector::parse("ul a[href]").unwrap();
+        assert_eq!(descendant.specificity, (0, 1, 2));
+        assert!(descendant.matches(&link, &[Element::new("ul"), Element::new("li")]));
+        assert!(!Selector::parse("ul > a").unwrap().matches(&link, &[list, item]));
+        assert!(Selector::parse("a + b").is_none());
+        assert!(Selector::parse("a[href^=http]").is_none());
+        assert!(Selector::parse("p::first-line").is_none());
+    }
+
+    #[test]
+    fn eml_headers_cannot_be_injected() {
+        let message = EmailMessage {
+            subject: "Report".to_string(),
+            html: "<p><img src=\"cid:image1@mdz\"></p>".to_string(),
+            text: "Report".to_string(),
+            attachments: vec![EmailAttachment {
+                content_id: "image1@mdz".to_string(),
+                filename: "chart.png".to_string(),
+                mime_type: "image/png".to_string(),
+                data: vec![1, 2, 3],
+            }],
+        };
+        let addresses = EmailAddresses {
+            from: "\"Ann\" <ann@example.com>".to_string(),
+            to: vec!["bob@example.com\r\nBcc: eve@example.com".to_string()],
+            cc: Vec::new(),
+        };
+        let eml = String::from_utf8(message.to_eml(&addresses)).unwrap();
+        assert!(eml.contains("From: Ann <ann@example.com>\r\n"));
+        assert!(eml.contains("To: bob@example.comBcc: eve@example.com\r\n"));
+        assert!(!eml.contains("\r\nBcc:"));
+        let message_id = eml.lines().find(|line| line.starts_with("Message-ID: ")).unwrap();
+        assert!(message_id.ends_with("@example.com>"));
+        assert!(eml.contains("Content-ID: <image1@mdz>"));
+        assert!(eml.contains("multipart/related"));
+        assert_eq!(parse_address("bob@example.com"), (None, "bob@example.com"));
+    }
+}
@@ -0,0 +1,542 @@
+use crate::markup::{tokenize, Tag, Token, VOID_ELEMENTS};
+use crate::renderer::ImageLinks;
+use crate::template::push_escaped;
+use crate::{svg, MdzDocument, MdzRenderer, Result};
+use pulldown_cmark::{Event, Parser};
+use regex::{Captures, Regex};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Write};
+use std::sync::OnceLock;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Deepest heading level that starts a new chapter
+const CHAPTER_LEVEL: u32 = 2;
+
+/// Chapter file with its content
+struct Chapter {
+    file: String,
+    title: String,
+    body: String,
+}
+
+/// Package a document as EPUB 3
+///
+/// Chapters are split at top-level H1 and H2 headings; the navigation
+/// document lists headings down to [`RenderOptions::toc_depth`](crate::RenderOptions).
+/// Chapter bodies, raw HTML included, are rewritten as well-formed XHTML.
+pub(crate) fn render(renderer: &MdzRenderer, document: &MdzDocument) -> Result<Vec<u8>> {
+    let options = renderer.options();
+
+    // Reading systems may run scripts in SVGs, so they are sanitized even when embedded
+    let mut images: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut names: HashMap<String, String> = HashMap::new();
+    for (path, data) in &document.images {
+        let (mime_type, data) = renderer.prepare_image(path, data)?;
+        let (path_out, data) = match mime_type {
+            "image/svg+xml" => (path.clone(), svg::sanitize(&String::from_utf8_lossy(&data)).into_bytes()),
+            "image/png" if path.to_ascii_lowercase().ends_with(".svg") => {
+                (format!("{}.png", &path[..path.len() - 4]), data.into_owned())
+            }
+            _ => (path.clone(), data.into_owned()),
+        };
+        images.insert(path_out.clone(), data);
+        names.insert(path.clone(), path_out);
+    }
+
+    let body = renderer.render_body(document, ImageLinks::Rename(&names), CHAPTER_LEVEL)?;
+    let title = options
+        .html_title
+        .as_deref()
+        .or(document.metadata.title.as_deref())
+        .unwrap_or("MDZ Document");
+
+    let mut chapters: Vec<Chapter> = body
+        .sections
+        .into_iter()
+        .filter(|section| !section.trim().is_empty())
+        .enumerate()
+        .map(|(i, body)| Chapter {
+            file: format!("chapter-{}.xhtml", i + 1),
+            title: String::new(),
+            body: to_xhtml(&body),
+        })
+        .collect();
+
+    // Element ids live in different files once the body is split
+    static ID_PATTERN: OnceLock<Regex> = OnceLock::new();
+    let id_pattern = ID_PATTERN.get_or_init(|| Regex::new(r#"\bid="([^"]+)""#).unwrap());
+    let mut files: HashMap<String, usize> = HashMap::new();
+    for (index, chapter) in chapters.iter().enumerate() {
+        for cap in id_pattern.captures_iter(&chapter.body) {
+            files.entry(unescape(&cap[1])).or_insert(index);
+        }
+    }
+    static HREF_PATTERN: OnceLock<Regex> = OnceLock::new();
+    let href_pattern = HREF_PATTERN.get_or_init(|| Regex::new(r##"href="#([^"]+)""##).unwrap());
+    for (index, chapter) in chapters.iter_mut().enumerate() {
+        chapter.body = href_pattern
+            .replace_all(&chapter.body, |caps: &Captures| match files.get(&unescape(&caps[1])) {
+                Some(&target) if target != index => format!("href=\"chapter-{}.xhtml#{}\"", target + 1, &caps[1]),
+                _ => caps[0].to_string(),
+            })
+            .into_owned();
+    }
+
+    let mut headings = body.headings.iter();
+    for (index, chapter) in chapters.iter_mut().enumerate() {
+        chapter.title = headings
+            .by_ref()
+            .find(|heading| files.get(&heading.id) == Some(&index))
+            .map_or_else(|| title.to_string(), |heading| heading.text.clone());
+    }
+
+    let mut css = renderer.get_css_content(document);
+    for extra in &body.extra_css {
+        css.push('\n');
+        css.push_str(extra);
+    }
+
+    let language = document.metadata.language.as_deref().unwrap_or("en");
+    let toc_entries: Vec<(u32, String, &str)> = body
+        .headings
+        .iter()
+        .filter(|heading| heading.level <= options.toc_depth)
+        .filter_map(|heading| {
+            let chapter = &chapters[*files.get(&heading.id)?];
+            Some((heading.level, format!("{}#{}", chapter.file, heading.id), heading.text.as_str()))
+        })
+        .collect();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The mimetype entry must come first and be stored uncompressed
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER_XML.as_bytes())?;
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(package_document(document, &images, title, language, &chapters).as_bytes())?;
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(navigation_document(title, language, &toc_entries, &chapters).as_bytes())?;
+
+    zip.start_file("OEBPS/css/style.css", deflated)?;
+    zip.write_all(css.as_bytes())?;
+
+    for chapter in &chapters {
+        zip.start_file(format!("OEBPS/{}", chapter.file), deflated)?;
+        zip.write_all(xhtml(&chapter.title, language, &chapter.body).as_bytes())?;
+    }
+
+    for (path, data) in sorted(&images).into_iter().chain(sorted(&document.fonts)) {
+        zip.start_file(format!("OEBPS/{}", path), deflated)?;
+        zip.write_all(data)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn sorted(files: &HashMap<String, Vec<u8>>) -> Vec<(&String, &Vec<u8>)> {
+    let mut files: Vec<_> = files.iter().collect();
+    files.sort_by(|a, b| a.0.cmp(b.0));
+    files
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&quot;", "\"").replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+fn escaped(text: &str) -> String {
+    let mut output = String::new();
+    push_escaped(&mut output, text);
+    output
+}
+
+/// Stable identifier derived from the title and content
+fn identifier(document: &MdzDocument, title: &str) -> String {
+    let hash = |seed: u8| {
+        let mut hasher = DefaultHasher::new();
+        seed.hash(&mut hasher);
+        title.hash(&mut hasher);
+        document.content.hash(&mut hasher);
+        hasher.finish()
+    };
+    let hex = format!("{:016x}{:016x}", hash(1), hash(2));
+    format!("urn:uuid:{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+fn package_document(
+    document: &MdzDocument,
+    images: &HashMap<String, Vec<u8>>,
+    title: &str,
+    language: &str,
+    chapters: &[Chapter],
+) -> String {
+    let metadata = &document.metadata;
+    let modified = metadata
+        .modified_at
+        .or(metadata.created_at)
+        .unwrap_or_else(chrono::Utc::now)
+        .format("%Y-%m-%dT%H:%M:%SZ");
+
+    let mut opf = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    opf.push_str(&format!(
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"{}\">\n",
+        escaped(language)
+    ));
+    opf.push_str("  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n");
+    opf.push_str(&format!("    <dc:identifier id=\"book-id\">{}</dc:identifier>\n", identifier(document, title)));
+    opf.push_str(&format!("    <dc:title>{}</dc:title>\n", escaped(title)));
+    opf.push_str(&format!("    <dc:language>{}</dc:language>\n", escaped(language)));
+    if let Some(author) = &metadata.author {
+        opf.push_str(&format!("    <dc:creator>{}</dc:creator>\n", escaped(author)));
+    }
+    if let Some(description) = &metadata.description {
+        opf.push_str(&format!("    <dc:description>{}</dc:description>\n", escaped(description)));
+    }
+    if let Some(created) = metadata.created_at {
+        opf.push_str(&format!("    <dc:date>{}</dc:date>\n", created.format("%Y-%m-%dT%H:%M:%SZ")));
+    }
+    opf.push_str(&format!("    <meta property=\"dcterms:modified\">{}</meta>\n", modified));
+    opf.push_str("  </metadata>\n  <manifest>\n");
+    opf.push_str("    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n");
+    opf.push_str("    <item id=\"style\" href=\"css/style.css\" media-type=\"text/css\"/>\n");
+
+    for (index, chapter) in chapters.iter().enumerate() {
+        let mut properties = Vec::new();
+        if chapter.body.contains("<math") {
+            properties.push("mathml");
+        }
+        if chapter.body.contains("<svg") {
+            properties.push("svg");
+        }
+        let properties = if properties.is_empty() {
+            String::new()
+        } else {
+            format!(" properties=\"{}\"", properties.join(" "))
+        };
+        opf.push_str(&format!(
+            "    <item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"{}/>\n",
+            index + 1,
+            chapter.file,
+            properties
+        ));
+    }
+    for (kind, files) in [("image", images), ("font", &document.fonts)] {
+        for (index, (path, _)) in sorted(files).into_iter().enumerate() {
+            let media_type = mime_guess::from_path(path).first_or_octet_stream();
+            opf.push_str(&format!(
+                "    <item id=\"{}-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                kind,
+                index + 1,
+                escaped(path),
+                media_type
+            ));
+        }
+    }
+
+    opf.push_str("  </manifest>\n  <spine>\n");
+    for index in 0..chapters.len() {
+        opf.push_str(&format!("    <itemref idref=\"chapter-{}\"/>\n", index + 1));
+    }
+    opf.push_str("  </spine>\n</package>\n");
+    opf
+}
+
+/// Navigation document with a nested list of `(level, href, text)` entries
+fn navigation_document(title: &str, language: &str, entries: &[(u32, String, &str)], chapters: &[Chapter]) -> String {
+    let mut nav = String::from("<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n");
+    if entries.is_empty() {
+        // Every document needs a non-empty table of contents
+        nav.push_str("<ol>\n");
+        for chapter in chapters {
+            nav.push_str(&format!("<li><a href=\"{}\">{}</a></li>\n", chapter.file, escaped(&chapter.title)));
+        }
+        nav.push_str("</ol>\n");
+    } else {
+        push_entries(&mut nav, entries);
+    }
+    nav.push_str("</nav>");
+    xhtml(title, language, &nav)
+}
+
+/// Append entries as nested `<ol>` lists; deeper entries nest under the previous one
+fn push_entries(output: &mut String, entries: &[(u32, String, &str)]) {
+    output.push_str("<ol>\n");
+    let mut index = 0;
+    while index < entries.len() {
+        let (level, href, text) = &entries[index];
+        let children = entries[index + 1..].iter().take_while(|(child, _, _)| child > level).count();
+        output.push_str(&format!("<li><a href=\"{}\">{}</a>", escaped(href), escaped(text)));
+        if children > 0 {
+            output.push('\n');
+            push_entries(output, &entries[index + 1..index + 1 + children]);
+        }
+        output.push_str("</li>\n");
+        index += 1 + children;
+    }
+    output.push_str("</ol>\n");
+}
+
+/// Elements whose start tag closes an open `<p>`
+const CLOSES_PARAGRAPH: &[&str] = &[
+    "address", "article", "aside", "blockquote", "details", "div", "dl", "fieldset", "figcaption", "figure",
+    "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "main", "nav", "ol", "p", "pre",
+    "section", "table", "ul",
+];
+
+/// Elements that shield an enclosing `<p>` from being closed, as HTML's button scope
+const PARAGRAPH_SCOPE: &[&str] = &["button", "caption", "math", "object", "svg", "table", "td", "template", "th"];
+
+/// Rewrite HTML as well-formed XHTML
+///
+/// Tags are rebuilt with quoted attributes, void elements are written as
+/// `<br />`, elements left open by HTML's optional end tags are closed and
+/// stray end tags dropped. Named entities other than XML's own become numeric
+/// references.
+fn to_xhtml(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut open: Vec<&str> = Vec::new();
+
+    for token in tokenize(html) {
+        let tag = match token {
+            Token::Tag(tag) if tag.is_complete() && is_xml_name(tag.name) => tag,
+            Token::Tag(Tag { raw, .. }) | Token::Text(raw) => {
+                push_xml_text(&mut output, raw, false);
+                continue;
+            }
+            Token::Other(raw) if raw.starts_with("<!--") && raw.ends_with("-->") => {
+                output.push_str(raw);
+                continue;
+            }
+            Token::Other(raw) if raw.starts_with("<![CDATA[") && raw.ends_with("]]>") => {
+                output.push_str(raw);
+                continue;
+            }
+            Token::Other(_) => continue,
+        };
+
+        let name = tag.name.to_ascii_lowercase();
+        let void = VOID_ELEMENTS.contains(&name.as_str());
+        if tag.closing {
+            if let Some(index) = open.iter().rposition(|open| open.eq_ignore_ascii_case(tag.name)) {
+                close_elements(&mut output, &mut open, index);
+            }
+            continue;
+        }
+
+        // Start tags that end an open element, up to the element enclosing it
+        let implied: Option<(&[&str], &[&str])> = match name.as_str() {
+            "li" => Some((&["li"], &["ol", "ul"])),
+            "dt" | "dd" => Some((&["dt", "dd"], &["dl"])),
+            "tr" => Some((&["tr"], &["table", "thead", "tbody", "tfoot"])),
+            "td" | "th" => Some((&["td", "th"], &["tr", "table"])),
+            "option" => Some((&["option"], &["select", "datalist"])),
+            _ if CLOSES_PARAGRAPH.contains(&name.as_str()) => Some((&["p"], PARAGRAPH_SCOPE)),
+            _ => None,
+        };
+        if let Some((ends, boundaries)) = implied {
+            close_implied(&mut output, &mut open, ends, boundaries);
+        }
+
+        output.push('<');
+        output.push_str(if void { &name } else { tag.name });
+        let mut seen = Vec::new();
+        for attr in &tag.attrs {
+            if !is_xml_name(attr.name) || seen.contains(&attr.name) {
+                continue;
+            }
+            seen.push(attr.name);
+            output.push(' ');
+            output.push_str(attr.name);
+            output.push_str("=\"");
+            push_xml_text(&mut output, attr.value, true);
+            output.push('"');
+        }
+        if void || tag.self_closing {
+            output.push_str(" />");
+        } else {
+            output.push('>');
+            open.push(tag.name);
+        }
+    }
+
+    close_elements(&mut output, &mut open, 0);
+    output
+}
+
+/// Close the innermost open element named in `ends`, unless one of
+/// `boundaries` is open inside it
+fn close_implied(output: &mut String, open: &mut Vec<&str>, ends: &[&str], boundaries: &[&str]) {
+    for index in (0..open.len()).rev() {
+        let name = open[index].to_ascii_lowercase();
+        if ends.contains(&name.as_str()) {
+            close_elements(output, open, index);
+            return;
+        }
+        if boundaries.contains(&name.as_str()) {
+            return;
+        }
+    }
+}
+
+/// Write end tags for open elements from the innermost down to `index`
+fn close_elements(output: &mut String, open: &mut Vec<&str>, index: usize) {
+    for name in open.drain(index..).rev() {
+        output.push_str("</");
+        output.push_str(name);
+        output.push('>');
+    }
+}
+
+/// Check for a name XML accepts for elements and attributes
+fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | ':' | '-' | '.'))
+}
+
+/// Escape text or an attribute value for XML, keeping valid character
+/// references and writing named HTML entities as numeric ones
+fn push_xml_text(output: &mut String, text: &str, attribute: bool) {
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    let reference =
+        REFERENCE.get_or_init(|| Regex::new(r"^&(?:#[0-9]{1,7}|#[xX][0-9a-fA-F]{1,6}|([A-Za-z][A-Za-z0-9]{0,31}));").unwrap());
+
+    let mut rest = text;
+    while let Some(index) = rest.find(['&', '<', '"']) {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+        if rest.starts_with('<') {
+            output.push_str("&lt;");
+            rest = &rest[1..];
+            continue;
+        }
+        if rest.starts_with('"') {
+            output.push_str(if attribute { "&quot;" } else { "\"" });
+            rest = &rest[1..];
+            continue;
+        }
+        let Some(caps) = reference.captures(rest) else {
+            output.push_str("&amp;");
+            rest = &rest[1..];
+            continue;
+        };
+        let whole = caps.get(0).unwrap().as_str();
+        match caps.get(1).map(|name| name.as_str()) {
+            None if decode_numeric(whole).is_some() => output.push_str(whole),
+            None => output.push_str("&#xFFFD;"),
+            Some("amp" | "lt" | "gt" | "quot" | "apos") => output.push_str(whole),
+            Some(_) => match html_entity(whole) {
+                Some(decoded) => {
+                    for c in decoded.chars() {
+                        output.push_str(&format!("&#x{:X};", c as u32));
+                    }
+                }
+                None => {
+                    output.push_str("&amp;");
+                    output.push_str(&whole[1..]);
+                }
+            },
+        }
+        rest = &rest[whole.len()..];
+    }
+    output.push_str(rest);
+}
+
+/// Character of a numeric reference, if XML allows it
+fn decode_numeric(reference: &str) -> Option<char> {
+    let digits = reference.trim_start_matches("&#").trim_end_matches(';');
+    let code = match digits.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    char::from_u32(code).filter(|&c| c >= ' ' || matches!(c, '\t' | '\n' | '\r'))
+}
+
+/// Text of a named HTML entity such as `&nbsp;`, looked up in pulldown-cmark's
+/// table by parsing it as Markdown
+fn html_entity(reference: &str) -> Option<String> {
+    let decoded: String = Parser::new(reference)
+        .filter_map(|event| match event {
+            Event::Text(text) => Some(text.into_string()),
+            _ => None,
+        })
+        .collect();
+    (decoded != reference).then_some(decoded)
+}
+
+fn xhtml(title: &str, language: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"{lang}\" xml:lang=\"{lang}\">\n\
+         <head>\n\
+         <meta charset=\"UTF-8\"/>\n\
+         <title>{title}</title>\n\
+         <link rel=\"stylesheet\" type=\"text/css\" href=\"css/style.css\"/>\n\
+         </head>\n\
+         <body>\n{body}\n</body>\n\
+         </html>\n",
+        lang = escaped(language),
+        title = escaped(title),
+        body = body,
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn xhtml_balances_tags_and_quotes_attributes() {
+        assert_eq!(
+            to_xhtml("<p>One<p>Two <b>bold<div class=x hidden>d</span></div><ul><li>a<li>b</ul>"),
+            "<p>One</p><p>Two <b>bold</b></p><div class=\"x\" hidden=\"\">d</div><ul><li>a</li><li>b</li></ul>"
+        );
+        assert_eq!(
+            to_xhtml("<table><tr><td>a<td>b<tr><th>c</table><BR><img src='a.png' alt='say \"hi\"'>"),
+            "<table><tr><td>a</td><td>b</td></tr><tr><th>c</th></tr></table><br /><img src=\"a.png\" alt=\"say &quot;hi&quot;\" />"
+        );
+    }
+
+    #[test]
+    fn xhtml_uses_numeric_entities() {
+        assert_eq!(
+            to_xhtml("a&nbsp;b &copy; &amp; &#169; &#x1; &nope; AT&T <3"),
+            "a&#xA0;b &#xA9; &amp; &#169; &#xFFFD; &amp;nope; AT&amp;T &lt;3"
+        );
+    }
+
+    #[test]
+    fn splits_chapters_and_links_across_them() {
+        let document = MdzDocument::new("# One\n\nSee [two](#two).\n\n# Two\n\n<p>Text&hellip;\n".to_string());
+        let epub = MdzRenderer::new().render_epub(&document).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub)).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+
+        let mut chapter = String::new();
+        archive.by_name("OEBPS/chapter-1.xhtml").unwrap().read_to_string(&mut chapter).unwrap();
+        assert!(chapter.contains("<a href=\"chapter-2.xhtml#two\">two</a>"));
+        chapter.clear();
+        archive.by_name("OEBPS/chapter-2.xhtml").unwrap().read_to_string(&mut chapter).unwrap();
+        assert!(chapter.contains("<p>Text&#x2026;\n</p>"), "{}", chapter);
+    }
+}
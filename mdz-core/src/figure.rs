@@ -0,0 +1,544 @@
+use crate::highlight::FenceInfo;
+use crate::template::push_escaped;
+use pulldown_cmark::escape::escape_href;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use regex::Regex;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Kind of numbered element
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FigureKind {
+    Figure,
+    Table,
+    Listing,
+}
+
+impl FigureKind {
+    /// Label prefix used in cross-references (`@fig:name`)
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::Figure => "fig",
+            Self::Table => "tbl",
+            Self::Listing => "lst",
+        }
+    }
+
+    /// Word shown before the number
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Figure => "Figure",
+            Self::Table => "Table",
+            Self::Listing => "Listing",
+        }
+    }
+
+    fn from_label(label: &str) -> Option<Self> {
+        match label.split_once(':')?.0 {
+            "fig" => Some(Self::Figure),
+            "tbl" => Some(Self::Table),
+            "lst" => Some(Self::Listing),
+            _ => None,
+        }
+    }
+}
+
+/// Numbers assigned to labelled figures, tables and listings
+#[derive(Debug, Clone, Default)]
+pub struct Labels {
+    numbers: HashMap<String, (FigureKind, usize)>,
+    /// Labels defined more than once
+    pub duplicates: Vec<String>,
+}
+
+impl Labels {
+    /// Kind and number of a label such as `fig:architecture`
+    pub fn get(&self, label: &str) -> Option<(FigureKind, usize)> {
+        self.numbers.get(label).copied()
+    }
+
+    /// All defined labels
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.numbers.keys().map(String::as_str)
+    }
+
+    fn insert(&mut self, label: String, kind: FigureKind, number: usize) {
+        match self.numbers.entry(label) {
+            Entry::Occupied(entry) => self.duplicates.push(entry.key().clone()),
+            Entry::Vacant(entry) => {
+                entry.insert((kind, number));
+            }
+        }
+    }
+}
+
+/// Numbered element found in the event stream
+struct Item {
+    kind: FigureKind,
+    label: Option<String>,
+    /// Events replaced by the item
+    range: Range<usize>,
+    /// Events of the element itself (image, table or code block)
+    body: Range<usize>,
+    caption: Caption,
+}
+
+enum Caption {
+    None,
+    Text(String),
+    /// Inline events of a `Table:` paragraph, with prefix and label removed
+    Events(Vec<Event<'static>>),
+}
+
+/// Reference to a label in text
+pub struct CrossReference<'t> {
+    pub label: &'t str,
+    /// Byte range of `@prefix:name` in the text
+    pub span: Range<usize>,
+}
+
+/// Find `@fig:`, `@tbl:` and `@lst:` references in text
+pub fn find_references(text: &str) -> Vec<CrossReference<'_>> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| Regex::new(r"(?:^|[^\w@])@((?:fig|tbl|lst):[\w-]+)").unwrap());
+    pattern
+        .captures_iter(text)
+        .map(|caps| {
+            let label = caps.get(1).unwrap();
+            CrossReference {
+                label: label.as_str(),
+                span: label.start() - 1..label.end(),
+            }
+        })
+        .collect()
+}
+
+/// Merge adjacent text events, which pulldown-cmark splits at special characters
+pub fn merge_text(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut merged: Vec<Event> = Vec::with_capacity(events.len());
+    for event in events {
+        match (merged.last_mut(), event) {
+            (Some(Event::Text(previous)), Event::Text(text)) => {
+                *previous = CowStr::from(format!("{}{}", previous, text));
+            }
+            (_, event) => merged.push(event),
+        }
+    }
+    merged
+}
+
+/// Collect the labels defined in an event stream
+pub fn collect_labels(events: &[Event]) -> Labels {
+    number_items(&find_items(events))
+}
+
+/// Wrap figures, tables and listings with captions and resolve cross-references
+pub(crate) fn apply<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let events = merge_text(events);
+    let items = find_items(&events);
+    let labels = number_items(&items);
+    let mut numbers = numbers_by_item(&items).into_iter();
+
+    let mut output = Vec::with_capacity(events.len());
+    let mut items = items.into_iter().peekable();
+    let mut index = 0;
+    // References are not resolved in code blocks
+    let mut code_depth = 0;
+
+    while index < events.len() {
+        if let Some(item) = items.next_if(|item| item.range.start == index) {
+            let number = numbers.next().unwrap_or(0);
+            render_item(&item, number, &events, &labels, &mut output);
+            index = item.range.end;
+            continue;
+        }
+
+        match &events[index] {
+            Event::Start(Tag::CodeBlock(_)) => {
+                code_depth += 1;
+                output.push(events[index].clone());
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                code_depth -= 1;
+                output.push(events[index].clone());
+            }
+            Event::Text(text) if code_depth == 0 => link_references(text, &labels, &mut output),
+            other => output.push(other.clone()),
+        }
+        index += 1;
+    }
+
+    output
+}
+
+fn number_items(items: &[Item]) -> Labels {
+    let mut labels = Labels::default();
+    for (item, number) in items.iter().zip(numbers_by_item(items)) {
+        if let Some(label) = &item.label {
+            labels.insert(label.clone(), item.kind, number);
+        }
+    }
+    labels
+}
+
+/// Number items per kind in document order
+fn numbers_by_item(items: &[Item]) -> Vec<usize> {
+    let mut counters: HashMap<FigureKind, usize> = HashMap::new();
+    items
+        .iter()
+        .map(|item| {
+            let counter = counters.entry(item.kind).or_default();
+            *counter += 1;
+            *counter
+        })
+        .collect()
+}
+
+fn find_items(events: &[Event]) -> Vec<Item> {
+    let mut items: Vec<Item> = Vec::new();
+    let mut index = 0;
+
+    while index < events.len() {
+        let item = match &events[index] {
+            Event::Start(Tag::Paragraph) => image_figure(events, index),
+            Event::Start(Tag::Table(_)) => table(events, index, items.last()),
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => listing(events, index, info),
+            _ => None,
+        };
+
+        match item {
+            Some(item) => {
+                // A caption paragraph before a table was passed over as plain text
+                if items.last().is_some_and(|last| last.range.end > item.range.start) {
+                    items.pop();
+                }
+                index = item.range.end;
+                items.push(item);
+            }
+            None => index += 1,
+        }
+    }
+
+    items
+}
+
+/// Paragraph holding a single image with a title or `{#fig:label}`
+fn image_figure(events: &[Event], start: usize) -> Option<Item> {
+    let Some(Event::Start(Tag::Image(_, _, title))) = events.get(start + 1) else {
+        return None;
+    };
+    let image_end = start + 1 + events[start + 1..].iter().position(|e| matches!(e, Event::End(Tag::Image(..))))?;
+
+    let mut trailing = String::new();
+    let mut end = image_end + 1;
+    loop {
+        match events.get(end)? {
+            Event::Text(text) => trailing.push_str(text),
+            Event::SoftBreak => trailing.push(' '),
+            Event::End(Tag::Paragraph) => break,
+            _ => return None,
+        }
+        end += 1;
+    }
+
+    let label = match trailing.trim() {
+        "" => None,
+        attrs => Some(parse_label(attrs).filter(|l| l.starts_with("fig:"))?),
+    };
+    if title.is_empty() && label.is_none() {
+        return None;
+    }
+
+    Some(Item {
+        kind: FigureKind::Figure,
+        label,
+        range: start..end + 1,
+        body: start + 1..image_end + 1,
+        caption: if title.is_empty() { Caption::None } else { Caption::Text(title.to_string()) },
+    })
+}
+
+/// Table with a `Table: caption {#tbl:label}` paragraph after or before it
+fn table(events: &[Event], start: usize, previous: Option<&Item>) -> Option<Item> {
+    let end = start
+        + events[start..]
+            .iter()
+            .position(|e| matches!(e, Event::End(Tag::Table(_))))
+            .unwrap_or(events.len() - start - 1)
+        + 1;
+
+    let after = table_caption(events, end);
+    let before = || {
+        let paragraph_start = events[..start].iter().rposition(|e| matches!(e, Event::Start(Tag::Paragraph)))?;
+        let claimed = previous.is_some_and(|item| item.range.end > paragraph_start);
+        let (caption_end, caption) = table_caption(events, paragraph_start).filter(|_| !claimed)?;
+        (caption_end == start).then_some((paragraph_start, caption))
+    };
+
+    let (range, (label, caption)) = match after {
+        Some((caption_end, caption)) => (start..caption_end, caption),
+        None => {
+            let (caption_start, caption) = before()?;
+            (caption_start..end, caption)
+        }
+    };
+    Some(Item {
+        kind: FigureKind::Table,
+        label,
+        range,
+        body: start..end,
+        caption: Caption::Events(caption),
+    })
+}
+
+/// Parse a `Table:` caption paragraph starting at `start`
+///
+/// Returns the index after the paragraph, the label and the caption's inline events.
+#[allow(clippy::type_complexity)]
+fn table_caption(events: &[Event], start: usize) -> Option<(usize, (Option<String>, Vec<Event<'static>>))> {
+    if !matches!(events.get(start), Some(Event::Start(Tag::Paragraph))) {
+        return None;
+    }
+    let Some(Event::Text(first)) = events.get(start + 1) else {
+        return None;
+    };
+    let rest = first.trim_start().strip_prefix("Table:")?;
+    let end = start + events[start..].iter().position(|e| matches!(e, Event::End(Tag::Paragraph)))?;
+
+    let mut inner: Vec<Event<'static>> = events[start + 2..end].iter().map(|e| into_static(e.clone())).collect();
+    inner.insert(0, Event::Text(rest.trim_start().to_string().into()));
+
+    let mut label = None;
+    if let Some(Event::Text(last)) = inner.last_mut() {
+        if let Some((text, attrs)) = last.trim_end().strip_suffix('}').and_then(|t| t.rsplit_once('{')) {
+            label = parse_label(&format!("{{{}}}", attrs)).filter(|l| l.starts_with("tbl:"));
+            if label.is_some() {
+                *last = text.trim_end().to_string().into();
+            }
+        }
+    }
+
+    Some((end + 1, (label, inner)))
+}
+
+/// Fenced code block with `{#lst:label caption="..."}`, or `#fig:` for rendered diagrams
+fn listing(events: &[Event], start: usize, info: &str) -> Option<Item> {
+    let fence = FenceInfo::parse(info);
+    let label = fence
+        .attributes
+        .iter()
+        .find_map(|(key, _)| key.strip_prefix('#'))
+        .filter(|label| matches!(FigureKind::from_label(label), Some(FigureKind::Figure | FigureKind::Listing)))
+        .map(str::to_string);
+    let caption = fence
+        .attributes
+        .iter()
+        .find(|(key, _)| key == "caption" || key == "title")
+        .map(|(_, value)| value.clone());
+
+    if label.is_none() && caption.is_none() {
+        return None;
+    }
+
+    let end = start + events[start..].iter().position(|e| matches!(e, Event::End(Tag::CodeBlock(_))))? + 1;
+    let kind = label.as_deref().and_then(FigureKind::from_label).unwrap_or(FigureKind::Listing);
+    Some(Item {
+        kind,
+        label,
+        range: start..end,
+        body: start..end,
+        caption: caption.map(Caption::Text).unwrap_or(Caption::None),
+    })
+}
+
+/// Extract the `#id` from an attribute block such as `{#fig:overview}`
+fn parse_label(attrs: &str) -> Option<String> {
+    let inner = attrs.trim().strip_prefix('{')?.strip_suffix('}')?;
+    inner
+        .split_whitespace()
+        .find_map(|word| word.strip_prefix('#'))
+        .map(str::to_string)
+}
+
+fn render_item<'a>(item: &Item, number: usize, events: &[Event<'a>], labels: &Labels, output: &mut Vec<Event<'a>>) {
+    let class = match item.kind {
+        FigureKind::Figure => "figure",
+        FigureKind::Table => "table",
+        FigureKind::Listing => "listing",
+    };
+    let mut open = format!("<figure class=\"{}\"", class);
+    if let Some(label) = &item.label {
+        open.push_str(" id=\"");
+        push_escaped(&mut open, label);
+        open.push('"');
+    }
+    open.push_str(">\n");
+
+    let mut caption_start = format!("<figcaption><span class=\"caption-label\">{} {}", item.kind.name(), number);
+    let mut caption_events = Vec::new();
+    match &item.caption {
+        Caption::None => caption_start.push_str("</span>"),
+        Caption::Text(text) => {
+            caption_start.push_str(":</span> ");
+            push_escaped(&mut caption_start, text);
+        }
+        Caption::Events(events) => {
+            caption_start.push_str(":</span> ");
+            for event in events {
+                match event {
+                    Event::Text(text) => link_references(text, labels, &mut caption_events),
+                    other => caption_events.push(other.clone()),
+                }
+            }
+        }
+    }
+
+    let body = events[item.body.clone()].iter().cloned();
+    if item.kind == FigureKind::Figure {
+        // Figures are captioned below, tables and listings above
+        output.push(Event::Html(open.into()));
+        output.extend(body);
+        output.push(Event::Html(format!("\n{}", caption_start).into()));
+        output.extend(caption_events);
+        output.push(Event::Html("</figcaption>\n</figure>\n".into()));
+    } else {
+        output.push(Event::Html(format!("{}{}", open, caption_start).into()));
+        output.extend(caption_events);
+        output.push(Event::Html("</figcaption>\n".into()));
+        output.extend(body);
+        output.push(Event::Html("</figure>\n".into()));
+    }
+}
+
+/// Push text with resolved cross-references turned into links
+fn link_references<'a>(text: &CowStr<'a>, labels: &Labels, output: &mut Vec<Event<'a>>) {
+    let references: Vec<_> = find_references(text)
+        .into_iter()
+        .filter_map(|r| labels.get(r.label).map(|target| (r, target)))
+        .collect();
+    if references.is_empty() {
+        output.push(Event::Text(text.clone()));
+        return;
+    }
+
+    let mut last = 0;
+    for (reference, (kind, number)) in references {
+        if reference.span.start > last {
+            output.push(Event::Text(text[last..reference.span.start].to_string().into()));
+        }
+        let mut link = String::from("<a class=\"cross-ref\" href=\"#");
+        escape_href(&mut link, reference.label).unwrap();
+        link.push_str(&format!("\">{} {}</a>", kind.name(), number));
+        output.push(Event::Html(link.into()));
+        last = reference.span.end;
+    }
+    if last < text.len() {
+        output.push(Event::Text(text[last..].to_string().into()));
+    }
+}
+
+fn into_static(event: Event<'_>) -> Event<'static> {
+    fn own(s: CowStr<'_>) -> CowStr<'static> {
+        CowStr::from(s.into_string())
+    }
+    match event {
+        Event::Text(t) => Event::Text(own(t)),
+        Event::Code(t) => Event::Code(own(t)),
+        Event::Html(t) => Event::Html(own(t)),
+        Event::FootnoteReference(t) => Event::FootnoteReference(own(t)),
+        Event::SoftBreak => Event::SoftBreak,
+        Event::HardBreak => Event::HardBreak,
+        Event::Rule => Event::Rule,
+        Event::TaskListMarker(checked) => Event::TaskListMarker(checked),
+        Event::Start(tag) => Event::Start(tag_into_static(tag)),
+        Event::End(tag) => Event::End(tag_into_static(tag)),
+    }
+}
+
+fn tag_into_static(tag: Tag<'_>) -> Tag<'static> {
+    fn own(s: CowStr<'_>) -> CowStr<'static> {
+        CowStr::from(s.into_string())
+    }
+    match tag {
+        Tag::Paragraph => Tag::Paragraph,
+        // Heading ids and classes are borrowed from the source; captions never contain headings
+        Tag::Heading(level, ..) => Tag::Heading(level, None, Vec::new()),
+        Tag::BlockQuote => Tag::BlockQuote,
+        Tag::CodeBlock(CodeBlockKind::Indented) => Tag::CodeBlock(CodeBlockKind::Indented),
+        Tag::CodeBlock(CodeBlockKind::Fenced(info)) => Tag::CodeBlock(CodeBlockKind::Fenced(own(info))),
+        Tag::List(start) => Tag::List(start),
+        Tag::Item => Tag::Item,
+        Tag::FootnoteDefinition(label) => Tag::FootnoteDefinition(own(label)),
+        Tag::Table(alignments) => Tag::Table(alignments),
+        Tag::TableHead => Tag::TableHead,
+        Tag::TableRow => Tag::TableRow,
+        Tag::TableCell => Tag::TableCell,
+        Tag::Emphasis => Tag::Emphasis,
+        Tag::Strong => Tag::Strong,
+        Tag::Strikethrough => Tag::Strikethrough,
+        Tag::Link(kind, dest, title) => Tag::Link(kind, own(dest), own(title)),
+        Tag::Image(kind, dest, title) => Tag::Image(kind, own(dest), own(title)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{html, Options, Parser};
+
+    fn render(markdown: &str) -> String {
+        let mut output = String::new();
+        html::push_html(&mut output, apply(Parser::new_ext(markdown, Options::ENABLE_TABLES).collect()).into_iter());
+        output
+    }
+
+    fn labels(markdown: &str) -> Labels {
+        collect_labels(&merge_text(Parser::new_ext(markdown, Options::ENABLE_TABLES).collect()))
+    }
+
+    #[test]
+    fn kinds_are_numbered_separately() {
+        let labels = labels(
+            "![A](a.png \"First\") {#fig:a}\n\n\
+             ```rust {#lst:main caption=\"Main\"}\nfn main() {}\n```\n\n\
+             ![B](b.png \"Second\") {#fig:b}\n",
+        );
+        assert_eq!(labels.get("fig:a"), Some((FigureKind::Figure, 1)));
+        assert_eq!(labels.get("fig:b"), Some((FigureKind::Figure, 2)));
+        assert_eq!(labels.get("lst:main"), Some((FigureKind::Listing, 1)));
+    }
+
+    #[test]
+    fn tables_without_captions_are_not_numbered() {
+        let labels = labels("| a |\n|---|\n| 1 |\n\n| b |\n|---|\n| 2 |\n\nTable: Results {#tbl:results}\n");
+        assert_eq!(labels.get("tbl:results"), Some((FigureKind::Table, 1)));
+    }
+
+    #[test]
+    fn duplicate_labels_are_reported() {
+        let labels = labels("![A](a.png) {#fig:x}\n\n![B](b.png) {#fig:x}\n");
+        assert_eq!(labels.get("fig:x"), Some((FigureKind::Figure, 1)));
+        assert_eq!(labels.duplicates, ["fig:x"]);
+    }
+
+    #[test]
+    fn references_link_to_numbered_items() {
+        let html = render("![A](a.png \"Arch\") {#fig:arch}\n\nSee @fig:arch.\n");
+        assert!(html.contains("<a class=\"cross-ref\" href=\"#fig:arch\">Figure 1</a>"), "{}", html);
+    }
+
+    #[test]
+    fn references_in_code_are_left_alone() {
+        let html = render("![A](a.png) {#fig:arch}\n\n```\n@fig:arch\n```\n\n`@fig:arch`\n");
+        assert!(html.contains("<code>@fig:arch\n</code>"), "{}", html);
+        assert!(html.contains("<code>@fig:arch</code>"), "{}", html);
+    }
+
+    #[test]
+    fn email_addresses_are_not_references() {
+        let labels: Vec<&str> = find_references("mail a@fig:x, see @fig:y").iter().map(|r| r.label).collect();
+        assert_eq!(labels, ["fig:y"]);
+    }
+}
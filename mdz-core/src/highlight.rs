@@ -0,0 +1,303 @@
+use crate::template::push_escaped;
+use crate::Result;
+use regex::Regex;
+use std::ops::RangeInclusive;
+use std::sync::OnceLock;
+
+/// Parsed fenced code block info string
+///
+/// Supports a language followed by optional attributes in braces:
+/// ```` ```rust {linenos hl_lines="2-3 5" start=10} ````
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FenceInfo {
+    pub language: Option<String>,
+    pub line_numbers: Option<bool>,
+    pub highlight_lines: Vec<RangeInclusive<usize>>,
+    pub start_line: usize,
+    /// Attributes not recognized by the renderer, as `(key, value)` pairs
+    pub attributes: Vec<(String, String)>,
+}
+
+impl FenceInfo {
+    /// Parse a fence info string
+    pub fn parse(info: &str) -> Self {
+        let mut fence = FenceInfo {
+            start_line: 1,
+            ..Default::default()
+        };
+
+        let (head, attrs) = match info.find('{') {
+            Some(i) => (&info[..i], info[i + 1..].trim_end().trim_end_matches('}')),
+            None => (info, ""),
+        };
+
+        let mut words = head.split_whitespace();
+        fence.language = words.next().map(|w| w.trim_start_matches('.').to_string());
+
+        for (key, value) in words.map(split_attr).chain(tokenize_attrs(attrs)) {
+            match key.as_str() {
+                "linenos" | ".line-numbers" | "line-numbers" | "linenums" => {
+                    fence.line_numbers = Some(value.as_deref() != Some("false"));
+                }
+                "hl_lines" | "highlight" | "hl" => {
+                    fence.highlight_lines = parse_line_ranges(value.as_deref().unwrap_or(""));
+                }
+                "start" | "linenostart" => {
+                    if let Some(n) = value.and_then(|v| v.parse().ok()) {
+                        fence.start_line = n;
+                    }
+                }
+                _ => fence.attributes.push((key, value.unwrap_or_default())),
+            }
+        }
+
+        fence
+    }
+
+    /// Check if a (1-based, relative to the block) line is highlighted
+    pub fn is_highlighted(&self, line: usize) -> bool {
+        self.highlight_lines.iter().any(|r| r.contains(&line))
+    }
+}
+
+/// Theme used for HTML code blocks when the configured one does not exist
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+
+/// Names of the bundled highlighting themes
+pub fn available_themes() -> Vec<String> {
+    #[cfg(feature = "highlight")]
+    {
+        let mut names: Vec<String> = engine::themes().themes.keys().cloned().collect();
+        names.sort();
+        names
+    }
+    #[cfg(not(feature = "highlight"))]
+    {
+        Vec::new()
+    }
+}
+
+/// Check if a highlighting theme is bundled
+pub fn has_theme(name: &str) -> bool {
+    #[cfg(feature = "highlight")]
+    {
+        engine::themes().themes.contains_key(name)
+    }
+    #[cfg(not(feature = "highlight"))]
+    {
+        let _ = name;
+        false
+    }
+}
+
+/// Render a code block to HTML, highlighted when `theme` is set and the language is known
+///
+/// An unknown theme falls back to [`DEFAULT_THEME`] rather than failing the render.
+pub(crate) fn render_code_block(code: &str, fence: &FenceInfo, theme: Option<&str>, line_numbers: bool) -> Result<String> {
+    let line_numbers = fence.line_numbers.unwrap_or(line_numbers);
+    let theme = theme.map(|name| if has_theme(name) { name } else { DEFAULT_THEME });
+    let lines: Vec<String> = match (theme, fence.language.as_deref()) {
+        (Some(theme), Some(language)) => highlight_lines(code, language, theme)?,
+        _ => None,
+    }
+    .unwrap_or_else(|| {
+        code.lines()
+            .map(|line| {
+                let mut escaped = String::new();
+                push_escaped(&mut escaped, line);
+                escaped
+            })
+            .collect()
+    });
+
+    let mut html = String::from("<pre class=\"code-block\"");
+    if let Some(style) = theme.map(pre_style).transpose()?.flatten() {
+        html.push_str(&format!(" style=\"{}\"", style));
+    }
+    html.push_str("><code");
+    if let Some(language) = &fence.language {
+        html.push_str(" class=\"language-");
+        push_escaped(&mut html, language);
+        html.push('"');
+    }
+    html.push('>');
+
+    for (i, line) in lines.iter().enumerate() {
+        let class = if fence.is_highlighted(i + 1) { "line highlighted" } else { "line" };
+        html.push_str(&format!("<span class=\"{}\">", class));
+        if line_numbers {
+            // Drawn from the attribute so that copying the code leaves the numbers out
+            html.push_str(&format!("<span class=\"line-number\" data-line=\"{}\"></span>", fence.start_line + i));
+        }
+        html.push_str(line);
+        html.push_str("</span>\n");
+    }
+
+    html.push_str("</code></pre>\n");
+    Ok(html)
+}
+
+/// Styles for line numbers and highlighted lines
+pub(crate) const CODE_BLOCK_CSS: &str = r#"
+pre.code-block .line { display: inline-block; min-width: 100%; }
+pre.code-block .line.highlighted { background-color: rgba(255, 213, 0, 0.25); }
+pre.code-block .line-number {
+    display: inline-block;
+    width: 3em;
+    margin-right: 1em;
+    text-align: right;
+    color: #999;
+    user-select: none;
+}
+pre.code-block .line-number::before { content: attr(data-line); }
+"#;
+
+/// Highlight code as lines coloured with 24-bit ANSI escapes, or `None` if the language is unknown
+#[cfg(feature = "highlight")]
+pub(crate) fn highlight_ansi(code: &str, language: &str, theme: &str) -> Result<Option<Vec<String>>> {
+    engine::highlight_ansi(code, language, theme)
+}
+
+#[cfg(not(feature = "highlight"))]
+pub(crate) fn highlight_ansi(_code: &str, _language: &str, _theme: &str) -> Result<Option<Vec<String>>> {
+    Ok(None)
+}
+
+#[cfg(feature = "highlight")]
+fn highlight_lines(code: &str, language: &str, theme: &str) -> Result<Option<Vec<String>>> {
+    engine::highlight(code, language, theme)
+}
+
+#[cfg(not(feature = "highlight"))]
+fn highlight_lines(_code: &str, _language: &str, _theme: &str) -> Result<Option<Vec<String>>> {
+    Ok(None)
+}
+
+#[cfg(feature = "highlight")]
+fn pre_style(theme: &str) -> Result<Option<String>> {
+    engine::pre_style(theme).map(Some)
+}
+
+#[cfg(not(feature = "highlight"))]
+fn pre_style(_theme: &str) -> Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(feature = "highlight")]
+mod engine {
+    use crate::{MdzError, Result};
+    use std::sync::OnceLock;
+    use syntect::easy::HighlightLines;
+    use syntect::highlighting::{Style, Theme, ThemeSet};
+    use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+    use syntect::parsing::SyntaxSet;
+    use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+    pub(super) fn syntaxes() -> &'static SyntaxSet {
+        static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+        SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+    }
+
+    pub(super) fn themes() -> &'static ThemeSet {
+        static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+        THEMES.get_or_init(ThemeSet::load_defaults)
+    }
+
+    fn theme(name: &str) -> Result<&'static Theme> {
+        themes()
+            .themes
+            .get(name)
+            .ok_or_else(|| MdzError::Render(format!("Unknown highlight theme: {}", name)))
+    }
+
+    /// Highlight code line by line, or `None` if the language is unknown
+    pub(super) fn highlight(code: &str, language: &str, theme_name: &str) -> Result<Option<Vec<String>>> {
+        highlight_with(code, language, theme_name, |regions| {
+            styled_line_to_highlighted_html(regions, IncludeBackground::No)
+                .map_err(|e| MdzError::Render(format!("Highlighting failed: {}", e)))
+        })
+    }
+
+    /// Highlight code line by line as terminal escapes ending in a reset
+    pub(super) fn highlight_ansi(code: &str, language: &str, theme_name: &str) -> Result<Option<Vec<String>>> {
+        highlight_with(code, language, theme_name, |regions| {
+            Ok(format!("{}\x1b[0m", as_24_bit_terminal_escaped(regions, false)))
+        })
+    }
+
+    fn highlight_with(
+        code: &str,
+        language: &str,
+        theme_name: &str,
+        format: impl Fn(&[(Style, &str)]) -> Result<String>,
+    ) -> Result<Option<Vec<String>>> {
+        let theme = theme(theme_name)?;
+        let syntaxes = syntaxes();
+        let syntax = match syntaxes
+            .find_syntax_by_token(language)
+            .or_else(|| syntaxes.find_syntax_by_name(language))
+        {
+            Some(syntax) => syntax,
+            None => return Ok(None),
+        };
+
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        let mut lines = Vec::new();
+        for line in LinesWithEndings::from(code) {
+            let regions = highlighter
+                .highlight_line(line, syntaxes)
+                .map_err(|e| MdzError::Render(format!("Highlighting failed: {}", e)))?;
+            let trimmed: Vec<_> = regions
+                .into_iter()
+                .map(|(style, text)| (style, text.trim_end_matches(['\n', '\r'])))
+                .collect();
+            lines.push(format(&trimmed)?);
+        }
+
+        Ok(Some(lines))
+    }
+
+    /// Background and foreground colors of a theme as an inline style
+    pub(super) fn pre_style(theme_name: &str) -> Result<String> {
+        let settings = &theme(theme_name)?.settings;
+        let mut style = String::new();
+        if let Some(bg) = settings.background {
+            style.push_str(&format!("background-color:#{:02x}{:02x}{:02x};", bg.r, bg.g, bg.b));
+        }
+        if let Some(fg) = settings.foreground {
+            style.push_str(&format!("color:#{:02x}{:02x}{:02x};", fg.r, fg.g, fg.b));
+        }
+        Ok(style)
+    }
+}
+
+fn split_attr(word: &str) -> (String, Option<String>) {
+    match word.split_once('=') {
+        Some((key, value)) => (key.to_string(), Some(value.trim_matches(['"', '\'']).to_string())),
+        None => (word.to_string(), None),
+    }
+}
+
+/// Split `key=value key="quoted value" flag` attribute lists
+fn tokenize_attrs(attrs: &str) -> Vec<(String, Option<String>)> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| Regex::new(r#"([^\s=]+)(?:=(?:"([^"]*)"|'([^']*)'|(\S+)))?"#).unwrap());
+    pattern
+        .captures_iter(attrs)
+        .map(|caps| {
+            let value = caps.get(2).or(caps.get(3)).or(caps.get(4)).map(|m| m.as_str().to_string());
+            (caps[1].to_string(), value)
+        })
+        .collect()
+}
+
+/// Parse line ranges such as `1,3-5` or `"2 4-6"`
+fn parse_line_ranges(spec: &str) -> Vec<RangeInclusive<usize>> {
+    spec.split([',', ' '])
+        .filter(|part| !part.is_empty())
+        .filter_map(|part| match part.split_once('-') {
+            Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+            None => part.trim().parse().ok().map(|n| n..=n),
+        })
+        .collect()
+}
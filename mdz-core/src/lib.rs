@@ -2,39 +2,78 @@ pub mod parser;
 pub mod renderer;
 pub mod validator;
 pub mod error;
+pub mod admonition;
+pub mod anchor;
+pub mod css;
+pub mod dialect;
+pub mod diagram;
+pub mod email;
+pub mod figure;
+pub mod highlight;
+pub mod math;
+pub mod pdf;
+pub mod plugin;
+pub mod sanitize;
+pub mod site;
+pub mod standalone;
+pub mod svg;
+pub mod terminal;
+pub mod text;
+mod docx;
+mod epub;
+mod markup;
+mod mhtml;
+mod mime;
+mod template;
 
+pub use dialect::MarkdownDialect;
+pub use email::{EmailAddresses, EmailAttachment, EmailMessage};
 pub use parser::MdzParser;
-pub use renderer::{MdzRenderer, RenderOptions};
-pub use validator::{MdzValidator, ValidationResult};
+pub use pdf::{Margins, PageSize, PdfOptions, PdfOutput};
+pub use plugin::{CodeBlock, RenderPlugin};
+pub use renderer::{MdzRenderer, RenderOptions, SvgMode};
+pub use sanitize::{HtmlAllowlist, HtmlPolicy};
+pub use site::StaticSite;
+pub use standalone::{EmbeddedImages, ExtractedImages};
+pub use terminal::TerminalOptions;
+pub use text::TextOptions;
+pub use validator::{
+    ArchiveEntry, ArchiveIndex, Diagnostics, MdzValidator, SpecFeature, ValidationProfile, ValidationResult,
+    ValidationRule,
+};
 pub use error::{MdzError, Result};
 
+/// Markdown parser whose events [`RenderPlugin`]s transform
+pub use pulldown_cmark;
+
 /// MDZ document structure
 #[derive(Debug, Clone)]
 pub struct MdzDocument {
     pub content: String,
     pub images: std::collections::HashMap<String, Vec<u8>>,
+    pub fonts: std::collections::HashMap<String, Vec<u8>>,
     pub css: Option<String>,
+    /// Print stylesheet (`css/print.css`)
+    pub print_css: Option<String>,
     pub metadata: DocumentMetadata,
 }
 
 /// Document metadata
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct DocumentMetadata {
     pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub modified_at: Option<chrono::DateTime<chrono::Utc>>,
-    pub version: String,
-}
-
-impl Default for DocumentMetadata {
-    fn default() -> Self {
-        Self {
-            title: None,
-            created_at: None,
-            modified_at: None,
-            version: "1.0".to_string(),
-        }
-    }
+    /// Spec version declared in metadata.json
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Markdown dialect the document is written in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dialect: Option<MarkdownDialect>,
 }
 
 impl MdzDocument {
@@ -43,7 +82,9 @@ impl MdzDocument {
         Self {
             content,
             images: std::collections::HashMap::new(),
+            fonts: std::collections::HashMap::new(),
             css: None,
+            print_css: None,
             metadata: DocumentMetadata::default(),
         }
     }
@@ -0,0 +1,169 @@
+/// HTML elements that never have content or an end tag
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/// Markup token
+pub(crate) enum Token<'a> {
+    Text(&'a str),
+    Tag(Tag<'a>),
+    /// Comment, CDATA, processing instruction or DOCTYPE
+    Other(&'a str),
+}
+
+/// Start or end tag
+pub(crate) struct Tag<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) attrs: Vec<Attribute<'a>>,
+    pub(crate) closing: bool,
+    pub(crate) self_closing: bool,
+    pub(crate) raw: &'a str,
+}
+
+/// Tag attribute with its unquoted value
+pub(crate) struct Attribute<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) value: &'a str,
+    pub(crate) raw: &'a str,
+}
+
+/// Split markup into text, tags and other constructs
+pub(crate) fn tokenize(input: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let rest = &input[pos..];
+        if !starts_markup(rest) {
+            let end = match rest.strip_prefix('<') {
+                Some(after) => after.find('<').map(|i| i + 1),
+                None => rest.find('<'),
+            };
+            let end = end.unwrap_or(rest.len());
+            tokens.push(Token::Text(&rest[..end]));
+            pos += end;
+            continue;
+        }
+
+        let terminator = if rest.starts_with("<!--") {
+            Some("-->")
+        } else if rest.starts_with("<![CDATA[") {
+            Some("]]>")
+        } else if rest.starts_with("<?") {
+            Some("?>")
+        } else {
+            None
+        };
+
+        let len = match terminator {
+            Some(end) => rest.find(end).map(|i| i + end.len()).unwrap_or(rest.len()),
+            None if rest.starts_with("<!") => doctype_len(rest),
+            None => tag_len(rest),
+        };
+
+        let raw = &rest[..len];
+        if terminator.is_some() || raw.starts_with("<!") {
+            tokens.push(Token::Other(raw));
+        } else {
+            tokens.push(Token::Tag(parse_tag(raw)));
+        }
+        pos += len;
+    }
+
+    tokens
+}
+
+/// Check if input starts with a tag, comment or declaration rather than a bare `<`
+fn starts_markup(input: &str) -> bool {
+    let mut chars = input.chars();
+    chars.next() == Some('<')
+        && matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '/' || c == '!' || c == '?')
+}
+
+impl Tag<'_> {
+    /// Check if the tag's closing `>` was present in the input
+    pub(crate) fn is_complete(&self) -> bool {
+        self.raw.ends_with('>')
+    }
+}
+
+/// Length of a tag, honoring quoted attribute values
+fn tag_len(input: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in input.char_indices().skip(1) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return i + 1,
+            _ => {}
+        }
+    }
+    input.len()
+}
+
+/// Length of a DOCTYPE, including an internal subset in brackets
+fn doctype_len(input: &str) -> usize {
+    let mut depth = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            '>' if depth <= 0 => return i + 1,
+            _ => {}
+        }
+    }
+    input.len()
+}
+
+fn parse_tag(raw: &str) -> Tag<'_> {
+    let inner = raw.trim_start_matches('<').trim_end_matches('>');
+    let closing = inner.starts_with('/');
+    let self_closing = inner.ends_with('/');
+    let inner = inner.trim_start_matches('/').trim_end_matches('/');
+
+    let name_end = inner
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(inner.len());
+    let name = &inner[..name_end];
+
+    let mut attrs = Vec::new();
+    let mut rest = &inner[name_end..];
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+
+        let name_len = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let attr_name = &rest[..name_len];
+        let mut after = rest[name_len..].trim_start();
+        let mut value = "";
+
+        if let Some(stripped) = after.strip_prefix('=') {
+            let stripped = stripped.trim_start();
+            match stripped.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let end = stripped[1..].find(q).map(|i| i + 1).unwrap_or(stripped.len() - 1);
+                    value = &stripped[1..end];
+                    after = &stripped[(end + 1).min(stripped.len())..];
+                }
+                _ => {
+                    let end = stripped.find(char::is_whitespace).unwrap_or(stripped.len());
+                    value = &stripped[..end];
+                    after = &stripped[end..];
+                }
+            }
+        }
+
+        let consumed = rest.len() - after.len();
+        if consumed == 0 {
+            break;
+        }
+        attrs.push(Attribute { name: attr_name, value, raw: rest[..consumed].trim_end() });
+        rest = after;
+    }
+
+    Tag { name, attrs, closing, self_closing, raw }
+}
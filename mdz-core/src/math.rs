@@ -0,0 +1,1363 @@
+use crate::template::push_escaped;
+use crate::{MdzError, Result};
+use pulldown_cmark::{CowStr, Event, Tag};
+
+/// Marks the start of a math placeholder in preprocessed Markdown
+const PLACEHOLDER_START: char = '\u{E000}';
+/// Marks the end of a math placeholder in preprocessed Markdown
+const PLACEHOLDER_END: char = '\u{E001}';
+
+/// `$...$` or `$$...$$` math found in Markdown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MathSpan {
+    /// LaTeX between the delimiters
+    pub latex: String,
+    /// `$$...$$` display math
+    pub display: bool,
+    /// Original text including delimiters
+    pub source: String,
+}
+
+impl MathSpan {
+    /// Convert to MathML
+    pub fn to_mathml(&self) -> Result<String> {
+        latex_to_mathml(&self.latex, self.display)
+    }
+}
+
+/// Find all math in Markdown, skipping code spans and fenced code blocks
+pub fn find_math(markdown: &str) -> Vec<MathSpan> {
+    extract(markdown).1
+}
+
+/// Replace math with placeholders so Markdown parsing leaves the LaTeX untouched
+pub(crate) fn extract(markdown: &str) -> (String, Vec<MathSpan>) {
+    let mut output = String::with_capacity(markdown.len());
+    let mut spans = Vec::new();
+    let mut fenced = fenced_ranges(markdown);
+    fenced.reverse();
+    let mut pos = 0;
+
+    while pos < markdown.len() {
+        if let Some(range) = fenced.last() {
+            if pos >= range.start {
+                output.push_str(&markdown[range.clone()]);
+                pos = range.end;
+                fenced.pop();
+                continue;
+            }
+        }
+        let limit = fenced.last().map(|r| r.start).unwrap_or(markdown.len());
+        let rest = &markdown[pos..limit];
+        let c = rest.chars().next().unwrap();
+
+        match c {
+            '\\' => {
+                let len = 1 + rest[1..].chars().next().map(char::len_utf8).unwrap_or(0);
+                output.push_str(&rest[..len]);
+                pos += len;
+            }
+            '`' => {
+                let ticks = rest.len() - rest.trim_start_matches('`').len();
+                let len = code_span_len(rest, ticks).unwrap_or(ticks);
+                output.push_str(&rest[..len]);
+                pos += len;
+            }
+            '$' => match math_span_len(rest) {
+                Some((len, display)) => {
+                    let delimiter = if display { 2 } else { 1 };
+                    spans.push(MathSpan {
+                        latex: rest[delimiter..len - delimiter].trim().to_string(),
+                        display,
+                        source: rest[..len].to_string(),
+                    });
+                    output.push(PLACEHOLDER_START);
+                    output.push_str(&(spans.len() - 1).to_string());
+                    output.push(PLACEHOLDER_END);
+                    pos += len;
+                }
+                None => {
+                    let len = rest.len() - rest.trim_start_matches('$').len();
+                    output.push_str(&rest[..len]);
+                    pos += len;
+                }
+            },
+            _ => {
+                output.push(c);
+                pos += c.len_utf8();
+            }
+        }
+    }
+
+    (output, spans)
+}
+
+/// Opening fence marker (three or more backticks or tildes) at the start of a line
+fn fence_marker(line: &str) -> Option<String> {
+    let first = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let count = line.len() - line.trim_start_matches(first).len();
+    (count >= 3).then(|| first.to_string().repeat(count))
+}
+
+/// Byte ranges of fenced code blocks, fences included
+fn fenced_ranges(text: &str) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut open: Option<(usize, String)> = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+        match &open {
+            Some((start, marker)) => {
+                let closing = trimmed.trim_end();
+                if indent < 4
+                    && closing.starts_with(marker.as_str())
+                    && closing.trim_start_matches(marker.chars().next().unwrap()).is_empty()
+                {
+                    ranges.push(*start..offset + line.len());
+                    open = None;
+                }
+            }
+            None if indent < 4 => {
+                if let Some(marker) = fence_marker(trimmed) {
+                    open = Some((offset, marker));
+                }
+            }
+            None => {}
+        }
+        offset += line.len();
+    }
+
+    if let Some((start, _)) = open {
+        ranges.push(start..text.len());
+    }
+    ranges
+}
+
+/// Length of a code span opened by `ticks` backticks, if it is closed
+fn code_span_len(text: &str, ticks: usize) -> Option<usize> {
+    let mut pos = ticks;
+    while let Some(offset) = text[pos..].find('`') {
+        let start = pos + offset;
+        let run = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if run == ticks {
+            return Some(start + run);
+        }
+        pos = start + run;
+    }
+    None
+}
+
+/// Length of a math span starting with `$`, and whether it is display math
+///
+/// Inline math follows Pandoc's rules: the opening `$` must be followed by a
+/// non-space character and the closing `$` preceded by one and not followed by
+/// a digit, so prices such as "$5 and $10" stay text. Math never crosses a
+/// blank line.
+fn math_span_len(text: &str) -> Option<(usize, bool)> {
+    let display = text.starts_with("$$");
+    let open = if display { 2 } else { 1 };
+    let body = &text[open..];
+
+    if !display && body.chars().next().is_none_or(char::is_whitespace) {
+        return None;
+    }
+
+    let mut chars = body.char_indices();
+    let mut previous = ' ';
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+                previous = 'x';
+                continue;
+            }
+            '\n' if body[i + 1..].trim_start_matches([' ', '\t']).starts_with('\n') => return None,
+            '$' if display => {
+                if body[i..].starts_with("$$") && !body[..i].trim().is_empty() {
+                    return Some((open + i + 2, true));
+                }
+                return None;
+            }
+            '$' => {
+                let next = body[i + 1..].chars().next();
+                if !previous.is_whitespace() && !next.is_some_and(|n| n.is_ascii_digit()) && i > 0 {
+                    return Some((open + i + 1, false));
+                }
+                return None;
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+    None
+}
+
+/// Restore the original source text of placeholders in `text`
+pub(crate) fn restore(text: &str, spans: &[MathSpan]) -> String {
+    let mut output = String::with_capacity(text.len());
+    for_each_part(text, |part| match part {
+        Part::Text(t) => output.push_str(t),
+        Part::Math(index) => {
+            if let Some(span) = spans.get(index) {
+                output.push_str(&span.source);
+            }
+        }
+    });
+    output
+}
+
+enum Part<'t> {
+    Text(&'t str),
+    Math(usize),
+}
+
+/// Split text into literal parts and placeholder indices
+fn for_each_part<'t>(mut text: &'t str, mut f: impl FnMut(Part<'t>)) {
+    while let Some(start) = text.find(PLACEHOLDER_START) {
+        let after = &text[start + PLACEHOLDER_START.len_utf8()..];
+        let Some(end) = after.find(PLACEHOLDER_END) else { break };
+        let Ok(index) = after[..end].parse() else { break };
+        if start > 0 {
+            f(Part::Text(&text[..start]));
+        }
+        f(Part::Math(index));
+        text = &after[end + PLACEHOLDER_END.len_utf8()..];
+    }
+    if !text.is_empty() {
+        f(Part::Text(text));
+    }
+}
+
+/// Turns placeholders in a pulldown-cmark event stream back into math
+///
+/// Text placeholders become rendered math; placeholders that ended up in code,
+/// raw HTML or link targets are restored to their original source.
+pub(crate) struct MathExpander<'s, F> {
+    spans: &'s [MathSpan],
+    render: F,
+    in_code_block: bool,
+}
+
+impl<'s, F> MathExpander<'s, F>
+where
+    F: FnMut(&MathSpan) -> String,
+{
+    pub(crate) fn new(spans: &'s [MathSpan], render: F) -> Self {
+        Self { spans, render, in_code_block: false }
+    }
+
+    /// Transform a single event into zero or more events
+    pub(crate) fn process<'a>(&mut self, event: Event<'a>) -> Vec<Event<'a>> {
+        if self.spans.is_empty() {
+            return vec![event];
+        }
+
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                self.in_code_block = true;
+                vec![Event::Start(Tag::CodeBlock(kind))]
+            }
+            Event::End(Tag::CodeBlock(kind)) => {
+                self.in_code_block = false;
+                vec![Event::End(Tag::CodeBlock(kind))]
+            }
+            Event::Text(text) if self.in_code_block => vec![Event::Text(self.restore(text))],
+            Event::Text(text) if text.contains(PLACEHOLDER_START) => {
+                let mut events = Vec::new();
+                for_each_part(&text, |part| match part {
+                    Part::Text(t) => events.push(Event::Text(t.to_string().into())),
+                    Part::Math(index) => {
+                        if let Some(span) = self.spans.get(index) {
+                            events.push(Event::Html((self.render)(span).into()));
+                        }
+                    }
+                });
+                events
+            }
+            Event::Code(text) => vec![Event::Code(self.restore(text))],
+            Event::Html(html) => vec![Event::Html(self.restore(html))],
+            Event::Start(Tag::Link(kind, dest, title)) => {
+                vec![Event::Start(Tag::Link(kind, self.restore(dest), self.restore(title)))]
+            }
+            Event::Start(Tag::Image(kind, dest, title)) => {
+                vec![Event::Start(Tag::Image(kind, self.restore(dest), self.restore(title)))]
+            }
+            other => vec![other],
+        }
+    }
+
+    fn restore<'a>(&self, text: CowStr<'a>) -> CowStr<'a> {
+        if text.contains(PLACEHOLDER_START) {
+            restore(&text, self.spans).into()
+        } else {
+            text
+        }
+    }
+}
+
+/// Render a math span to MathML, or its escaped source marked as an error
+pub(crate) fn render_span(span: &MathSpan) -> String {
+    span.to_mathml().unwrap_or_else(|e| {
+        let mut html = String::from("<code class=\"math-error\" title=\"");
+        push_escaped(&mut html, &e.to_string());
+        html.push_str("\">");
+        push_escaped(&mut html, &span.source);
+        html.push_str("</code>");
+        html
+    })
+}
+
+/// Styles for display math and math that failed to parse
+pub(crate) const MATH_CSS: &str = r#"
+math[display="block"] { margin: 1em 0; overflow-x: auto; }
+code.math-error { color: #c0392b; }
+"#;
+
+/// Convert LaTeX math to a MathML `<math>` element
+pub fn latex_to_mathml(latex: &str, display: bool) -> Result<String> {
+    let mut parser = LatexParser::new(latex, display);
+    let rows = parser.parse_rows(None)?;
+    let body = if rows.len() == 1 {
+        mrow(&rows[0].concat())
+    } else {
+        table(&rows, None)
+    };
+
+    let mut html = String::from("<math xmlns=\"http://www.w3.org/1998/Math/MathML\"");
+    if display {
+        html.push_str(" display=\"block\"");
+    }
+    html.push_str("><semantics>");
+    html.push_str(&body);
+    html.push_str("<annotation encoding=\"application/x-tex\">");
+    push_escaped(&mut html, latex);
+    html.push_str("</annotation></semantics></math>");
+    Ok(html)
+}
+
+/// Parsed element with the information needed to attach scripts
+struct Node {
+    xml: String,
+    /// Scripts go above and below in display style (`\sum`, `\lim`)
+    limits: bool,
+    /// Already carries a sub- or superscript
+    scripted: bool,
+}
+
+impl Node {
+    fn new(xml: String) -> Self {
+        Self { xml, limits: false, scripted: false }
+    }
+}
+
+/// Why a sequence of nodes ended
+enum Stop {
+    Eof,
+    CloseBrace,
+    CloseBracket,
+    Ampersand,
+    Newline,
+    Right(String),
+    End(String),
+}
+
+struct LatexParser<'a> {
+    src: &'a str,
+    pos: usize,
+    display: bool,
+    /// Letter style from `\mathbb`, `\mathbf` and friends
+    variant: Option<&'static str>,
+}
+
+impl<'a> LatexParser<'a> {
+    fn new(src: &'a str, display: bool) -> Self {
+        Self { src, pos: 0, display, variant: None }
+    }
+
+    fn error(message: impl Into<String>) -> MdzError {
+        MdzError::Parse(message.into())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('%') => {
+                    let rest = &self.src[self.pos..];
+                    self.pos += rest.find('\n').unwrap_or(rest.len());
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Read a command name after `\`: a run of letters or a single other character
+    fn command_name(&mut self) -> Result<String> {
+        let rest = &self.src[self.pos..];
+        let letters = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_alphabetic()).len();
+        if letters > 0 {
+            self.pos += letters;
+            return Ok(rest[..letters].to_string());
+        }
+        self.bump()
+            .map(String::from)
+            .ok_or_else(|| Self::error("Trailing backslash"))
+    }
+
+    /// Parse cells and rows until the end of input or `\end{env}`
+    fn parse_rows(&mut self, env: Option<&str>) -> Result<Vec<Vec<String>>> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+
+        loop {
+            let (nodes, stop) = self.parse_sequence(false)?;
+            row.push(nodes.into_iter().map(|n| n.xml).collect::<String>());
+            match stop {
+                Stop::Ampersand if env.is_some() => {}
+                Stop::Ampersand => return Err(Self::error("'&' outside an environment")),
+                Stop::Newline => {
+                    // Optional spacing argument: \\[2pt]
+                    if self.src[self.pos..].trim_start().starts_with('[') {
+                        self.skip_whitespace();
+                        self.raw_until(']')?;
+                    }
+                    rows.push(std::mem::take(&mut row));
+                }
+                Stop::Eof => match env {
+                    None => break,
+                    Some(name) => return Err(Self::error(format!("Missing \\end{{{}}}", name))),
+                },
+                Stop::End(name) => match env {
+                    Some(expected) if expected == name => break,
+                    Some(expected) => {
+                        return Err(Self::error(format!("\\end{{{}}} does not match \\begin{{{}}}", name, expected)))
+                    }
+                    None => return Err(Self::error(format!("Unexpected \\end{{{}}}", name))),
+                },
+                Stop::CloseBrace => return Err(Self::error("Unexpected '}'")),
+                Stop::CloseBracket => unreachable!("brackets only stop optional arguments"),
+                Stop::Right(_) => return Err(Self::error("\\right without matching \\left")),
+            }
+        }
+
+        // A trailing `\\` does not start a new row
+        if !(row.len() == 1 && row[0].is_empty() && !rows.is_empty()) {
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    /// Parse nodes until a closing token, which is consumed and returned
+    fn parse_sequence(&mut self, in_brackets: bool) -> Result<(Vec<Node>, Stop)> {
+        let mut nodes: Vec<Node> = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            let Some(c) = self.peek() else {
+                return Ok((nodes, Stop::Eof));
+            };
+
+            match c {
+                '}' => {
+                    self.bump();
+                    return Ok((nodes, Stop::CloseBrace));
+                }
+                ']' if in_brackets => {
+                    self.bump();
+                    return Ok((nodes, Stop::CloseBracket));
+                }
+                '&' => {
+                    self.bump();
+                    return Ok((nodes, Stop::Ampersand));
+                }
+                '^' | '_' => {
+                    let base = nodes.pop().unwrap_or_else(|| Node::new("<mrow></mrow>".to_string()));
+                    nodes.push(self.parse_scripts(base)?);
+                }
+                '\'' => {
+                    let mut primes = String::new();
+                    while self.peek() == Some('\'') {
+                        self.bump();
+                        primes.push('′');
+                    }
+                    let base = nodes.pop().unwrap_or_else(|| Node::new("<mrow></mrow>".to_string()));
+                    nodes.push(Node {
+                        xml: format!("<msup>{}{}</msup>", base.xml, mo(&primes)),
+                        limits: false,
+                        scripted: true,
+                    });
+                }
+                '\\' => {
+                    self.bump();
+                    let name = self.command_name()?;
+                    match name.as_str() {
+                        "\\" | "cr" => return Ok((nodes, Stop::Newline)),
+                        "right" => {
+                            let delimiter = self.delimiter("\\right")?;
+                            return Ok((nodes, Stop::Right(delimiter)));
+                        }
+                        "end" => {
+                            let env = self.braced_raw("\\end")?;
+                            return Ok((nodes, Stop::End(env)));
+                        }
+                        "limits" | "nolimits" => {
+                            if let Some(last) = nodes.last_mut() {
+                                last.limits = name == "limits";
+                            }
+                        }
+                        _ => {
+                            if let Some(node) = self.command(&name)? {
+                                nodes.push(node);
+                            }
+                        }
+                    }
+                }
+                _ => nodes.push(self.parse_char(true)?),
+            }
+        }
+    }
+
+    /// Parse `^` and `_` scripts attached to `base`
+    fn parse_scripts(&mut self, base: Node) -> Result<Node> {
+        if base.scripted {
+            return Err(Self::error("Double subscript or superscript; use braces to group"));
+        }
+
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            self.skip_whitespace();
+            let slot = match self.peek() {
+                Some('_') => &mut sub,
+                Some('^') => &mut sup,
+                _ => break,
+            };
+            if slot.is_some() {
+                return Err(Self::error("Double subscript or superscript; use braces to group"));
+            }
+            self.bump();
+            *slot = Some(self.parse_argument()?);
+        }
+
+        let under_over = base.limits && self.display;
+        let xml = match (sub, sup) {
+            (Some(sub), Some(sup)) if under_over => format!("<munderover>{}{}{}</munderover>", base.xml, sub, sup),
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", base.xml, sub, sup),
+            (Some(sub), None) if under_over => format!("<munder>{}{}</munder>", base.xml, sub),
+            (Some(sub), None) => format!("<msub>{}{}</msub>", base.xml, sub),
+            (None, Some(sup)) if under_over => format!("<mover>{}{}</mover>", base.xml, sup),
+            (None, Some(sup)) => format!("<msup>{}{}</msup>", base.xml, sup),
+            (None, None) => base.xml,
+        };
+        Ok(Node { xml, limits: false, scripted: true })
+    }
+
+    /// Parse a single argument: a braced group, a command or one character
+    fn parse_argument(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(Self::error("Missing argument")),
+            Some('{') => {
+                self.bump();
+                self.parse_group()
+            }
+            Some('\\') => {
+                self.bump();
+                let name = self.command_name()?;
+                match self.command(&name)? {
+                    Some(node) => Ok(node.xml),
+                    None => Err(Self::error(format!("\\{} cannot be used as an argument", name))),
+                }
+            }
+            Some('}' | '&' | '^' | '_') => Err(Self::error("Missing argument")),
+            Some(_) => Ok(self.parse_char(false)?.xml),
+        }
+    }
+
+    /// Parse the rest of a braced group whose `{` was consumed
+    fn parse_group(&mut self) -> Result<String> {
+        let (nodes, stop) = self.parse_sequence(false)?;
+        match stop {
+            Stop::CloseBrace => Ok(mrow(&nodes.into_iter().map(|n| n.xml).collect::<String>())),
+            Stop::Eof => Err(Self::error("Missing '}'")),
+            Stop::Right(_) => Err(Self::error("\\right without matching \\left")),
+            Stop::End(name) => Err(Self::error(format!("Unexpected \\end{{{}}}", name))),
+            Stop::Ampersand | Stop::Newline => Err(Self::error("Unexpected '&' or '\\\\' inside braces")),
+            Stop::CloseBracket => unreachable!("brackets only stop optional arguments"),
+        }
+    }
+
+    /// Parse a character: numbers, identifiers and operators
+    fn parse_char(&mut self, allow_number: bool) -> Result<Node> {
+        let c = self.bump().ok_or_else(|| Self::error("Unexpected end of input"))?;
+
+        if c.is_ascii_digit() {
+            let mut number = c.to_string();
+            if allow_number {
+                while let Some(next) = self.peek() {
+                    let decimal = next == '.'
+                        && self.src[self.pos + 1..].chars().next().is_some_and(|d| d.is_ascii_digit());
+                    if next.is_ascii_digit() || decimal {
+                        number.push(next);
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+            }
+            let styled: String = number.chars().map(|d| styled_char(d, self.variant)).collect();
+            return Ok(Node::new(format!("<mn>{}</mn>", escape(&styled))));
+        }
+
+        let xml = match c {
+            'a'..='z' | 'A'..='Z' => self.identifier(c),
+            '-' => mo("−"),
+            '*' => mo("∗"),
+            '~' => "<mtext>\u{a0}</mtext>".to_string(),
+            '+' | '=' | '<' | '>' | '/' | '(' | ')' | '[' | ']' | '|' | ',' | ';' | ':' | '!' | '?' | '.' | '@' => {
+                mo(&c.to_string())
+            }
+            '{' => {
+                return Ok(Node::new(self.parse_group()?));
+            }
+            '#' | '$' => return Err(Self::error(format!("Unexpected '{}'", c))),
+            c if c.is_alphabetic() => mi(&c.to_string()),
+            c => mo(&c.to_string()),
+        };
+        Ok(Node::new(xml))
+    }
+
+    /// Single-letter identifier in the current letter style
+    fn identifier(&self, c: char) -> String {
+        match self.variant {
+            Some("normal") => format!("<mi mathvariant=\"normal\">{}</mi>", c),
+            variant => mi(&styled_char(c, variant).to_string()),
+        }
+    }
+
+    /// Expand a command, returning `None` for commands that produce no output
+    fn command(&mut self, name: &str) -> Result<Option<Node>> {
+        if let Some(symbol) = greek(name) {
+            let xml = if name.starts_with(|c: char| c.is_ascii_uppercase()) {
+                format!("<mi mathvariant=\"normal\">{}</mi>", symbol)
+            } else {
+                mi(symbol)
+            };
+            return Ok(Some(Node::new(xml)));
+        }
+        if let Some(symbol) = identifier_symbol(name) {
+            return Ok(Some(Node::new(mi(symbol))));
+        }
+        if let Some(symbol) = operator(name) {
+            return Ok(Some(Node::new(mo(symbol))));
+        }
+        if let Some(symbol) = large_operator(name) {
+            let limits = !matches!(name, "int" | "iint" | "iiint" | "oint");
+            return Ok(Some(Node { xml: mo(symbol), limits, scripted: false }));
+        }
+        if is_function(name) {
+            let limits = matches!(
+                name,
+                "lim" | "liminf" | "limsup" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr" | "argmax" | "argmin"
+            );
+            return Ok(Some(Node { xml: mi(name), limits, scripted: false }));
+        }
+        if let Some(width) = space(name) {
+            return Ok(Some(Node::new(format!("<mspace width=\"{}\"/>", width))));
+        }
+        if let Some((accent, stretchy)) = accent(name) {
+            let base = self.parse_argument()?;
+            let stretch = if stretchy { " stretchy=\"true\"" } else { "" };
+            return Ok(Some(Node::new(format!(
+                "<mover accent=\"true\">{}<mo{}>{}</mo></mover>",
+                base, stretch, accent
+            ))));
+        }
+        if let Some(variant) = math_variant(name) {
+            let previous = self.variant.replace(variant);
+            let argument = self.parse_argument();
+            self.variant = previous;
+            return Ok(Some(Node::new(argument?)));
+        }
+
+        let xml = match name {
+            "{" => mo("{"),
+            "}" => mo("}"),
+            "|" => mo("‖"),
+            "%" | "$" | "&" | "#" | "_" => mo(name),
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_argument()?;
+                let denominator = self.parse_argument()?;
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let n = self.parse_argument()?;
+                let k = self.parse_argument()?;
+                format!("<mrow>{}<mfrac linethickness=\"0\">{}{}</mfrac>{}</mrow>", mo("("), n, k, mo(")"))
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    self.bump();
+                    let (nodes, stop) = self.parse_sequence(true)?;
+                    if !matches!(stop, Stop::CloseBracket) {
+                        return Err(Self::error("Missing ']' in \\sqrt"));
+                    }
+                    let index = mrow(&nodes.into_iter().map(|n| n.xml).collect::<String>());
+                    let radicand = self.parse_argument()?;
+                    format!("<mroot>{}{}</mroot>", radicand, index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.parse_argument()?)
+                }
+            }
+            "text" | "textrm" | "textnormal" | "mbox" | "textit" | "textbf" | "texttt" => {
+                let text = unescape_text(&self.braced_raw(&format!("\\{}", name))?);
+                let variant = match name {
+                    "textit" => " mathvariant=\"italic\"",
+                    "textbf" => " mathvariant=\"bold\"",
+                    "texttt" => " mathvariant=\"monospace\"",
+                    _ => "",
+                };
+                let text = preserve_edge_spaces(&text);
+                format!("<mtext{}>{}</mtext>", variant, escape(&text))
+            }
+            "operatorname" => {
+                let text = self.braced_raw("\\operatorname")?;
+                mi(&unescape_text(&text))
+            }
+            "left" => {
+                let open = self.delimiter("\\left")?;
+                let (nodes, stop) = self.parse_sequence(false)?;
+                let Stop::Right(close) = stop else {
+                    return Err(Self::error("\\left without matching \\right"));
+                };
+                let inner: String = nodes.into_iter().map(|n| n.xml).collect();
+                format!("<mrow>{}{}{}</mrow>", fence(&open), inner, fence(&close))
+            }
+            "middle" => fence(&self.delimiter("\\middle")?),
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "Bigl" | "biggl" | "Biggl" | "bigr" | "Bigr" | "biggr"
+            | "Biggr" | "bigm" | "Bigm" | "biggm" | "Biggm" => {
+                let size = match name.trim_end_matches(['l', 'r', 'm']) {
+                    "big" => "1.2em",
+                    "Big" => "1.8em",
+                    "bigg" => "2.4em",
+                    _ => "3em",
+                };
+                let delimiter = self.delimiter(&format!("\\{}", name))?;
+                format!(
+                    "<mo stretchy=\"true\" symmetric=\"true\" minsize=\"{}\" maxsize=\"{}\">{}</mo>",
+                    size,
+                    size,
+                    escape(&delimiter)
+                )
+            }
+            "overset" | "stackrel" => {
+                let over = self.parse_argument()?;
+                let base = self.parse_argument()?;
+                format!("<mover>{}{}</mover>", base, over)
+            }
+            "underset" => {
+                let under = self.parse_argument()?;
+                let base = self.parse_argument()?;
+                format!("<munder>{}{}</munder>", base, under)
+            }
+            "underline" => {
+                let base = self.parse_argument()?;
+                format!("<munder accentunder=\"true\">{}<mo stretchy=\"true\">_</mo></munder>", base)
+            }
+            "underbrace" => {
+                let base = self.parse_argument()?;
+                return Ok(Some(Node {
+                    xml: format!("<munder accentunder=\"true\">{}<mo stretchy=\"true\">⏟</mo></munder>", base),
+                    limits: true,
+                    scripted: false,
+                }));
+            }
+            "overbrace" => {
+                let base = self.parse_argument()?;
+                return Ok(Some(Node {
+                    xml: format!("<mover accent=\"true\">{}<mo stretchy=\"true\">⏞</mo></mover>", base),
+                    limits: true,
+                    scripted: false,
+                }));
+            }
+            "not" => {
+                let negated = self.parse_argument()?;
+                match negated.as_str() {
+                    "<mo>=</mo>" => mo("≠"),
+                    "<mo>∈</mo>" => mo("∉"),
+                    "<mo>⊂</mo>" => mo("⊄"),
+                    "<mo>⊆</mo>" => mo("⊈"),
+                    "<mo>≡</mo>" => mo("≢"),
+                    other => match other.strip_prefix("<mo>").and_then(|s| s.strip_suffix("</mo>")) {
+                        Some(symbol) => format!("<mo>{}\u{338}</mo>", symbol),
+                        None => format!("<menclose notation=\"updiagonalstrike\">{}</menclose>", other),
+                    },
+                }
+            }
+            "bmod" => mo("mod"),
+            "pmod" => {
+                let argument = self.parse_argument()?;
+                format!(
+                    "<mrow><mspace width=\"1em\"/>{}<mi>mod</mi><mspace width=\"0.2778em\"/>{}{}</mrow>",
+                    mo("("),
+                    argument,
+                    mo(")")
+                )
+            }
+            "begin" => self.environment()?,
+            "displaystyle" | "textstyle" | "scriptstyle" | "nonumber" | "notag" | "relax" => return Ok(None),
+            _ => return Err(Self::error(format!("Unknown command \\{}", name))),
+        };
+
+        Ok(Some(Node::new(xml)))
+    }
+
+    /// Parse `\begin{env} ... \end{env}` after `\begin`
+    fn environment(&mut self) -> Result<String> {
+        let name = self.braced_raw("\\begin")?;
+        let (open, close, align) = match name.as_str() {
+            "matrix" | "smallmatrix" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("‖", "‖", None),
+            "cases" => ("{", "", Some("left left")),
+            "aligned" | "align" | "align*" | "split" | "alignat" | "alignat*" => ("", "", Some("right left")),
+            "gathered" | "gather" | "gather*" => ("", "", None),
+            "array" => {
+                self.skip_whitespace();
+                self.braced_raw("\\begin{array}")?;
+                ("", "", None)
+            }
+            _ => return Err(Self::error(format!("Unknown environment '{}'", name))),
+        };
+        if name.starts_with("alignat") {
+            self.braced_raw("\\begin{alignat}")?;
+        }
+
+        let rows = self.parse_rows(Some(&name))?;
+        let table = table(&rows, align);
+        if open.is_empty() && close.is_empty() {
+            Ok(table)
+        } else {
+            Ok(format!("<mrow>{}{}{}</mrow>", fence(open), table, fence(close)))
+        }
+    }
+
+    /// Read a delimiter after `\left`, `\right`, `\big` and friends
+    fn delimiter(&mut self, command: &str) -> Result<String> {
+        self.skip_whitespace();
+        let delimiter = match self.bump() {
+            Some('\\') => {
+                let name = self.command_name()?;
+                match name.as_str() {
+                    "{" | "lbrace" => "{",
+                    "}" | "rbrace" => "}",
+                    "|" | "Vert" => "‖",
+                    "vert" => "|",
+                    "langle" => "⟨",
+                    "rangle" => "⟩",
+                    "lfloor" => "⌊",
+                    "rfloor" => "⌋",
+                    "lceil" => "⌈",
+                    "rceil" => "⌉",
+                    "uparrow" => "↑",
+                    "downarrow" => "↓",
+                    "backslash" => "\\",
+                    _ => return Err(Self::error(format!("Invalid delimiter \\{} after {}", name, command))),
+                }
+                .to_string()
+            }
+            Some('.') => String::new(),
+            Some(c @ ('(' | ')' | '[' | ']' | '|' | '/' | '<' | '>')) => match c {
+                '<' => "⟨".to_string(),
+                '>' => "⟩".to_string(),
+                c => c.to_string(),
+            },
+            Some(c) => return Err(Self::error(format!("Invalid delimiter '{}' after {}", c, command))),
+            None => return Err(Self::error(format!("Missing delimiter after {}", command))),
+        };
+        Ok(delimiter)
+    }
+
+    /// Read the raw content of a braced argument, keeping nested braces
+    fn braced_raw(&mut self, command: &str) -> Result<String> {
+        self.skip_whitespace();
+        if self.bump() != Some('{') {
+            return Err(Self::error(format!("Missing '{{' after {}", command)));
+        }
+        let start = self.pos;
+        let mut depth = 1;
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    self.bump();
+                }
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(self.src[start..self.pos - 1].to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(Self::error(format!("Missing '}}' after {}", command)))
+    }
+
+    /// Skip raw text up to and including `end`
+    fn raw_until(&mut self, end: char) -> Result<()> {
+        while let Some(c) = self.bump() {
+            if c == end {
+                return Ok(());
+            }
+        }
+        Err(Self::error(format!("Missing '{}'", end)))
+    }
+}
+
+fn mrow(content: &str) -> String {
+    format!("<mrow>{}</mrow>", content)
+}
+
+fn mi(text: &str) -> String {
+    format!("<mi>{}</mi>", escape(text))
+}
+
+fn mo(text: &str) -> String {
+    format!("<mo>{}</mo>", escape(text))
+}
+
+/// Stretchy fence, or nothing for the `.` null delimiter
+fn fence(delimiter: &str) -> String {
+    if delimiter.is_empty() {
+        String::new()
+    } else {
+        format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape(delimiter))
+    }
+}
+
+fn table(rows: &[Vec<String>], column_align: Option<&str>) -> String {
+    let mut xml = String::from("<mtable");
+    if let Some(align) = column_align {
+        xml.push_str(&format!(" columnalign=\"{}\"", align));
+    }
+    xml.push('>');
+    for row in rows {
+        xml.push_str("<mtr>");
+        for cell in row {
+            xml.push_str(&format!("<mtd>{}</mtd>", mrow(cell)));
+        }
+        xml.push_str("</mtr>");
+    }
+    xml.push_str("</mtable>");
+    xml
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    push_escaped(&mut escaped, text);
+    escaped
+}
+
+/// Remove backslash escapes from `\text{...}` content
+fn unescape_text(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    output.push(if next == ' ' { '\u{a0}' } else { next });
+                }
+            }
+            '~' => output.push('\u{a0}'),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// Keep leading and trailing spaces of `\text{ and }` visible
+fn preserve_edge_spaces(text: &str) -> String {
+    let leading = text.len() - text.trim_start_matches(' ').len();
+    let trailing = text.len() - text.trim_end_matches(' ').len();
+    if leading == text.len() {
+        return "\u{a0}".repeat(leading);
+    }
+    format!(
+        "{}{}{}",
+        "\u{a0}".repeat(leading),
+        &text[leading..text.len() - trailing],
+        "\u{a0}".repeat(trailing)
+    )
+}
+
+/// Map a letter or digit to its Unicode mathematical alphanumeric form
+fn styled_char(c: char, variant: Option<&str>) -> char {
+    let exception = match (variant, c) {
+        (Some("double-struck"), 'C') => Some('ℂ'),
+        (Some("double-struck"), 'H') => Some('ℍ'),
+        (Some("double-struck"), 'N') => Some('ℕ'),
+        (Some("double-struck"), 'P') => Some('ℙ'),
+        (Some("double-struck"), 'Q') => Some('ℚ'),
+        (Some("double-struck"), 'R') => Some('ℝ'),
+        (Some("double-struck"), 'Z') => Some('ℤ'),
+        (Some("script"), 'B') => Some('ℬ'),
+        (Some("script"), 'E') => Some('ℰ'),
+        (Some("script"), 'F') => Some('ℱ'),
+        (Some("script"), 'H') => Some('ℋ'),
+        (Some("script"), 'I') => Some('ℐ'),
+        (Some("script"), 'L') => Some('ℒ'),
+        (Some("script"), 'M') => Some('ℳ'),
+        (Some("script"), 'R') => Some('ℛ'),
+        (Some("script"), 'e') => Some('ℯ'),
+        (Some("script"), 'g') => Some('ℊ'),
+        (Some("script"), 'o') => Some('ℴ'),
+        (Some("fraktur"), 'C') => Some('ℭ'),
+        (Some("fraktur"), 'H') => Some('ℌ'),
+        (Some("fraktur"), 'I') => Some('ℑ'),
+        (Some("fraktur"), 'R') => Some('ℜ'),
+        (Some("fraktur"), 'Z') => Some('ℨ'),
+        _ => None,
+    };
+    if let Some(symbol) = exception {
+        return symbol;
+    }
+
+    // Start of the uppercase, lowercase and digit blocks for each style
+    let (upper, lower, digit) = match variant {
+        Some("bold") => (0x1D400, 0x1D41A, Some(0x1D7CE)),
+        Some("double-struck") => (0x1D538, 0x1D552, Some(0x1D7D8)),
+        Some("script") => (0x1D49C, 0x1D4B6, None),
+        Some("fraktur") => (0x1D504, 0x1D51E, None),
+        Some("sans-serif") => (0x1D5A0, 0x1D5BA, Some(0x1D7E2)),
+        Some("monospace") => (0x1D670, 0x1D68A, Some(0x1D7F6)),
+        _ => return c,
+    };
+    let code = match c {
+        'A'..='Z' => upper + (c as u32 - 'A' as u32),
+        'a'..='z' => lower + (c as u32 - 'a' as u32),
+        '0'..='9' => match digit {
+            Some(start) => start + (c as u32 - '0' as u32),
+            None => return c,
+        },
+        _ => return c,
+    };
+    char::from_u32(code).unwrap_or(c)
+}
+
+fn math_variant(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "mathrm" | "rm" => "normal",
+        "mathit" => "italic",
+        "mathbf" | "bf" | "boldsymbol" | "bm" => "bold",
+        "mathbb" => "double-struck",
+        "mathcal" | "mathscr" => "script",
+        "mathfrak" => "fraktur",
+        "mathsf" => "sans-serif",
+        "mathtt" => "monospace",
+        _ => return None,
+    })
+}
+
+fn greek(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ϵ",
+        "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "omicron" => "ο",
+        "pi" => "π",
+        "varpi" => "ϖ",
+        "rho" => "ρ",
+        "varrho" => "ϱ",
+        "sigma" => "σ",
+        "varsigma" => "ς",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        _ => return None,
+    })
+}
+
+fn identifier_symbol(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "ell" => "ℓ",
+        "hbar" => "ℏ",
+        "imath" => "ı",
+        "jmath" => "ȷ",
+        "emptyset" => "∅",
+        "varnothing" => "∅",
+        "aleph" => "ℵ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "wp" => "℘",
+        _ => return None,
+    })
+}
+
+fn operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "times" => "×",
+        "cdot" => "⋅",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "∙",
+        "oplus" => "⊕",
+        "ominus" => "⊖",
+        "otimes" => "⊗",
+        "odot" => "⊙",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "leqslant" => "⩽",
+        "geqslant" => "⩾",
+        "neq" | "ne" => "≠",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "ll" => "≪",
+        "gg" => "≫",
+        "prec" => "≺",
+        "succ" => "≻",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "supset" => "⊃",
+        "subseteq" => "⊆",
+        "supseteq" => "⊇",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "land" | "wedge" => "∧",
+        "lor" | "vee" => "∨",
+        "neg" | "lnot" => "¬",
+        "forall" => "∀",
+        "exists" => "∃",
+        "nexists" => "∄",
+        "to" | "rightarrow" => "→",
+        "leftarrow" | "gets" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" => "⇔",
+        "implies" => "⟹",
+        "impliedby" => "⟸",
+        "iff" => "⟺",
+        "mapsto" => "↦",
+        "longrightarrow" => "⟶",
+        "longleftarrow" => "⟵",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "ldots" | "dots" | "dotsc" | "dotso" => "…",
+        "cdots" | "dotsb" | "dotsm" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "prime" => "′",
+        "perp" => "⊥",
+        "parallel" => "∥",
+        "mid" => "∣",
+        "angle" => "∠",
+        "triangle" => "△",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "vert" => "|",
+        "Vert" => "‖",
+        "lbrace" => "{",
+        "rbrace" => "}",
+        "colon" => ":",
+        "backslash" => "\\",
+        "therefore" => "∴",
+        "because" => "∵",
+        "top" => "⊤",
+        "bot" => "⊥",
+        "vdash" => "⊢",
+        "models" => "⊨",
+        "dagger" => "†",
+        "ddagger" => "‡",
+        _ => return None,
+    })
+}
+
+fn large_operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "sum" => "∑",
+        "prod" => "∏",
+        "coprod" => "∐",
+        "int" => "∫",
+        "iint" => "∬",
+        "iiint" => "∭",
+        "oint" => "∮",
+        "bigcup" => "⋃",
+        "bigcap" => "⋂",
+        "bigvee" => "⋁",
+        "bigwedge" => "⋀",
+        "bigoplus" => "⨁",
+        "bigotimes" => "⨂",
+        "bigodot" => "⨀",
+        "biguplus" => "⨄",
+        _ => return None,
+    })
+}
+
+fn is_function(name: &str) -> bool {
+    matches!(
+        name,
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh" | "cosh" | "tanh"
+            | "coth" | "log" | "ln" | "lg" | "exp" | "det" | "dim" | "ker" | "deg" | "gcd" | "hom" | "arg" | "max"
+            | "min" | "sup" | "inf" | "lim" | "liminf" | "limsup" | "Pr" | "argmax" | "argmin"
+    )
+}
+
+fn space(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "," | "thinspace" => "0.1667em",
+        ":" | ">" | "medspace" => "0.2222em",
+        ";" | "thickspace" => "0.2778em",
+        " " => "0.25em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        "!" | "negthinspace" => "-0.1667em",
+        _ => return None,
+    })
+}
+
+/// Accent character and whether it stretches over its base
+fn accent(name: &str) -> Option<(&'static str, bool)> {
+    Some(match name {
+        "hat" => ("^", false),
+        "widehat" => ("^", true),
+        "check" => ("ˇ", false),
+        "tilde" => ("~", false),
+        "widetilde" => ("~", true),
+        "bar" => ("¯", false),
+        "overline" => ("‾", true),
+        "vec" => ("→", false),
+        "overrightarrow" => ("→", true),
+        "overleftarrow" => ("←", true),
+        "dot" => ("˙", false),
+        "ddot" => ("¨", false),
+        "acute" => ("´", false),
+        "grave" => ("`", false),
+        "breve" => ("˘", false),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latex(markdown: &str) -> Vec<(String, bool)> {
+        find_math(markdown).into_iter().map(|span| (span.latex, span.display)).collect()
+    }
+
+    #[test]
+    fn finds_inline_and_display_math() {
+        assert_eq!(latex("$x$ and $$y$$"), [("x".to_string(), false), ("y".to_string(), true)]);
+    }
+
+    #[test]
+    fn skips_code_escapes_and_prices() {
+        assert_eq!(latex("`$x$` $y$"), [("y".to_string(), false)]);
+        assert_eq!(latex("```\n$x$\n```\n$z$"), [("z".to_string(), false)]);
+        assert_eq!(latex("\\$x$ $w$"), [("w".to_string(), false)]);
+        assert!(latex("Price $5 and $6").is_empty());
+    }
+
+    #[test]
+    fn extract_and_restore_round_trip() {
+        let markdown = "Area $\\pi r^2$ of *a* circle, $$E = mc^2$$";
+        let (processed, spans) = extract(markdown);
+        assert!(!processed.contains('$'));
+        assert_eq!(restore(&processed, &spans), markdown);
+    }
+
+    #[test]
+    fn escapes_operators_and_annotation() {
+        let mathml = latex_to_mathml("a < b", false).unwrap();
+        assert!(mathml.contains("<mo>&lt;</mo>"), "{}", mathml);
+        assert!(mathml.contains("<annotation encoding=\"application/x-tex\">a &lt; b</annotation>"), "{}", mathml);
+    }
+
+    #[test]
+    fn converts_fractions_and_scripts() {
+        let mathml = latex_to_mathml("\\frac{1}{x^2}", true).unwrap();
+        assert!(mathml.contains(" display=\"block\""));
+        assert!(mathml.contains("<mfrac><mrow><mn>1</mn></mrow><mrow><msup><mi>x</mi><mn>2</mn></msup></mrow></mfrac>"));
+        assert!(latex_to_mathml("x_{i} + \\alpha", false).unwrap().contains("<msub><mi>x</mi><mrow><mi>i</mi></mrow></msub>"));
+    }
+
+    #[test]
+    fn rejects_malformed_latex() {
+        assert!(latex_to_mathml("\\frac{1", false).is_err());
+        assert!(latex_to_mathml("\\unknown", false).is_err());
+    }
+
+    #[test]
+    fn errors_show_escaped_source() {
+        let span = MathSpan { latex: "<b>\\bad".to_string(), display: false, source: "$<b>\\bad$".to_string() };
+        let html = render_span(&span);
+        assert!(html.starts_with("<code class=\"math-error\""), "{}", html);
+        assert!(html.ends_with(">$&lt;b&gt;\\bad$</code>"), "{}", html);
+    }
+}
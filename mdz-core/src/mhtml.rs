@@ -0,0 +1,79 @@
+use crate::css::{self, CssTarget};
+use crate::mime::{encode_header, Body, Entity};
+use crate::renderer::ImageLinks;
+use crate::{MdzDocument, MdzRenderer, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Content-Security-Policy for hardened archives, which load resources from their own parts
+const HARDENED_MHTML_CSP: &str =
+    "default-src 'none'; style-src 'unsafe-inline'; img-src cid:; font-src cid:; base-uri 'none'; form-action 'none'";
+
+/// Package a document as a `multipart/related` web archive (RFC 2557)
+///
+/// Images and fonts become parts of their own, referenced from the HTML and
+/// stylesheets through `cid:` URLs.
+pub(crate) fn render(renderer: &MdzRenderer, document: &MdzDocument) -> Result<Vec<u8>> {
+    let options = renderer.options();
+    let seed: String = Sha256::digest(document.content.as_bytes())[..6]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    // Archive path of each resource mapped to its `cid:` URL
+    let mut names: HashMap<String, String> = HashMap::new();
+    let mut resources = Vec::new();
+    let mut paths: Vec<(&String, &Vec<u8>, bool)> = document
+        .images
+        .iter()
+        .map(|(path, data)| (path, data, true))
+        .chain(document.fonts.iter().map(|(path, data)| (path, data, false)))
+        .collect();
+    paths.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (index, (path, data, is_image)) in paths.into_iter().enumerate() {
+        let (mime_type, data) = if is_image {
+            let (mime_type, data) = renderer.prepare_image(path, data)?;
+            (mime_type.to_string(), data.into_owned())
+        } else {
+            (mime_guess::from_path(path).first_or_octet_stream().to_string(), data.clone())
+        };
+        let content_id = format!("part{}.{}@mdz", index + 1, seed);
+        names.insert(path.clone(), format!("cid:{}", content_id));
+        resources.push(
+            Entity::new(Body::Binary(mime_type, data))
+                .header("Content-ID", format!("<{}>", content_id))
+                .header("Content-Location", path.clone()),
+        );
+    }
+
+    let body = renderer.render_body(document, ImageLinks::Rename(&names), 0)?;
+    let mut shell = renderer.html_shell(document, HARDENED_MHTML_CSP);
+    if options.include_css {
+        shell.style(&rewrite_css_urls(&renderer.get_css_content(document), &names));
+        for css in &body.extra_css {
+            shell.style(css);
+        }
+        if let Some(print_css) = &document.print_css {
+            shell.style(&format!("@media print {{\n{}\n}}", rewrite_css_urls(print_css, &names)));
+        }
+    }
+
+    let mut parts = vec![Entity::new(Body::Text("text/html", shell.render(&body.sections.concat())))];
+    parts.extend(resources);
+
+    let message = Entity::new(Body::Multipart("related", "type=\"text/html\"".to_string(), parts))
+        .header("From", "<Saved by mdz-core>")
+        .header("Subject", encode_header(&shell.title))
+        .header("Date", chrono::Utc::now().to_rfc2822())
+        .header("MIME-Version", "1.0");
+    Ok(message.to_bytes(&seed))
+}
+
+/// Point archive-relative `url()` references in a stylesheet at resource parts
+fn rewrite_css_urls(css: &str, names: &HashMap<String, String>) -> String {
+    css::rewrite_urls(css, |reference| match reference.target("css/style.css") {
+        CssTarget::Archive(path) => names.get(&path).cloned(),
+        _ => None,
+    })
+}
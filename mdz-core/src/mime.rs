@@ -0,0 +1,130 @@
+use base64::engine::general_purpose::STANDARD as base64_engine;
+use base64::Engine;
+
+/// MIME entity: headers and a body
+pub(crate) struct Entity {
+    /// Headers written before `Content-Type`, values already encoded
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Body,
+}
+
+/// Body of a MIME entity
+pub(crate) enum Body {
+    /// Text of the given MIME type, sent as UTF-8 quoted-printable
+    Text(&'static str, String),
+    /// Binary data of the given MIME type, sent as base64
+    Binary(String, Vec<u8>),
+    /// `multipart/<subtype>` with extra `Content-Type` parameters
+    Multipart(&'static str, String, Vec<Entity>),
+}
+
+impl Entity {
+    pub(crate) fn new(body: Body) -> Self {
+        Self { headers: Vec::new(), body }
+    }
+
+    pub(crate) fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Serialize with CRLF line endings; `seed` makes boundaries unique to this message
+    pub(crate) fn to_bytes(&self, seed: &str) -> Vec<u8> {
+        let mut output = String::new();
+        self.write(&mut output, seed, &mut 0);
+        output.into_bytes()
+    }
+
+    fn write(&self, output: &mut String, seed: &str, boundaries: &mut usize) {
+        for (name, value) in &self.headers {
+            output.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        match &self.body {
+            Body::Text(mime_type, text) => {
+                output.push_str(&format!("Content-Type: {}; charset=\"utf-8\"\r\n", mime_type));
+                output.push_str("Content-Transfer-Encoding: quoted-printable\r\n\r\n");
+                output.push_str(&quoted_printable(text));
+                output.push_str("\r\n");
+            }
+            Body::Binary(mime_type, data) => {
+                output.push_str(&format!("Content-Type: {}\r\n", mime_type));
+                output.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+                output.push_str(&base64_lines(data));
+            }
+            Body::Multipart(subtype, parameters, parts) => {
+                *boundaries += 1;
+                // "=_" cannot occur in quoted-printable or base64 output
+                let boundary = format!("----=_mdz_{}_{}", seed, boundaries);
+                output.push_str(&format!("Content-Type: multipart/{}; boundary=\"{}\"", subtype, boundary));
+                if !parameters.is_empty() {
+                    output.push_str(&format!(";\r\n\t{}", parameters));
+                }
+                output.push_str("\r\n\r\nThis is a multi-part message in MIME format.\r\n");
+                for part in parts {
+                    output.push_str(&format!("\r\n--{}\r\n", boundary));
+                    part.write(output, seed, boundaries);
+                }
+                output.push_str(&format!("\r\n--{}--\r\n", boundary));
+            }
+        }
+    }
+}
+
+/// Quoted-printable encoding (RFC 2045) with lines of at most 76 characters
+pub(crate) fn quoted_printable(text: &str) -> String {
+    let mut output = String::with_capacity(text.len() + text.len() / 8);
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            output.push_str("\r\n");
+        }
+        let bytes = line.strip_suffix('\r').unwrap_or(line).as_bytes();
+        let mut width = 0;
+        for (j, &byte) in bytes.iter().enumerate() {
+            let literal = match byte {
+                // Trailing whitespace would be stripped in transit
+                b' ' | b'\t' => j + 1 < bytes.len(),
+                b'=' => false,
+                33..=126 => true,
+                _ => false,
+            };
+            let piece = if literal { (byte as char).to_string() } else { format!("={:02X}", byte) };
+            if width + piece.len() > 75 {
+                output.push_str("=\r\n");
+                width = 0;
+            }
+            output.push_str(&piece);
+            width += piece.len();
+        }
+    }
+    output
+}
+
+/// Base64 in lines of 76 characters, each ending in CRLF
+pub(crate) fn base64_lines(data: &[u8]) -> String {
+    let encoded = base64_engine.encode(data);
+    let mut output = String::with_capacity(encoded.len() + encoded.len() / 38);
+    for line in encoded.as_bytes().chunks(76) {
+        output.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        output.push_str("\r\n");
+    }
+    output
+}
+
+/// Header value, as RFC 2047 encoded words when it is not plain ASCII
+pub(crate) fn encode_header(text: &str) -> String {
+    if text.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        return text.to_string();
+    }
+    // Encoded words are limited to 75 characters, which fits 45 bytes of input
+    let mut words = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = (start + 45).min(text.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        words.push(format!("=?UTF-8?B?{}?=", base64_engine.encode(&text[start..end])));
+        start = end;
+    }
+    words.join("\r\n ")
+}
@@ -10,26 +10,46 @@ impl MdzParser {
     /// Parse MDZ file from reader
     pub fn parse<R: Read + std::io::Seek>(reader: R) -> Result<MdzDocument> {
         let mut archive = ZipArchive::new(reader)?;
-        
+        Self::parse_archive(&mut archive)
+    }
+
+    /// Parse MDZ document from an opened ZIP archive
+    pub(crate) fn parse_archive<R: Read + std::io::Seek>(archive: &mut ZipArchive<R>) -> Result<MdzDocument> {
         // Read main.md
-        let content = Self::read_main_md(&mut archive)?;
+        let content = Self::read_main_md(archive)?;
         
         // Parse metadata from content (extract title from first heading)
-        let metadata = Self::extract_metadata(&content);
+        let mut metadata = Self::extract_metadata(&content);
+
+        // Declared metadata takes precedence over derived values; an unreadable
+        // metadata.json is reported by the validator and otherwise ignored
+        if let Ok(Some(declared)) = Self::load_metadata_file(archive) {
+            metadata = DocumentMetadata {
+                title: declared.title.or(metadata.title),
+                modified_at: declared.modified_at.or(metadata.modified_at),
+                ..declared
+            };
+        }
         
         // Create document
         let mut document = MdzDocument {
             content,
             images: HashMap::new(),
+            fonts: HashMap::new(),
             css: None,
+            print_css: None,
             metadata,
         };
 
         // Load images
-        document.images = Self::load_images(&mut archive)?;
+        document.images = Self::load_directory(archive, "img/")?;
+
+        // Load fonts
+        document.fonts = Self::load_directory(archive, "fonts/")?;
 
         // Load CSS
-        document.css = Self::load_css(&mut archive)?;
+        document.css = Self::load_css(archive, "css/style.css")?;
+        document.print_css = Self::load_css(archive, "css/print.css")?;
 
         Ok(document)
     }
@@ -52,8 +72,8 @@ impl MdzParser {
         // Extract title from first heading
         for line in content.lines() {
             let line = line.trim();
-            if line.starts_with("# ") {
-                metadata.title = Some(line[2..].trim().to_string());
+            if let Some(title) = line.strip_prefix("# ") {
+                metadata.title = Some(title.trim().to_string());
                 break;
             }
         }
@@ -62,27 +82,39 @@ impl MdzParser {
         metadata
     }
 
-    /// Load all images from the archive
-    fn load_images<R: Read + std::io::Seek>(archive: &mut ZipArchive<R>) -> Result<HashMap<String, Vec<u8>>> {
-        let mut images = HashMap::new();
+    /// Load declared metadata from metadata.json, if present
+    pub(crate) fn load_metadata_file<R: Read + std::io::Seek>(archive: &mut ZipArchive<R>) -> Result<Option<DocumentMetadata>> {
+        match archive.by_name("metadata.json") {
+            Ok(mut file) => {
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                Ok(Some(serde_json::from_str(&content)?))
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Load all files under a directory prefix from the archive
+    fn load_directory<R: Read + std::io::Seek>(archive: &mut ZipArchive<R>, prefix: &str) -> Result<HashMap<String, Vec<u8>>> {
+        let mut files = HashMap::new();
 
         for i in 0..archive.len() {
             let mut file = archive.by_index(i)?;
             let file_name = file.name().to_string();
 
-            if file_name.starts_with("img/") && !file_name.ends_with('/') {
+            if file_name.starts_with(prefix) && !file_name.ends_with('/') {
                 let mut buffer = Vec::new();
                 file.read_to_end(&mut buffer)?;
-                images.insert(file_name, buffer);
+                files.insert(file_name, buffer);
             }
         }
 
-        Ok(images)
+        Ok(files)
     }
 
-    /// Load CSS content
-    fn load_css<R: Read + std::io::Seek>(archive: &mut ZipArchive<R>) -> Result<Option<String>> {
-        match archive.by_name("css/style.css") {
+    /// Load a stylesheet, if present
+    fn load_css<R: Read + std::io::Seek>(archive: &mut ZipArchive<R>, path: &str) -> Result<Option<String>> {
+        match archive.by_name(path) {
             Ok(mut file) => {
                 let mut content = String::new();
                 file.read_to_string(&mut content)?;
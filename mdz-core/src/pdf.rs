@@ -0,0 +1,1844 @@
+use crate::css::{declarations, matching_brace, strip_comments};
+use crate::{svg, MarkdownDialect, MdzDocument, MdzError, Result};
+use pdf_writer::types::{ActionType, AnnotationType, CidFontType, FontFlags, SystemInfo, UnicodeCmap};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use pulldown_cmark::{Alignment, Event, HeadingLevel, Parser, Tag};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Points per millimetre
+const MM: f32 = 72.0 / 25.4;
+
+/// Page dimensions in points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl PageSize {
+    pub const A3: Self = Self { width: 297.0 * MM, height: 420.0 * MM };
+    pub const A4: Self = Self { width: 210.0 * MM, height: 297.0 * MM };
+    pub const A5: Self = Self { width: 148.0 * MM, height: 210.0 * MM };
+    pub const LETTER: Self = Self { width: 612.0, height: 792.0 };
+    pub const LEGAL: Self = Self { width: 612.0, height: 1008.0 };
+
+    /// Same size with the long side horizontal
+    pub fn landscape(self) -> Self {
+        Self {
+            width: self.width.max(self.height),
+            height: self.width.min(self.height),
+        }
+    }
+}
+
+impl FromStr for PageSize {
+    type Err = MdzError;
+
+    /// Parse a CSS `size` value: `A4`, `letter landscape` or `210mm 297mm`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || MdzError::Parse(format!("Invalid page size '{}'", s));
+        let mut named = None;
+        let mut lengths = Vec::new();
+        let mut landscape = false;
+
+        for token in s.split_whitespace() {
+            match token.to_ascii_lowercase().as_str() {
+                "a3" => named = Some(Self::A3),
+                "a4" => named = Some(Self::A4),
+                "a5" => named = Some(Self::A5),
+                "letter" => named = Some(Self::LETTER),
+                "legal" => named = Some(Self::LEGAL),
+                "landscape" => landscape = true,
+                "portrait" => {}
+                other => lengths.push(parse_length(other).ok_or_else(invalid)?),
+            }
+        }
+
+        let size = match (named, lengths.as_slice()) {
+            (Some(size), []) => size,
+            (None, [side]) => Self { width: *side, height: *side },
+            (None, [width, height]) => Self { width: *width, height: *height },
+            (None, []) if landscape => Self::A4,
+            _ => return Err(invalid()),
+        };
+        Ok(if landscape { size.landscape() } else { size })
+    }
+}
+
+/// Page margins in points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Margins {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl Margins {
+    /// Same margin on all sides
+    pub fn uniform(margin: f32) -> Self {
+        Self { top: margin, right: margin, bottom: margin, left: margin }
+    }
+}
+
+impl Default for Margins {
+    fn default() -> Self {
+        Self::uniform(20.0 * MM)
+    }
+}
+
+impl FromStr for Margins {
+    type Err = MdzError;
+
+    /// Parse a CSS `margin` shorthand of one to four lengths
+    fn from_str(s: &str) -> Result<Self> {
+        let lengths = s
+            .split_whitespace()
+            .map(parse_length)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| MdzError::Parse(format!("Invalid margin '{}'", s)))?;
+
+        match lengths.as_slice() {
+            [all] => Ok(Self::uniform(*all)),
+            [vertical, horizontal] => Ok(Self { top: *vertical, right: *horizontal, bottom: *vertical, left: *horizontal }),
+            [top, horizontal, bottom] => Ok(Self { top: *top, right: *horizontal, bottom: *bottom, left: *horizontal }),
+            [top, right, bottom, left] => Ok(Self { top: *top, right: *right, bottom: *bottom, left: *left }),
+            _ => Err(MdzError::Parse(format!("Invalid margin '{}'", s))),
+        }
+    }
+}
+
+/// Parse a CSS length (`pt`, `px`, `mm`, `cm`, `in`, `pc`) into points
+pub fn parse_length(value: &str) -> Option<f32> {
+    let value = value.trim().to_ascii_lowercase();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f32 = number.parse().ok()?;
+    let factor = match unit {
+        "pt" => 1.0,
+        "px" => 0.75,
+        "mm" => MM,
+        "cm" => 10.0 * MM,
+        "in" => 72.0,
+        "pc" => 12.0,
+        "" if number == 0.0 => 1.0,
+        _ => return None,
+    };
+    Some(number * factor)
+}
+
+/// PDF page setup
+///
+/// Unset values fall back to `css/print.css`, then to A4 with 20mm margins.
+#[derive(Debug, Clone)]
+pub struct PdfOptions {
+    pub page_size: Option<PageSize>,
+    pub margins: Option<Margins>,
+    /// Body text size in points
+    pub font_size: Option<f32>,
+    /// Text at the top of every page
+    ///
+    /// `{page}`, `{pages}` and `{title}` are substituted; `|` separates
+    /// left, centre and right aligned parts.
+    pub header: Option<String>,
+    /// Text at the bottom of every page, in the same format as `header`
+    pub footer: Option<String>,
+    /// TrueType or OpenType font file to embed, tried before the fonts in `fonts/`
+    pub font: Option<PathBuf>,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            page_size: None,
+            margins: None,
+            font_size: None,
+            header: None,
+            footer: Some("{page} / {pages}".to_string()),
+            font: None,
+        }
+    }
+}
+
+/// PDF file rendered by [`MdzRenderer::render_pdf`](crate::MdzRenderer::render_pdf)
+#[derive(Debug, Clone, Default)]
+pub struct PdfOutput {
+    pub data: Vec<u8>,
+    /// Problems that did not stop the render, such as characters no font can show
+    pub warnings: Vec<String>,
+}
+
+/// Subset of `css/print.css` applied to PDF output
+///
+/// Understood: `@page { size; margin* }`, `font-size` on `body`/`html`, and
+/// `page-break-before/after` or `break-before/after` on `h1`-`h6`.
+#[derive(Debug, Clone, Default)]
+struct PrintStyle {
+    page_size: Option<PageSize>,
+    margins: Option<Margins>,
+    font_size: Option<f32>,
+    /// Heading levels starting a new page, indexed from `h1`
+    break_before: [bool; 6],
+    /// Heading levels followed by a new page
+    break_after: [bool; 6],
+}
+
+impl PrintStyle {
+    fn parse(css: &str) -> Self {
+        let mut style = Self::default();
+        style.parse_rules(&strip_comments(css));
+        style
+    }
+
+    fn parse_rules(&mut self, css: &str) {
+        let mut rest = css;
+        while let Some(open) = rest.find('{') {
+            let Some(close) = matching_brace(rest, open) else {
+                break;
+            };
+            // Statements such as `@import ...;` may precede the selector
+            let selector = rest[..open].rsplit(';').next().unwrap_or("").trim().to_ascii_lowercase();
+            let body = &rest[open + 1..close];
+
+            if selector.starts_with("@media") {
+                if selector.contains("print") || selector.contains("all") {
+                    self.parse_rules(body);
+                }
+            } else if selector.starts_with("@page") {
+                for (property, value, _) in declarations(&top_level(body)) {
+                    self.page_property(&property, value);
+                }
+            } else if !selector.starts_with('@') {
+                for selector in selector.split(',') {
+                    for (property, value, _) in declarations(body) {
+                        self.element_property(selector.trim(), &property, value);
+                    }
+                }
+            }
+            rest = &rest[close + 1..];
+        }
+    }
+
+    fn page_property(&mut self, property: &str, value: &str) {
+        match property {
+            "size" => self.page_size = value.parse().ok().or(self.page_size),
+            "margin" => self.margins = value.parse().ok().or(self.margins),
+            "margin-top" | "margin-right" | "margin-bottom" | "margin-left" => {
+                let Some(length) = parse_length(value) else {
+                    return;
+                };
+                let margins = self.margins.get_or_insert_with(Margins::default);
+                match property {
+                    "margin-top" => margins.top = length,
+                    "margin-right" => margins.right = length,
+                    "margin-bottom" => margins.bottom = length,
+                    _ => margins.left = length,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn element_property(&mut self, selector: &str, property: &str, value: &str) {
+        match selector {
+            "body" | "html" | ":root" if property == "font-size" => {
+                self.font_size = parse_length(value).or(self.font_size);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = (selector.as_bytes()[1] - b'1') as usize;
+                let breaks = matches!(value.to_ascii_lowercase().as_str(), "always" | "page" | "left" | "right");
+                match property {
+                    "page-break-before" | "break-before" => self.break_before[level] = breaks,
+                    "page-break-after" | "break-after" => self.break_after[level] = breaks,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Declarations of a block without nested blocks such as `@top-center { ... }`
+fn top_level(block: &str) -> String {
+    let mut output = String::new();
+    let mut rest = block;
+    while let Some(open) = rest.find('{') {
+        // Drop the nested block's selector, which follows the last declaration
+        let head = &rest[..open];
+        output.push_str(&head[..head.rfind(';').map_or(0, |i| i + 1)]);
+        match matching_brace(rest, open) {
+            Some(close) => rest = &rest[close + 1..],
+            None => return output,
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Check if raw HTML asks for a page break
+///
+/// Recognises `<!-- pagebreak -->` and elements styled with
+/// `page-break-before/after: always` or `break-before/after: page`.
+pub(crate) fn is_page_break(html: &str) -> bool {
+    let compact: String = html.to_ascii_lowercase().chars().filter(|c| !c.is_whitespace()).collect();
+    [
+        "<!--pagebreak-->",
+        "<!--page-break-->",
+        "page-break-before:always",
+        "page-break-after:always",
+        "break-before:page",
+        "break-after:page",
+    ]
+    .iter()
+    .any(|directive| compact.contains(directive))
+}
+
+/// Render a document to PDF using the standard Helvetica and Courier fonts
+///
+/// Fonts in `fonts/`, or the one set in [`PdfOptions::font`], are embedded
+/// for the styles they match and for text outside Windows-1252; characters no
+/// font can show are drawn as `?` and reported in [`PdfOutput::warnings`].
+/// Raw HTML is skipped apart from page-break
+/// directives; math is shown as its LaTeX source.
+pub(crate) fn render(document: &MdzDocument, dialect: MarkdownDialect, options: &PdfOptions, title: Option<&str>) -> Result<PdfOutput> {
+    let print = document.print_css.as_deref().map(PrintStyle::parse).unwrap_or_default();
+    let settings = Settings {
+        page: options.page_size.or(print.page_size).unwrap_or(PageSize::A4),
+        margins: options.margins.or(print.margins).unwrap_or_default(),
+        font_size: options.font_size.or(print.font_size).unwrap_or(10.5),
+        break_before: print.break_before,
+        break_after: print.break_after,
+    };
+    if settings.page.width - settings.margins.left - settings.margins.right < 72.0
+        || settings.page.height - settings.margins.top - settings.margins.bottom < 72.0
+    {
+        return Err(MdzError::Render("Page margins leave less than one inch for content".to_string()));
+    }
+
+    let custom_font = options.font.as_ref().map(std::fs::read).transpose()?;
+    let mut archive_fonts: Vec<(&String, &Vec<u8>)> = document
+        .fonts
+        .iter()
+        .filter(|(path, _)| {
+            let path = path.to_ascii_lowercase();
+            path.ends_with(".ttf") || path.ends_with(".otf")
+        })
+        .collect();
+    archive_fonts.sort();
+    let fonts = Fonts::new(custom_font.iter().map(Vec::as_slice).chain(archive_fonts.iter().map(|(_, data)| data.as_slice())));
+
+    let mut layout = Layout::new(document, settings, &fonts);
+    for event in Parser::new_ext(&document.content, dialect.parser_options()) {
+        layout.event(event);
+    }
+    layout.flush();
+
+    let title = title.unwrap_or("");
+    let pages = layout.pages.len();
+    for (index, page) in layout.pages.iter_mut().enumerate() {
+        let substitute = |template: &str| {
+            template
+                .replace("{page}", &(index + 1).to_string())
+                .replace("{pages}", &pages.to_string())
+                .replace("{title}", title)
+        };
+        let bottom = settings.page.height - settings.margins.bottom / 2.0;
+        if let Some(header) = &options.header {
+            draw_margin_text(page, &fonts, &substitute(header), &settings, settings.margins.top / 2.0);
+        }
+        if let Some(footer) = &options.footer {
+            draw_margin_text(page, &fonts, &substitute(footer), &settings, bottom);
+        }
+    }
+
+    let mut warnings = Vec::new();
+    let missing = fonts.missing.borrow();
+    if !missing.is_empty() {
+        let characters: Vec<String> = missing.iter().take(10).map(|c| format!("'{}' (U+{:04X})", c, *c as u32)).collect();
+        warnings.push(format!(
+            "No font for {}{}, drawn as '?'; add a TrueType or OpenType font covering them to fonts/",
+            characters.join(", "),
+            if missing.len() > 10 { " and others" } else { "" }
+        ));
+    }
+
+    let data = write_pdf(&layout, &settings, document, title)?;
+    Ok(PdfOutput { data, warnings })
+}
+
+/// Draw a `left|centre|right` header or footer line with its baseline at `y`
+fn draw_margin_text(page: &mut Page, fonts: &Fonts, text: &str, settings: &Settings, y: f32) {
+    let size = 8.5;
+    let parts: Vec<&str> = text.split('|').collect();
+    let aligned: Vec<(&str, Alignment)> = match parts.as_slice() {
+        [center] => vec![(center, Alignment::Center)],
+        [left, right] => vec![(left, Alignment::Left), (right, Alignment::Right)],
+        [left, center, right, ..] => vec![(left, Alignment::Left), (center, Alignment::Center), (right, Alignment::Right)],
+        [] => vec![],
+    };
+
+    let left = settings.margins.left;
+    let width = settings.page.width - left - settings.margins.right;
+    for (text, alignment) in aligned {
+        let segments = fonts.encode(text.trim(), Font::Regular);
+        let text_width: f32 = segments.iter().map(|(face, text)| fonts.width(*face, text, size)).sum();
+        let x = match alignment {
+            Alignment::Center => left + (width - text_width) / 2.0,
+            Alignment::Right => left + width - text_width,
+            _ => left,
+        };
+        push_text(&mut page.ops, fonts, segments, x, y, size, MUTED);
+    }
+}
+
+/// Draw encoded segments one after another from `x`
+fn push_text(ops: &mut Vec<Op>, fonts: &Fonts, segments: Vec<(Face, Vec<u8>)>, mut x: f32, y: f32, size: f32, color: Color) {
+    for (face, text) in segments {
+        let width = fonts.width(face, &text, size);
+        ops.push(Op::Text { x, y, face, size, color, text });
+        x += width;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    page: PageSize,
+    margins: Margins,
+    font_size: f32,
+    break_before: [bool; 6],
+    break_after: [bool; 6],
+}
+
+type Color = (f32, f32, f32);
+
+const TEXT: Color = (0.2, 0.2, 0.2);
+const HEADING: Color = (0.1, 0.1, 0.1);
+const LINK: Color = (0.16, 0.42, 0.72);
+const MUTED: Color = (0.45, 0.45, 0.45);
+const RULE: Color = (0.8, 0.8, 0.8);
+const CODE_BACKGROUND: Color = (0.96, 0.965, 0.97);
+const TABLE_HEADER: Color = (0.97, 0.976, 0.98);
+
+/// Standard PDF font, one per resource name `F1`-`F6`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+    MonoBold,
+}
+
+impl Font {
+    const ALL: [Font; 6] = [Self::Regular, Self::Bold, Self::Italic, Self::BoldItalic, Self::Mono, Self::MonoBold];
+
+    fn styled(bold: bool, italic: bool, code: bool) -> Self {
+        match (code, bold, italic) {
+            (true, true, _) => Self::MonoBold,
+            (true, false, _) => Self::Mono,
+            (false, true, true) => Self::BoldItalic,
+            (false, true, false) => Self::Bold,
+            (false, false, true) => Self::Italic,
+            (false, false, false) => Self::Regular,
+        }
+    }
+
+    fn bold(self) -> Self {
+        match self {
+            Self::Regular | Self::Bold => Self::Bold,
+            Self::Italic | Self::BoldItalic => Self::BoldItalic,
+            Self::Mono | Self::MonoBold => Self::MonoBold,
+        }
+    }
+
+    fn resource_name(self) -> Name<'static> {
+        Name([b"F1", b"F2", b"F3", b"F4", b"F5", b"F6"][self as usize])
+    }
+
+    fn base_font(self) -> Name<'static> {
+        Name(match self {
+            Self::Regular => b"Helvetica",
+            Self::Bold => b"Helvetica-Bold",
+            Self::Italic => b"Helvetica-Oblique",
+            Self::BoldItalic => b"Helvetica-BoldOblique",
+            Self::Mono => b"Courier",
+            Self::MonoBold => b"Courier-Bold",
+        })
+    }
+
+    /// Advance width of Windows-1252 encoded text
+    fn width(self, text: &[u8], size: f32) -> f32 {
+        let units: u32 = text
+            .iter()
+            .map(|&byte| match self {
+                Self::Mono | Self::MonoBold => 600,
+                Self::Regular | Self::Italic => glyph_width(byte, &HELVETICA_WIDTHS),
+                Self::Bold | Self::BoldItalic => glyph_width(byte, &HELVETICA_BOLD_WIDTHS),
+            })
+            .sum();
+        units as f32 * size / 1000.0
+    }
+}
+
+/// Helvetica advance widths for ASCII 32-126, in 1/1000 em
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667,
+    556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556,
+    556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722,
+    500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Helvetica-Bold advance widths for ASCII 32-126, in 1/1000 em
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722,
+    611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556, 333, 556,
+    611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778,
+    556, 556, 500, 389, 280, 389, 584,
+];
+
+fn glyph_width(byte: u8, ascii: &[u16; 95]) -> u32 {
+    let width = match byte {
+        32..=126 => ascii[(byte - 32) as usize],
+        0x91 | 0x92 | 0x82 => 222,
+        0x93 | 0x94 | 0x84 => 333,
+        0x95 => 350,
+        0x85 | 0x97 | 0x89 | 0x99 => 1000,
+        0xA0 => 278,
+        // Accented capitals
+        0xC0..=0xDE if byte != 0xD7 => 722,
+        _ => 556,
+    };
+    width as u32
+}
+
+/// Windows-1252 (`WinAnsiEncoding`) code of a character, if it has one
+fn win_ansi(c: char) -> Option<u8> {
+    match c {
+        ' '..='~' => Some(c as u8),
+        '\u{A0}'..='\u{FF}' => Some(c as u32 as u8),
+        '€' => Some(0x80),
+        '‚' => Some(0x82),
+        'ƒ' => Some(0x83),
+        '„' => Some(0x84),
+        '…' => Some(0x85),
+        '†' => Some(0x86),
+        '‡' => Some(0x87),
+        'ˆ' => Some(0x88),
+        '‰' => Some(0x89),
+        'Š' => Some(0x8A),
+        '‹' => Some(0x8B),
+        'Œ' => Some(0x8C),
+        'Ž' => Some(0x8E),
+        '‘' => Some(0x91),
+        '’' => Some(0x92),
+        '“' => Some(0x93),
+        '”' => Some(0x94),
+        '•' => Some(0x95),
+        '–' => Some(0x96),
+        '—' => Some(0x97),
+        '˜' => Some(0x98),
+        '™' => Some(0x99),
+        'š' => Some(0x9A),
+        '›' => Some(0x9B),
+        'œ' => Some(0x9C),
+        'ž' => Some(0x9E),
+        'Ÿ' => Some(0x9F),
+        _ => None,
+    }
+}
+
+/// Check for control, zero-width, bidi and variation selector characters,
+/// which have no glyph of their own
+fn is_invisible(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{200B}'..='\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'..='\u{2069}'
+                | '\u{FE00}'..='\u{FE0F}'
+                | '\u{FEFF}'
+                | '\u{E0000}'..='\u{E007F}'
+                | '\u{E0100}'..='\u{E01EF}'
+        )
+}
+
+/// Font a piece of text is drawn in
+///
+/// Standard fonts take one Windows-1252 byte per character, embedded fonts
+/// two bytes of glyph id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Face {
+    Standard(Font),
+    Embedded(usize),
+}
+
+impl Face {
+    fn resource_name(self) -> Vec<u8> {
+        match self {
+            Self::Standard(font) => font.resource_name().0.to_vec(),
+            Self::Embedded(index) => format!("E{}", index + 1).into_bytes(),
+        }
+    }
+
+    /// Bytes per character
+    fn code_len(self) -> usize {
+        match self {
+            Self::Standard(_) => 1,
+            Self::Embedded(_) => 2,
+        }
+    }
+}
+
+/// TrueType or OpenType font embedded for the glyphs it is used for
+struct EmbeddedFont<'f> {
+    face: ttf_parser::Face<'f>,
+    data: &'f [u8],
+    /// Glyph ids drawn so far, with the character each one shows
+    used: RefCell<BTreeMap<u16, char>>,
+}
+
+impl EmbeddedFont<'_> {
+    fn glyph(&self, c: char) -> Option<u16> {
+        self.face.glyph_index(c).map(|glyph| glyph.0).filter(|&glyph| glyph != 0)
+    }
+
+    /// Advance width of a glyph in em
+    fn advance(&self, glyph: u16) -> f32 {
+        let advance = self.face.glyph_hor_advance(ttf_parser::GlyphId(glyph)).unwrap_or(0);
+        advance as f32 / self.face.units_per_em() as f32
+    }
+
+    /// PostScript name from the `name` table, falling back to `MdzFont<n>`
+    fn postscript_name(&self, index: usize) -> String {
+        let name: String = self
+            .face
+            .names()
+            .into_iter()
+            .filter(|name| name.name_id == ttf_parser::name_id::POST_SCRIPT_NAME)
+            .find_map(|name| name.to_string())
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| c.is_ascii_graphic() && !"()<>[]{}/%#".contains(*c))
+            .collect();
+        if name.is_empty() {
+            format!("MdzFont{}", index + 1)
+        } else {
+            name
+        }
+    }
+}
+
+/// Fonts available to a render: the standard ones plus any embeddable fonts
+struct Fonts<'f> {
+    embedded: Vec<EmbeddedFont<'f>>,
+    /// Embedded font matching each standard font's style, used in its place
+    primary: [Option<usize>; 6],
+    /// Characters no font has a glyph for
+    missing: RefCell<BTreeSet<char>>,
+}
+
+impl<'f> Fonts<'f> {
+    /// Parse candidate font files, skipping invalid ones and those whose
+    /// license forbids embedding
+    fn new(data: impl IntoIterator<Item = &'f [u8]>) -> Self {
+        let embedded: Vec<EmbeddedFont> = data
+            .into_iter()
+            .filter_map(|data| {
+                let face = ttf_parser::Face::parse(data, 0).ok()?;
+                (face.permissions() != Some(ttf_parser::Permissions::Restricted))
+                    .then(|| EmbeddedFont { face, data, used: RefCell::default() })
+            })
+            .collect();
+        let primary = Font::ALL.map(|font| {
+            let (bold, italic, mono) = match font {
+                Font::Regular => (false, false, false),
+                Font::Bold => (true, false, false),
+                Font::Italic => (false, true, false),
+                Font::BoldItalic => (true, true, false),
+                Font::Mono => (false, false, true),
+                Font::MonoBold => (true, false, true),
+            };
+            embedded.iter().position(|font| {
+                font.face.is_bold() == bold && font.face.is_italic() == italic && font.face.is_monospaced() == mono
+            })
+        });
+        Self { embedded, primary, missing: RefCell::default() }
+    }
+
+    /// Split text into runs of one face each and encode them
+    ///
+    /// Invisible formatting characters are dropped. Other characters go to the embedded font matching `font`'s style, then the
+    /// standard font, then any embedded font that has them; anything left is
+    /// drawn as `?` and recorded in `missing`.
+    fn encode(&self, text: &str, font: Font) -> Vec<(Face, Vec<u8>)> {
+        let mut segments: Vec<(Face, Vec<u8>)> = Vec::new();
+        for c in text.chars() {
+            let c = if c == '\t' { ' ' } else { c };
+            if is_invisible(c) {
+                continue;
+            }
+            let embedded = |index: usize| {
+                let embedded = &self.embedded[index];
+                embedded.glyph(c).map(|glyph| {
+                    embedded.used.borrow_mut().insert(glyph, c);
+                    (Face::Embedded(index), glyph.to_be_bytes().to_vec())
+                })
+            };
+            let (face, bytes) = self.primary[font as usize]
+                .and_then(embedded)
+                .or_else(|| win_ansi(c).map(|byte| (Face::Standard(font), vec![byte])))
+                .or_else(|| (0..self.embedded.len()).find_map(embedded))
+                .unwrap_or_else(|| {
+                    self.missing.borrow_mut().insert(c);
+                    (Face::Standard(font), vec![b'?'])
+                });
+            match segments.last_mut() {
+                Some((last, text)) if *last == face => text.extend(bytes),
+                _ => segments.push((face, bytes)),
+            }
+        }
+        segments
+    }
+
+    /// Advance width of encoded text
+    fn width(&self, face: Face, text: &[u8], size: f32) -> f32 {
+        match face {
+            Face::Standard(font) => font.width(text, size),
+            Face::Embedded(index) => {
+                let font = &self.embedded[index];
+                let ems: f32 = text.chunks(2).map(|code| font.advance(u16::from_be_bytes([code[0], code[1]]))).sum();
+                ems * size
+            }
+        }
+    }
+}
+
+/// Drawing operation, with `y` measured from the top of the page
+enum Op {
+    /// Text with its baseline at `y`
+    Text { x: f32, y: f32, face: Face, size: f32, color: Color, text: Vec<u8> },
+    Fill { x: f32, y: f32, width: f32, height: f32, color: Color },
+    Line { x1: f32, y1: f32, x2: f32, y2: f32, width: f32, color: Color },
+    Image { index: usize, x: f32, y: f32, width: f32, height: f32 },
+}
+
+#[derive(Default)]
+struct Page {
+    ops: Vec<Op>,
+    /// `(x, y, width, height, uri)` of external links
+    links: Vec<(f32, f32, f32, f32, String)>,
+}
+
+/// Styled inline text; `\n` forces a line break
+#[derive(Debug, Clone)]
+struct Run {
+    text: String,
+    font: Font,
+    color: Color,
+    link: Option<String>,
+    strike: bool,
+}
+
+/// Encoded, measured part of a wrapped line
+#[derive(Debug, Clone)]
+struct Piece {
+    text: Vec<u8>,
+    width: f32,
+    face: Face,
+    color: Color,
+    link: Option<String>,
+    strike: bool,
+}
+
+impl Piece {
+    /// Encode text in the run's style, one piece per face it needs
+    fn split(text: &str, run: &Run, fonts: &Fonts, size: f32) -> Vec<Self> {
+        fonts
+            .encode(text, run.font)
+            .into_iter()
+            .map(|(face, text)| Self {
+                width: fonts.width(face, &text, size),
+                text,
+                face,
+                color: run.color,
+                link: run.link.clone(),
+                strike: run.strike,
+            })
+            .collect()
+    }
+}
+
+/// Break runs into lines no wider than `width`
+///
+/// Words glued across runs (`**bold**,`) are kept together.
+fn wrap(runs: &[Run], fonts: &Fonts, width: f32, size: f32) -> Vec<Vec<Piece>> {
+    enum Token {
+        Word(Vec<Piece>),
+        Space(Vec<Piece>),
+        Newline,
+    }
+
+    let mut tokens = Vec::new();
+    let mut word = Vec::new();
+    for run in runs {
+        for (i, part) in run.text.split('\n').enumerate() {
+            if i > 0 {
+                if !word.is_empty() {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                }
+                tokens.push(Token::Newline);
+            }
+            for (j, chunk) in part.split(' ').enumerate() {
+                if j > 0 {
+                    if !word.is_empty() {
+                        tokens.push(Token::Word(std::mem::take(&mut word)));
+                    }
+                    tokens.push(Token::Space(Piece::split(" ", run, fonts, size)));
+                }
+                if !chunk.is_empty() {
+                    word.extend(Piece::split(chunk, run, fonts, size));
+                }
+            }
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(Token::Word(word));
+    }
+
+    let mut lines: Vec<Vec<Piece>> = vec![Vec::new()];
+    let mut line_width = 0.0;
+    let mut space: Option<Vec<Piece>> = None;
+    for token in tokens {
+        match token {
+            Token::Newline => {
+                lines.push(Vec::new());
+                line_width = 0.0;
+                space = None;
+            }
+            Token::Space(pieces) => {
+                if !lines.last().unwrap().is_empty() {
+                    space = Some(pieces);
+                }
+            }
+            Token::Word(pieces) => {
+                let word_width: f32 = pieces.iter().map(|p| p.width).sum();
+                let space_width: f32 = space.iter().flatten().map(|p| p.width).sum();
+                if !lines.last().unwrap().is_empty() && line_width + space_width + word_width > width {
+                    lines.push(Vec::new());
+                    line_width = 0.0;
+                    space = None;
+                }
+                if let Some(space) = space.take() {
+                    line_width += space_width;
+                    lines.last_mut().unwrap().extend(space);
+                }
+                if word_width <= width {
+                    line_width += word_width;
+                    lines.last_mut().unwrap().extend(pieces);
+                    continue;
+                }
+                // Hard-break a word wider than the line
+                for piece in pieces {
+                    let mut start = 0;
+                    let mut piece_width = 0.0;
+                    let step = piece.face.code_len();
+                    for (i, code) in piece.text.chunks(step).enumerate() {
+                        let i = i * step;
+                        let advance = fonts.width(piece.face, code, size);
+                        if line_width + piece_width + advance > width && (line_width > 0.0 || i > start) {
+                            let mut head = piece.clone();
+                            head.text = piece.text[start..i].to_vec();
+                            head.width = piece_width;
+                            if !head.text.is_empty() {
+                                lines.last_mut().unwrap().push(head);
+                            }
+                            lines.push(Vec::new());
+                            line_width = 0.0;
+                            piece_width = 0.0;
+                            start = i;
+                        }
+                        piece_width += advance;
+                    }
+                    let mut tail = piece;
+                    tail.text.drain(..start);
+                    tail.width = piece_width;
+                    line_width += piece_width;
+                    lines.last_mut().unwrap().push(tail);
+                }
+            }
+        }
+    }
+
+    if lines.len() > 1 && lines.last().is_some_and(Vec::is_empty) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Decoded image ready to be written as an XObject
+struct ImageData {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    filter: Filter,
+    components: u8,
+    alpha: Option<Vec<u8>>,
+}
+
+fn decode_image(path: &str, data: &[u8]) -> Option<ImageData> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        let (width, height, components) = jpeg_info(data)?;
+        return Some(ImageData { width, height, data: data.to_vec(), filter: Filter::DctDecode, components, alpha: None });
+    }
+    if path.to_ascii_lowercase().ends_with(".svg") {
+        return decode_png(&svg::rasterize(data).ok()?);
+    }
+    decode_png(data)
+}
+
+/// Dimensions and colour components from a JPEG frame header
+pub(crate) fn jpeg_info(data: &[u8]) -> Option<(u32, u32, u8)> {
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let header = data.get(pos + 4..pos + 10)?;
+            let height = u16::from_be_bytes([header[1], header[2]]) as u32;
+            let width = u16::from_be_bytes([header[3], header[4]]) as u32;
+            return Some((width, height, header[5]));
+        }
+        pos += 2 + length;
+    }
+    None
+}
+
+fn decode_png(data: &[u8]) -> Option<ImageData> {
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).ok()?;
+    buffer.truncate(info.buffer_size());
+
+    let (components, has_alpha) = match info.color_type {
+        png::ColorType::Grayscale => (1, false),
+        png::ColorType::GrayscaleAlpha => (1, true),
+        png::ColorType::Rgb => (3, false),
+        png::ColorType::Rgba => (3, true),
+        png::ColorType::Indexed => return None,
+    };
+    let (color, alpha) = if has_alpha {
+        let stride = components + 1;
+        let color: Vec<u8> = buffer.chunks(stride).flat_map(|pixel| pixel[..components].to_vec()).collect();
+        let alpha: Vec<u8> = buffer.chunks(stride).map(|pixel| pixel[components]).collect();
+        (color, Some(deflate(&alpha)))
+    } else {
+        (buffer, None)
+    };
+
+    Some(ImageData {
+        width: info.width,
+        height: info.height,
+        data: deflate(&color),
+        filter: Filter::FlateDecode,
+        components: components as u8,
+        alpha,
+    })
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
+
+/// Table being collected
+struct TableState {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<Vec<Run>>>,
+    header_rows: usize,
+}
+
+/// Image being collected; its alt text arrives as text events
+struct ImageState {
+    dest: String,
+    title: String,
+    alt: String,
+}
+
+/// Flows the Markdown event stream onto pages
+struct Layout<'d> {
+    document: &'d MdzDocument,
+    fonts: &'d Fonts<'d>,
+    settings: Settings,
+    pages: Vec<Page>,
+    images: Vec<ImageData>,
+    image_indices: HashMap<String, Option<usize>>,
+    /// Distance of the cursor from the top of the page
+    y: f32,
+    indent: f32,
+    /// Left edges of open blockquotes, where their bars are drawn
+    quotes: Vec<f32>,
+    runs: Vec<Run>,
+    bold: usize,
+    italic: usize,
+    strike: usize,
+    links: Vec<String>,
+    heading: Option<HeadingLevel>,
+    /// Counter of each open list, `None` for bullets
+    lists: Vec<Option<u64>>,
+    /// List marker or footnote number waiting for the next line of text
+    marker: Option<String>,
+    code: Option<String>,
+    table: Option<TableState>,
+    image: Option<ImageState>,
+    footnotes: HashMap<String, usize>,
+}
+
+impl<'d> Layout<'d> {
+    fn new(document: &'d MdzDocument, settings: Settings, fonts: &'d Fonts<'d>) -> Self {
+        Self {
+            document,
+            fonts,
+            settings,
+            pages: vec![Page::default()],
+            images: Vec::new(),
+            image_indices: HashMap::new(),
+            y: settings.margins.top,
+            indent: 0.0,
+            quotes: Vec::new(),
+            runs: Vec::new(),
+            bold: 0,
+            italic: 0,
+            strike: 0,
+            links: Vec::new(),
+            heading: None,
+            lists: Vec::new(),
+            marker: None,
+            code: None,
+            table: None,
+            image: None,
+            footnotes: HashMap::new(),
+        }
+    }
+
+    fn left(&self) -> f32 {
+        self.settings.margins.left + self.indent
+    }
+
+    fn content_width(&self) -> f32 {
+        self.settings.page.width - self.settings.margins.left - self.settings.margins.right - self.indent
+    }
+
+    fn bottom(&self) -> f32 {
+        self.settings.page.height - self.settings.margins.bottom
+    }
+
+    fn at_page_top(&self) -> bool {
+        self.y <= self.settings.margins.top
+    }
+
+    fn page(&mut self) -> &mut Page {
+        self.pages.last_mut().unwrap()
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Page::default());
+        self.y = self.settings.margins.top;
+    }
+
+    /// Start a new page unless the current one is still empty
+    fn page_break(&mut self) {
+        if !self.page().ops.is_empty() {
+            self.new_page();
+        }
+    }
+
+    /// Move to a new page if `height` does not fit on the current one
+    fn ensure(&mut self, height: f32) {
+        if self.y + height > self.bottom() && !self.at_page_top() {
+            self.new_page();
+        }
+    }
+
+    /// Vertical gap, dropped at the top of a page
+    fn space(&mut self, height: f32) {
+        if !self.at_page_top() {
+            self.y = (self.y + height).min(self.bottom());
+        }
+    }
+
+    /// Reserve `height` for a line of content, drawing blockquote bars beside it
+    fn reserve(&mut self, height: f32) -> f32 {
+        self.ensure(height);
+        let top = self.y;
+        for x in self.quotes.clone() {
+            self.page().ops.push(Op::Fill { x, y: top, width: 3.0, height, color: RULE });
+        }
+        self.y += height;
+        top
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if let Some(code) = &mut self.code {
+            code.push_str(text);
+            return;
+        }
+        if let Some(image) = &mut self.image {
+            image.alt.push_str(text);
+            return;
+        }
+        let code = false;
+        self.push_run(text, Font::styled(self.bold > 0 || self.heading.is_some(), self.italic > 0, code));
+    }
+
+    fn push_run(&mut self, text: &str, font: Font) {
+        let link = self.links.last().cloned();
+        let color = if link.is_some() {
+            LINK
+        } else if self.heading.is_some() {
+            HEADING
+        } else {
+            TEXT
+        };
+        self.runs.push(Run { text: text.to_string(), font, color, link, strike: self.strike > 0 });
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.push_text(&text),
+            Event::Code(code) => self.push_run(&code, Font::styled(self.bold > 0, false, true)),
+            Event::SoftBreak => self.push_text(" "),
+            Event::HardBreak => self.push_text("\n"),
+            Event::Html(html) => {
+                if is_page_break(&html) {
+                    self.flush();
+                    self.page_break();
+                } else if html.trim_start().to_ascii_lowercase().starts_with("<br") {
+                    self.push_text("\n");
+                }
+            }
+            Event::Rule => {
+                self.flush();
+                self.space(self.settings.font_size * 0.6);
+                let top = self.reserve(self.settings.font_size * 0.6);
+                let (x, width) = (self.left(), self.content_width());
+                self.page().ops.push(Op::Line { x1: x, y1: top, x2: x + width, y2: top, width: 0.75, color: RULE });
+            }
+            Event::FootnoteReference(label) => {
+                let number = self.footnote_number(&label);
+                self.push_run(&format!("[{}]", number), Font::Regular);
+            }
+            Event::TaskListMarker(checked) => {
+                self.push_run(if checked { "[x] " } else { "[ ] " }, Font::Mono);
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.flush(),
+            Tag::Heading(level, _, _) => {
+                self.flush();
+                if self.settings.break_before[level as usize - 1] {
+                    self.page_break();
+                } else {
+                    self.space(self.settings.font_size * 1.2);
+                }
+                self.heading = Some(level);
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                self.quotes.push(self.left());
+                self.indent += 14.0;
+            }
+            Tag::CodeBlock(_) => {
+                self.flush();
+                self.code = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(counter)) => {
+                        *counter += 1;
+                        format!("{}.", *counter - 1)
+                    }
+                    _ => "\u{2022}".to_string(),
+                };
+                self.marker = Some(marker);
+                self.indent += 18.0;
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.flush();
+                self.marker = Some(format!("[{}]", self.footnote_number(&label)));
+                self.indent += 18.0;
+            }
+            Tag::Table(alignments) => {
+                self.flush();
+                self.table = Some(TableState { alignments, rows: Vec::new(), header_rows: 0 });
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::TableCell => self.runs.clear(),
+            Tag::Emphasis => self.italic += 1,
+            Tag::Strong => self.bold += 1,
+            Tag::Strikethrough => self.strike += 1,
+            Tag::Link(_, dest, _) => self.links.push(dest.to_string()),
+            Tag::Image(_, dest, title) => {
+                self.image = Some(ImageState { dest: dest.to_string(), title: title.to_string(), alt: String::new() });
+            }
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                let text: String = self.runs.iter().map(|r| r.text.as_str()).collect();
+                if matches!(text.trim(), "\\newpage" | "\\pagebreak") {
+                    self.runs.clear();
+                    self.page_break();
+                    return;
+                }
+                self.flush();
+                self.space(self.settings.font_size * 0.6);
+            }
+            Tag::Heading(level, _, _) => {
+                let index = level as usize - 1;
+                let size = self.settings.font_size * [2.0, 1.6, 1.3, 1.15, 1.0, 0.9][index];
+                let runs = std::mem::take(&mut self.runs);
+                let lines = wrap(&runs, self.fonts, self.content_width(), size);
+                // Keep the heading with the first lines of what follows
+                self.ensure(lines.len() as f32 * size * 1.3 + self.settings.font_size * 3.0);
+                self.draw_lines(&lines, size, size * 1.3, Alignment::Left);
+                self.heading = None;
+                if self.settings.break_after[index] {
+                    self.page_break();
+                } else {
+                    self.space(self.settings.font_size * 0.4);
+                }
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                self.indent -= 14.0;
+                self.quotes.pop();
+                self.space(self.settings.font_size * 0.6);
+            }
+            Tag::CodeBlock(_) => {
+                let code = self.code.take().unwrap_or_default();
+                self.code_block(&code);
+            }
+            Tag::List(_) => {
+                self.flush();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.space(self.settings.font_size * 0.6);
+                }
+            }
+            Tag::Item | Tag::FootnoteDefinition(_) => {
+                self.flush();
+                self.marker = None;
+                self.indent -= 18.0;
+            }
+            Tag::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.header_rows = table.rows.len();
+                }
+            }
+            Tag::TableCell => {
+                let runs = std::mem::take(&mut self.runs);
+                if let Some(row) = self.table.as_mut().and_then(|t| t.rows.last_mut()) {
+                    row.push(runs);
+                }
+            }
+            Tag::Table(_) => {
+                if let Some(table) = self.table.take() {
+                    self.table_block(table);
+                }
+            }
+            Tag::TableRow => {}
+            Tag::Emphasis => self.italic -= 1,
+            Tag::Strong => self.bold -= 1,
+            Tag::Strikethrough => self.strike -= 1,
+            Tag::Link(..) => {
+                self.links.pop();
+            }
+            Tag::Image(..) => {
+                if let Some(image) = self.image.take() {
+                    self.image_block(image);
+                }
+            }
+        }
+    }
+
+    fn footnote_number(&mut self, label: &str) -> usize {
+        let next = self.footnotes.len() + 1;
+        *self.footnotes.entry(label.to_string()).or_insert(next)
+    }
+
+    /// Lay out pending inline text as a paragraph
+    fn flush(&mut self) {
+        if self.runs.iter().all(|r| r.text.trim().is_empty()) {
+            self.runs.clear();
+            return;
+        }
+        let size = self.settings.font_size;
+        let runs = std::mem::take(&mut self.runs);
+        let lines = wrap(&runs, self.fonts, self.content_width(), size);
+        self.draw_lines(&lines, size, size * 1.45, Alignment::Left);
+    }
+
+    fn draw_lines(&mut self, lines: &[Vec<Piece>], size: f32, line_height: f32, alignment: Alignment) {
+        for line in lines {
+            let top = self.reserve(line_height);
+            let baseline = top + line_height * 0.75;
+            if let Some(marker) = self.marker.take() {
+                let segments = self.fonts.encode(&marker, Font::Regular);
+                let marker_width: f32 = segments.iter().map(|(face, text)| self.fonts.width(*face, text, size)).sum();
+                let x = self.left() - marker_width - 5.0;
+                let fonts = self.fonts;
+                push_text(&mut self.page().ops, fonts, segments, x, baseline, size, TEXT);
+            }
+            let (x, width) = (self.left(), self.content_width());
+            self.draw_line(line, x, width, baseline, size, alignment);
+        }
+    }
+
+    fn draw_line(&mut self, line: &[Piece], x: f32, width: f32, baseline: f32, size: f32, alignment: Alignment) {
+        let line_width: f32 = line.iter().map(|p| p.width).sum();
+        let mut x = match alignment {
+            Alignment::Center => x + (width - line_width) / 2.0,
+            Alignment::Right => x + width - line_width,
+            _ => x,
+        };
+        // Adjacent pieces in the same style are drawn as one string
+        let mut merged: Vec<Piece> = Vec::new();
+        for piece in line {
+            match merged.last_mut() {
+                Some(last)
+                    if last.face == piece.face
+                        && last.color == piece.color
+                        && last.link == piece.link
+                        && last.strike == piece.strike =>
+                {
+                    last.text.extend_from_slice(&piece.text);
+                    last.width += piece.width;
+                }
+                _ => merged.push(piece.clone()),
+            }
+        }
+
+        let page = self.pages.last_mut().unwrap();
+        for piece in merged {
+            if piece.strike {
+                let y = baseline - size * 0.3;
+                page.ops.push(Op::Line { x1: x, y1: y, x2: x + piece.width, y2: y, width: 0.6, color: piece.color });
+            }
+            if let Some(link) = piece.link.filter(|l| l.contains(':')) {
+                page.links.push((x, baseline - size, piece.width, size * 1.25, link));
+            }
+            page.ops.push(Op::Text { x, y: baseline, face: piece.face, size, color: piece.color, text: piece.text });
+            x += piece.width;
+        }
+    }
+
+    fn code_block(&mut self, code: &str) {
+        let size = self.settings.font_size * 0.85;
+        let line_height = size * 1.4;
+        let padding = 6.0;
+        let width = self.content_width();
+        let columns = (((width - 2.0 * padding) / (0.6 * size)) as usize).max(1);
+
+        let mut lines = Vec::new();
+        for line in code.strip_suffix('\n').unwrap_or(code).split('\n') {
+            let chars: Vec<char> = line.replace('\t', "    ").chars().collect();
+            if chars.is_empty() {
+                lines.push(String::new());
+            }
+            lines.extend(chars.chunks(columns).map(|chunk| chunk.iter().collect::<String>()));
+        }
+
+        let count = lines.len();
+        for (i, line) in lines.iter().enumerate() {
+            let top_padding = if i == 0 { padding } else { 0.0 };
+            let height = line_height + top_padding + if i + 1 == count { padding } else { 0.0 };
+            let top = self.reserve(height);
+            let x = self.left();
+            let segments = self.fonts.encode(line, Font::Mono);
+            let fonts = self.fonts;
+            let page = self.page();
+            page.ops.push(Op::Fill { x, y: top, width, height, color: CODE_BACKGROUND });
+            let baseline = top + top_padding + line_height * 0.75;
+            push_text(&mut page.ops, fonts, segments, x + padding, baseline, size, TEXT);
+        }
+        self.space(self.settings.font_size * 0.8);
+    }
+
+    fn table_block(&mut self, table: TableState) {
+        let size = self.settings.font_size * 0.9;
+        let line_height = size * 1.35;
+        let padding = 4.0;
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+
+        let header_rows = table.header_rows;
+        let rows: Vec<Vec<Vec<Run>>> = table
+            .rows
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut row)| {
+                row.resize(columns, Vec::new());
+                if index < header_rows {
+                    for run in row.iter_mut().flatten() {
+                        run.font = run.font.bold();
+                    }
+                }
+                row
+            })
+            .collect();
+
+        // Natural and minimum (longest word) column widths
+        let fonts = self.fonts;
+        let mut natural = vec![0.0f32; columns];
+        let mut minimum = vec![0.0f32; columns];
+        for row in &rows {
+            for (column, cell) in row.iter().enumerate() {
+                let words: Vec<f32> = cell
+                    .iter()
+                    .flat_map(|run| {
+                        run.text.split(' ').map(move |word| {
+                            Piece::split(word, run, fonts, size).iter().map(|p| p.width).sum::<f32>()
+                        })
+                    })
+                    .collect();
+                let space = Font::Regular.width(b" ", size);
+                let total: f32 = words.iter().map(|width| width + space).sum();
+                let longest = words.iter().copied().fold(0.0, f32::max);
+                natural[column] = natural[column].max(total + 2.0 * padding);
+                minimum[column] = minimum[column].max(longest + 2.0 * padding);
+            }
+        }
+
+        let available = self.content_width();
+        let natural_total: f32 = natural.iter().sum();
+        let minimum_total: f32 = minimum.iter().sum();
+        let widths: Vec<f32> = if natural_total <= available {
+            natural
+        } else if minimum_total < available {
+            let extra = (available - minimum_total) / (natural_total - minimum_total);
+            minimum.iter().zip(&natural).map(|(min, nat)| min + (nat - min) * extra).collect()
+        } else {
+            natural.iter().map(|width| width * available / natural_total).collect()
+        };
+
+        let layout_row = |row: &Vec<Vec<Run>>| -> Vec<Vec<Vec<Piece>>> {
+            row.iter()
+                .zip(&widths)
+                .map(|(cell, width)| wrap(cell, fonts, width - 2.0 * padding, size))
+                .collect()
+        };
+        let header: Vec<_> = rows[..header_rows].iter().map(layout_row).collect();
+
+        for (index, row) in rows.iter().enumerate() {
+            let cells = layout_row(row);
+            let height = |cells: &Vec<Vec<Vec<Piece>>>| {
+                cells.iter().map(Vec::len).max().unwrap_or(1) as f32 * line_height + 2.0 * padding
+            };
+            if self.y + height(&cells) > self.bottom() && !self.at_page_top() {
+                self.new_page();
+                // Repeat the header row on continuation pages
+                if index >= header_rows {
+                    for cells in &header {
+                        self.table_row(cells, &widths, &table.alignments, height(cells), true, size, padding);
+                    }
+                }
+            }
+            self.table_row(&cells, &widths, &table.alignments, height(&cells), index < header_rows, size, padding);
+        }
+        self.space(self.settings.font_size * 0.8);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn table_row(
+        &mut self,
+        cells: &[Vec<Vec<Piece>>],
+        widths: &[f32],
+        alignments: &[Alignment],
+        height: f32,
+        header: bool,
+        size: f32,
+        padding: f32,
+    ) {
+        let line_height = size * 1.35;
+        let top = self.reserve(height);
+        let mut x = self.left();
+        for (column, (lines, width)) in cells.iter().zip(widths).enumerate() {
+            let page = self.pages.last_mut().unwrap();
+            if header {
+                page.ops.push(Op::Fill { x, y: top, width: *width, height, color: TABLE_HEADER });
+            }
+            for (x1, y1, x2, y2) in [
+                (x, top, x + width, top),
+                (x, top + height, x + width, top + height),
+                (x, top, x, top + height),
+                (x + width, top, x + width, top + height),
+            ] {
+                page.ops.push(Op::Line { x1, y1, x2, y2, width: 0.5, color: RULE });
+            }
+            let alignment = alignments.get(column).copied().unwrap_or(Alignment::None);
+            for (i, line) in lines.iter().enumerate() {
+                let baseline = top + padding + i as f32 * line_height + line_height * 0.75;
+                self.draw_line(line, x + padding, width - 2.0 * padding, baseline, size, alignment);
+            }
+            x += width;
+        }
+    }
+
+    fn image_block(&mut self, image: ImageState) {
+        let Some(index) = self.load_image(&image.dest) else {
+            let alt = if image.alt.is_empty() { image.dest.as_str() } else { image.alt.as_str() };
+            let text = format!("[Image: {}]", alt);
+            self.runs.push(Run { text, font: Font::Italic, color: MUTED, link: None, strike: false });
+            return;
+        };
+
+        // Text before the image in the same paragraph goes above it
+        self.flush();
+        let data = &self.images[index];
+        let (mut width, mut height) = (data.width as f32 * 0.75, data.height as f32 * 0.75);
+        let max_height = (self.bottom() - self.settings.margins.top) * 0.9;
+        let scale = (self.content_width() / width).min(max_height / height).min(1.0);
+        width *= scale;
+        height *= scale;
+
+        self.space(self.settings.font_size * 0.3);
+        let top = self.reserve(height);
+        let x = self.left() + (self.content_width() - width) / 2.0;
+        self.page().ops.push(Op::Image { index, x, y: top, width, height });
+
+        if !image.title.is_empty() {
+            let size = self.settings.font_size * 0.9;
+            let caption = Run { text: image.title, font: Font::Italic, color: MUTED, link: None, strike: false };
+            let lines = wrap(&[caption], self.fonts, self.content_width(), size);
+            self.space(size * 0.3);
+            self.draw_lines(&lines, size, size * 1.4, Alignment::Center);
+        }
+        self.space(self.settings.font_size * 0.3);
+    }
+
+    fn load_image(&mut self, dest: &str) -> Option<usize> {
+        let path = dest.strip_prefix("./").unwrap_or(dest);
+        if let Some(index) = self.image_indices.get(path) {
+            return *index;
+        }
+        let index = self.document.images.get(path).and_then(|data| decode_image(path, data)).map(|image| {
+            self.images.push(image);
+            self.images.len() - 1
+        });
+        self.image_indices.insert(path.to_string(), index);
+        index
+    }
+}
+
+fn write_pdf(layout: &Layout, settings: &Settings, document: &MdzDocument, title: &str) -> Result<Vec<u8>> {
+    let mut next_id = 1;
+    let mut allocate = || {
+        let id = Ref::new(next_id);
+        next_id += 1;
+        id
+    };
+
+    let catalog_id = allocate();
+    let tree_id = allocate();
+    let info_id = allocate();
+    let font_ids: Vec<Ref> = Font::ALL.iter().map(|_| allocate()).collect();
+    // Type0 font, CIDFont, descriptor, font file and ToUnicode map
+    let embedded_ids: Vec<Option<[Ref; 5]>> = layout
+        .fonts
+        .embedded
+        .iter()
+        .map(|font| (!font.used.borrow().is_empty()).then(|| [(); 5].map(|_| allocate())))
+        .collect();
+    let image_ids: Vec<(Ref, Option<Ref>)> = layout
+        .images
+        .iter()
+        .map(|image| (allocate(), image.alpha.as_ref().map(|_| allocate())))
+        .collect();
+    let page_ids: Vec<(Ref, Ref)> = layout.pages.iter().map(|_| (allocate(), allocate())).collect();
+    let image_names: Vec<String> = (1..=layout.images.len()).map(|i| format!("Im{}", i)).collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(tree_id);
+    pdf.pages(tree_id)
+        .kids(page_ids.iter().map(|(page, _)| *page))
+        .count(page_ids.len() as i32);
+
+    let mut info = pdf.document_info(info_id);
+    if !title.is_empty() {
+        info.title(TextStr(title));
+    }
+    if let Some(author) = &document.metadata.author {
+        info.author(TextStr(author));
+    }
+    info.creator(TextStr(concat!("mdz-core ", env!("CARGO_PKG_VERSION"))));
+    info.finish();
+
+    for (font, id) in Font::ALL.iter().zip(&font_ids) {
+        pdf.type1_font(*id)
+            .base_font(font.base_font())
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+    for (index, (font, ids)) in layout.fonts.embedded.iter().zip(&embedded_ids).enumerate() {
+        if let Some(ids) = ids {
+            write_embedded_font(&mut pdf, font, index, *ids);
+        }
+    }
+
+    for (image, (id, mask_id)) in layout.images.iter().zip(&image_ids) {
+        let mut xobject = pdf.image_xobject(*id, &image.data);
+        xobject.filter(image.filter);
+        xobject.width(image.width as i32);
+        xobject.height(image.height as i32);
+        match image.components {
+            1 => xobject.color_space().device_gray(),
+            4 => xobject.color_space().device_cmyk(),
+            _ => xobject.color_space().device_rgb(),
+        };
+        xobject.bits_per_component(8);
+        if let Some(mask_id) = mask_id {
+            xobject.s_mask(*mask_id);
+        }
+        xobject.finish();
+
+        if let (Some(alpha), Some(mask_id)) = (&image.alpha, mask_id) {
+            let mut mask = pdf.image_xobject(*mask_id, alpha);
+            mask.filter(Filter::FlateDecode);
+            mask.width(image.width as i32);
+            mask.height(image.height as i32);
+            mask.color_space().device_gray();
+            mask.bits_per_component(8);
+        }
+    }
+
+    let height = settings.page.height;
+    for (page, (page_id, content_id)) in layout.pages.iter().zip(&page_ids) {
+        let mut writer = pdf.page(*page_id);
+        writer.media_box(Rect::new(0.0, 0.0, settings.page.width, height));
+        writer.parent(tree_id);
+        writer.contents(*content_id);
+        let mut resources = writer.resources();
+        let mut fonts = resources.fonts();
+        for (font, id) in Font::ALL.iter().zip(&font_ids) {
+            fonts.pair(font.resource_name(), *id);
+        }
+        for (index, ids) in embedded_ids.iter().enumerate() {
+            if let Some([id, ..]) = ids {
+                fonts.pair(Name(&Face::Embedded(index).resource_name()), *id);
+            }
+        }
+        fonts.finish();
+        let mut xobjects = resources.x_objects();
+        for (name, (id, _)) in image_names.iter().zip(&image_ids) {
+            xobjects.pair(Name(name.as_bytes()), *id);
+        }
+        xobjects.finish();
+        resources.finish();
+
+        if !page.links.is_empty() {
+            let mut annotations = writer.annotations();
+            for (x, y, width, link_height, uri) in &page.links {
+                let mut annotation = annotations.push();
+                annotation.subtype(AnnotationType::Link);
+                annotation.rect(Rect::new(*x, height - y - link_height, x + width, height - y));
+                annotation.border(0.0, 0.0, 0.0, None);
+                annotation.action().action_type(ActionType::Uri).uri(Str(uri.as_bytes()));
+            }
+        }
+        writer.finish();
+
+        let mut content = Content::new();
+        for op in &page.ops {
+            match op {
+                Op::Text { x, y, face, size, color, text } => {
+                    content.set_fill_rgb(color.0, color.1, color.2);
+                    content.begin_text();
+                    content.set_font(Name(&face.resource_name()), *size);
+                    content.next_line(*x, height - y);
+                    content.show(Str(text));
+                    content.end_text();
+                }
+                Op::Fill { x, y, width, height: fill_height, color } => {
+                    content.set_fill_rgb(color.0, color.1, color.2);
+                    content.rect(*x, height - y - fill_height, *width, *fill_height);
+                    content.fill_nonzero();
+                }
+                Op::Line { x1, y1, x2, y2, width, color } => {
+                    content.set_stroke_rgb(color.0, color.1, color.2);
+                    content.set_line_width(*width);
+                    content.move_to(*x1, height - y1);
+                    content.line_to(*x2, height - y2);
+                    content.stroke();
+                }
+                Op::Image { index, x, y, width, height: image_height } => {
+                    content.save_state();
+                    content.transform([*width, 0.0, 0.0, *image_height, *x, height - y - image_height]);
+                    content.x_object(Name(image_names[*index].as_bytes()));
+                    content.restore_state();
+                }
+            }
+        }
+        pdf.stream(*content_id, &deflate(&content.finish())).filter(Filter::FlateDecode);
+    }
+
+    Ok(pdf.finish())
+}
+
+/// Write an embedded font as a Type0 font addressing glyphs by id
+/// (`Identity-H`), with a ToUnicode map so its text can be copied
+fn write_embedded_font(pdf: &mut Pdf, font: &EmbeddedFont, index: usize, ids: [Ref; 5]) {
+    let [type0_id, cid_id, descriptor_id, file_id, cmap_id] = ids;
+    let name = font.postscript_name(index);
+    let base_font = Name(name.as_bytes());
+    let face = &font.face;
+    let cff = face.tables().cff.is_some();
+    let used = font.used.borrow();
+    let system_info = SystemInfo { registry: Str(b"Adobe"), ordering: Str(b"Identity"), supplement: 0 };
+    // Font units to 1/1000 em
+    let scale = |value: f32| value * 1000.0 / face.units_per_em() as f32;
+
+    pdf.type0_font(type0_id)
+        .base_font(base_font)
+        .encoding_predefined(Name(b"Identity-H"))
+        .descendant_font(cid_id)
+        .to_unicode(cmap_id);
+
+    let mut cid = pdf.cid_font(cid_id);
+    cid.subtype(if cff { CidFontType::Type0 } else { CidFontType::Type2 });
+    cid.base_font(base_font);
+    cid.system_info(system_info);
+    cid.font_descriptor(descriptor_id);
+    if !cff {
+        cid.cid_to_gid_map_predefined(Name(b"Identity"));
+    }
+    let mut widths = cid.widths();
+    for &glyph in used.keys() {
+        widths.consecutive(glyph, [font.advance(glyph) * 1000.0]);
+    }
+    widths.finish();
+    cid.finish();
+
+    let mut flags = FontFlags::NON_SYMBOLIC;
+    flags.set(FontFlags::FIXED_PITCH, face.is_monospaced());
+    flags.set(FontFlags::ITALIC, face.is_italic());
+    let bbox = face.global_bounding_box();
+    let mut descriptor = pdf.font_descriptor(descriptor_id);
+    descriptor
+        .name(base_font)
+        .flags(flags)
+        .bbox(Rect::new(
+            scale(bbox.x_min as f32),
+            scale(bbox.y_min as f32),
+            scale(bbox.x_max as f32),
+            scale(bbox.y_max as f32),
+        ))
+        .italic_angle(face.italic_angle())
+        .ascent(scale(face.ascender() as f32))
+        .descent(scale(face.descender() as f32))
+        .cap_height(scale(face.capital_height().unwrap_or(face.ascender()) as f32))
+        .stem_v(if face.is_bold() { 120.0 } else { 80.0 });
+    if cff {
+        descriptor.font_file3(file_id);
+    } else {
+        descriptor.font_file2(file_id);
+    }
+    descriptor.finish();
+
+    let data = deflate(font.data);
+    let mut file = pdf.stream(file_id, &data);
+    file.filter(Filter::FlateDecode);
+    if cff {
+        file.pair(Name(b"Subtype"), Name(b"OpenType"));
+    } else {
+        file.pair(Name(b"Length1"), font.data.len() as i32);
+    }
+    file.finish();
+
+    let mut cmap = UnicodeCmap::new(Name(b"Custom"), system_info);
+    for (&glyph, &c) in used.iter() {
+        cmap.pair(glyph, c);
+    }
+    pdf.cmap(cmap_id, &cmap.finish());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_markdown(markdown: &str) -> PdfOutput {
+        let document = MdzDocument::new(markdown.to_string());
+        render(&document, MarkdownDialect::default(), &PdfOptions::default(), None).unwrap()
+    }
+
+    #[test]
+    fn page_sizes_and_margins_parse_css_values() {
+        assert_eq!("letter landscape".parse::<PageSize>().unwrap(), PageSize::LETTER.landscape());
+        assert_eq!("A4".parse::<PageSize>().unwrap(), PageSize::A4);
+        assert!("huge".parse::<PageSize>().is_err());
+
+        let margins: Margins = "1in 36pt".parse().unwrap();
+        assert_eq!((margins.top, margins.right, margins.bottom, margins.left), (72.0, 36.0, 72.0, 36.0));
+        assert_eq!(parse_length("2.54cm"), Some(72.0));
+        assert_eq!(parse_length("12em"), None);
+    }
+
+    #[test]
+    fn print_stylesheet_sets_page_and_breaks() {
+        let style = PrintStyle::parse(
+            "@media print { @page { size: A5; margin: 10mm } body { font-size: 9pt } }\nh2 { break-before: page }",
+        );
+        assert_eq!(style.page_size, Some(PageSize::A5));
+        assert_eq!(style.font_size, Some(9.0));
+        assert!(style.break_before[1] && !style.break_before[0]);
+    }
+
+    #[test]
+    fn invisible_characters_are_dropped() {
+        let fonts = Fonts::new([]);
+        let encoded = fonts.encode("a\u{200D}b\u{FE0F}\u{1}c", Font::Regular);
+        assert_eq!(encoded, [(Face::Standard(Font::Regular), b"abc".to_vec())]);
+        assert!(fonts.missing.borrow().is_empty());
+    }
+
+    #[test]
+    fn missing_glyphs_are_warnings() {
+        let output = render_markdown("# Title\n\nLatin text, Greek αβ\n");
+        assert!(output.data.starts_with(b"%PDF"));
+        assert_eq!(output.warnings.len(), 1);
+        assert!(output.warnings[0].contains("U+03B1"));
+        assert!(render_markdown("Caf\u{E9} \u{2014} \u{20AC}5\n").warnings.is_empty());
+    }
+}
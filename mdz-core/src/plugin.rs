@@ -0,0 +1,154 @@
+use crate::admonition::AdmonitionKind;
+use crate::diagram;
+use crate::highlight::FenceInfo;
+use crate::{RenderOptions, Result};
+use pulldown_cmark::{Event, Tag};
+
+/// Extension point for [`MdzRenderer`](crate::MdzRenderer)
+///
+/// Plugins claim fenced code blocks by language and may rewrite the event
+/// stream before it is turned into HTML. Plugins run in registration order;
+/// the first one to render a code block wins.
+pub trait RenderPlugin: Send + Sync {
+    /// Plugin name
+    fn name(&self) -> &str;
+
+    /// Fenced code block languages handled by this plugin (case-insensitive)
+    fn languages(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Render a claimed code block to HTML, or `None` to fall back to the next handler
+    fn render_code_block(&self, _block: &CodeBlock) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Transform the event stream after code blocks have been rendered
+    ///
+    /// `options.dialect` is always set to the dialect the document is read with.
+    fn transform<'a>(&self, events: Vec<Event<'a>>, _options: &RenderOptions) -> Result<Vec<Event<'a>>> {
+        Ok(events)
+    }
+
+    /// Stylesheet added to the document head
+    fn css(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Fenced code block passed to [`RenderPlugin::render_code_block`]
+#[derive(Debug, Clone, Copy)]
+pub struct CodeBlock<'c> {
+    pub fence: &'c FenceInfo,
+    pub code: &'c str,
+    /// Position among the document's code blocks, starting at 0
+    pub index: usize,
+    pub options: &'c RenderOptions,
+}
+
+/// Renders Graphviz `dot` blocks to inline SVG
+pub struct DiagramPlugin;
+
+impl RenderPlugin for DiagramPlugin {
+    fn name(&self) -> &str {
+        "diagrams"
+    }
+
+    fn languages(&self) -> &[&str] {
+        &["dot", "graphviz"]
+    }
+
+    /// Blocks already pre-rendered into the archive render to nothing, as the
+    /// image reference follows them; diagrams that fail to render are shown as code.
+    /// Layout panics are not caught, see [`diagram::render_dot`].
+    fn render_code_block(&self, block: &CodeBlock) -> Result<Option<String>> {
+        if !block.options.diagrams {
+            return Ok(None);
+        }
+        if diagram::rendered_path(block.fence).is_some() {
+            return Ok(Some(String::new()));
+        }
+
+        let Ok(svg) = diagram::render_dot(block.code) else {
+            return Ok(None);
+        };
+        let prefix = format!("diagram{}-", block.index + 1);
+        Ok(Some(format!("<figure class=\"diagram\">\n{}\n</figure>\n", diagram::inline_svg(&svg, &prefix))))
+    }
+}
+
+/// Turns GitHub alert blockquotes (`> [!NOTE]`, `> [!WARNING]`, ...) into admonitions
+pub struct AdmonitionPlugin;
+
+impl RenderPlugin for AdmonitionPlugin {
+    fn name(&self) -> &str {
+        "admonitions"
+    }
+
+    fn transform<'a>(&self, events: Vec<Event<'a>>, options: &RenderOptions) -> Result<Vec<Event<'a>>> {
+        if !options.dialect.unwrap_or_default().admonitions {
+            return Ok(events);
+        }
+
+        let mut output = Vec::with_capacity(events.len());
+        // Whether each open blockquote was turned into an admonition
+        let mut quotes: Vec<bool> = Vec::new();
+        let mut index = 0;
+
+        while index < events.len() {
+            match &events[index] {
+                Event::Start(Tag::BlockQuote) => match admonition_marker(&events[index + 1..]) {
+                    Some((kind, consumed, continues)) => {
+                        output.push(Event::Html(kind.open_html().into()));
+                        if continues {
+                            output.push(Event::Start(Tag::Paragraph));
+                        }
+                        quotes.push(true);
+                        index += 1 + consumed;
+                        continue;
+                    }
+                    None => {
+                        quotes.push(false);
+                        output.push(Event::Start(Tag::BlockQuote));
+                    }
+                },
+                Event::End(Tag::BlockQuote) => {
+                    if quotes.pop() == Some(true) {
+                        output.push(Event::Html("</div>\n".into()));
+                    } else {
+                        output.push(Event::End(Tag::BlockQuote));
+                    }
+                }
+                other => output.push(other.clone()),
+            }
+            index += 1;
+        }
+
+        Ok(output)
+    }
+}
+
+/// Find a `[!TYPE]` line opening a blockquote's first paragraph
+///
+/// Returns the kind, the number of events making up the marker and whether
+/// the paragraph continues after it.
+fn admonition_marker(events: &[Event]) -> Option<(AdmonitionKind, usize, bool)> {
+    if !matches!(events.first(), Some(Event::Start(Tag::Paragraph))) {
+        return None;
+    }
+
+    let mut marker = String::new();
+    for (i, event) in events.iter().enumerate().skip(1) {
+        match event {
+            Event::Text(text) => marker.push_str(text),
+            Event::SoftBreak | Event::HardBreak => {
+                return AdmonitionKind::from_marker(&marker).map(|kind| (kind, i + 1, true));
+            }
+            Event::End(Tag::Paragraph) => {
+                return AdmonitionKind::from_marker(&marker).map(|kind| (kind, i + 1, false));
+            }
+            _ => return None,
+        }
+    }
+    None
+}
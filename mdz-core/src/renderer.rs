@@ -1,12 +1,47 @@
-use crate::{MdzDocument, Result};
-use pulldown_cmark::{Parser, Options, html};
+use crate::anchor::{self, Heading};
+use crate::css::{self, CssTarget};
+use crate::docx;
+use crate::email::{self, EmailMessage};
+use crate::epub;
+use crate::figure;
+use crate::highlight::{self, FenceInfo};
+use crate::math::{self, MathExpander};
+use crate::mhtml;
+use crate::pdf::{self, PdfOptions, PdfOutput};
+use crate::plugin::{AdmonitionPlugin, CodeBlock, DiagramPlugin, RenderPlugin};
+use crate::sanitize::{self, HtmlPolicy, HtmlSanitizer};
+use crate::site::{self, StaticSite};
+use crate::standalone::{self, EmbeddedImages};
+use crate::template::HtmlShell;
+use crate::terminal::TerminalOptions;
+use crate::text::{self, TextOptions};
+use crate::{svg, MarkdownDialect, MdzDocument, MdzError, Result};
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{html, CodeBlockKind, Event, HeadingLevel, LinkType, Parser, Tag};
 use std::collections::HashMap;
+use std::str::FromStr;
 use base64::engine::general_purpose::STANDARD as base64_engine;
 use base64::Engine;
 
+/// Content-Security-Policy for hardened output: inline styles and data: images/fonts only
+const HARDENED_CSP: &str =
+    "default-src 'none'; style-src 'unsafe-inline'; img-src data:; font-src data:; base-uri 'none'; form-action 'none'";
+
+/// Styles for the table of contents and heading permalinks
+const HEADING_CSS: &str = r#"
+nav.toc { margin: 1em 0; padding: 0.5em 1em; border-left: 4px solid #bdc3c7; }
+nav.toc ul { list-style: none; margin: 0; padding-left: 1.2em; }
+nav.toc > ul { padding-left: 0; }
+.heading-anchor { margin-left: 0.3em; color: #bdc3c7; opacity: 0; text-decoration: none; }
+h1:hover .heading-anchor, h2:hover .heading-anchor, h3:hover .heading-anchor,
+h4:hover .heading-anchor, h5:hover .heading-anchor, h6:hover .heading-anchor,
+.heading-anchor:focus { opacity: 1; }
+"#;
+
 /// MDZ document renderer
 pub struct MdzRenderer {
     options: RenderOptions,
+    plugins: Vec<Box<dyn RenderPlugin>>,
 }
 
 /// Rendering options
@@ -16,6 +51,68 @@ pub struct RenderOptions {
     pub base64_images: bool,
     pub custom_css: Option<String>,
     pub html_title: Option<String>,
+    /// Rewrite archive-relative `url()` values in document CSS to data URLs
+    pub inline_css_urls: bool,
+    /// How SVG images are processed before embedding
+    pub svg_mode: SvgMode,
+    /// How raw HTML blocks and inline HTML are treated
+    pub html_policy: HtmlPolicy,
+    /// Emit a strict Content-Security-Policy and no-referrer links
+    pub hardened: bool,
+    /// Theme for server-side syntax highlighting, `None` to disable; unknown
+    /// themes fall back to [`highlight::DEFAULT_THEME`]
+    pub highlight_theme: Option<String>,
+    /// Number lines in all code blocks (fences may override with `{linenos=false}`)
+    pub line_numbers: bool,
+    /// Add a hover permalink (`#`) to every heading
+    pub heading_permalinks: bool,
+    /// Insert a table of contents after the title when the document has no `[TOC]` placeholder
+    pub toc: bool,
+    /// Deepest heading level listed in the table of contents
+    pub toc_depth: u32,
+    /// Markdown dialect, overriding the one declared in the document's metadata
+    pub dialect: Option<MarkdownDialect>,
+    /// Render ```` ```dot ```` code blocks to inline SVG
+    pub diagrams: bool,
+    /// Page setup for [`MdzRenderer::render_pdf`]
+    pub pdf: PdfOptions,
+    /// Add a content hash to asset file names written by [`MdzRenderer::render_site`]
+    pub hash_asset_names: bool,
+    /// How [`MdzRenderer::render_standalone_markdown`] embeds images
+    pub embedded_images: EmbeddedImages,
+    /// Layout for [`MdzRenderer::render_text`]
+    pub text: TextOptions,
+    /// Layout for [`MdzRenderer::render_terminal`]
+    pub terminal: TerminalOptions,
+}
+
+/// Processing applied to SVG images embedded as data URLs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SvgMode {
+    /// Embed SVG markup unchanged
+    #[default]
+    Embed,
+    /// Strip scripts, event handlers, foreignObject and external references
+    Sanitize,
+    /// Render to PNG (requires the `svg-raster` feature)
+    Rasterize,
+}
+
+impl FromStr for SvgMode {
+    type Err = MdzError;
+
+    /// Parse a mode name: `embed`, `sanitize` or `rasterize`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "embed" => Ok(Self::Embed),
+            "sanitize" => Ok(Self::Sanitize),
+            "rasterize" => Ok(Self::Rasterize),
+            _ => Err(MdzError::Parse(format!(
+                "Unknown SVG mode '{}' (expected embed, sanitize or rasterize)",
+                s
+            ))),
+        }
+    }
 }
 
 impl Default for RenderOptions {
@@ -25,6 +122,22 @@ impl Default for RenderOptions {
             base64_images: true,
             custom_css: None,
             html_title: None,
+            inline_css_urls: false,
+            svg_mode: SvgMode::Embed,
+            html_policy: HtmlPolicy::AllowAll,
+            hardened: false,
+            highlight_theme: Some(highlight::DEFAULT_THEME.to_string()),
+            line_numbers: false,
+            heading_permalinks: false,
+            toc: false,
+            toc_depth: 3,
+            dialect: None,
+            diagrams: true,
+            pdf: PdfOptions::default(),
+            hash_asset_names: false,
+            embedded_images: EmbeddedImages::Inline,
+            text: TextOptions::default(),
+            terminal: TerminalOptions::default(),
         }
     }
 }
@@ -32,118 +145,448 @@ impl Default for RenderOptions {
 impl MdzRenderer {
     /// Create new renderer with default options
     pub fn new() -> Self {
-        Self {
-            options: RenderOptions::default(),
-        }
+        Self::with_options(RenderOptions::default())
     }
 
     /// Create renderer with custom options
     pub fn with_options(options: RenderOptions) -> Self {
-        Self { options }
+        Self {
+            options,
+            plugins: vec![Box::new(DiagramPlugin), Box::new(AdmonitionPlugin)],
+        }
+    }
+
+    /// Register a plugin, run after the ones already registered
+    pub fn add_plugin<P: RenderPlugin + 'static>(&mut self, plugin: P) -> &mut Self {
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+
+    /// Options the renderer was created with
+    pub fn options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    /// Names of the registered plugins
+    pub fn plugin_names(&self) -> Vec<&str> {
+        self.plugins.iter().map(|p| p.name()).collect()
     }
 
     /// Render MDZ document to HTML
     pub fn render_html(&self, document: &MdzDocument) -> Result<String> {
-        let processed_markdown = self.process_image_links(&document.content, &document.images)?;
-        
-        // Configure markdown parser
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-        options.insert(Options::ENABLE_TABLES);
-        options.insert(Options::ENABLE_FOOTNOTES);
-        options.insert(Options::ENABLE_TASKLISTS);
-
-        let parser = Parser::new_ext(&processed_markdown, options);
+        let images = if self.options.base64_images { ImageLinks::Embed } else { ImageLinks::Archive };
+        let body = self.render_body(document, images, 0)?;
+        self.create_html_document(&body.sections.concat(), document, &body.extra_css)
+    }
+
+    /// Render the document body, split into sections before top-level headings
+    /// up to `split_level` (0 for a single section)
+    pub(crate) fn render_body(&self, document: &MdzDocument, images: ImageLinks, split_level: u32) -> Result<RenderedBody<'_>> {
+        let dialect = self.dialect(document);
+        let processed_markdown = self.process_image_links(&document.content, &document.images, dialect, images)?;
+        let plugin_options = RenderOptions {
+            dialect: Some(dialect),
+            ..self.options.clone()
+        };
+        let (processed_markdown, math_spans) = if dialect.math {
+            math::extract(&processed_markdown)
+        } else {
+            (processed_markdown, Vec::new())
+        };
         
+        let options = dialect.parser_options();
+
+        let mut sanitizer = HtmlSanitizer::new(&self.options.html_policy, self.options.hardened);
+        let mut math = MathExpander::new(&math_spans, math::render_span);
+        let parser = Parser::new_ext(&processed_markdown, options)
+            .filter_map(|event| sanitizer.process(event))
+            .flat_map(|event| math.process(event))
+            .map(|event| match event {
+                Event::Start(Tag::Link(kind, dest, title)) if self.options.hardened => {
+                    Event::Html(Self::hardened_link(kind, &dest, &title).into())
+                }
+                other => other,
+            });
+        let parsed: Vec<Event> = if dialect.figures {
+            figure::apply(parser.collect())
+        } else {
+            parser.collect()
+        };
+
+        // Render code blocks ourselves for highlighting and line numbers
+        let mut events = Vec::new();
+        let mut code_block: Option<(FenceInfo, String)> = None;
+        let mut has_code_blocks = false;
+        let mut code_block_count = 0;
+        for event in parsed {
+            match event {
+                Event::Start(Tag::CodeBlock(kind)) => {
+                    let fence = match kind {
+                        CodeBlockKind::Fenced(info) => FenceInfo::parse(&info),
+                        CodeBlockKind::Indented => FenceInfo::parse(""),
+                    };
+                    code_block = Some((fence, String::new()));
+                }
+                Event::Text(text) if code_block.is_some() => {
+                    if let Some((_, code)) = code_block.as_mut() {
+                        code.push_str(&text);
+                    }
+                }
+                Event::End(Tag::CodeBlock(_)) => {
+                    if let Some((fence, code)) = code_block.take() {
+                        let block = CodeBlock {
+                            fence: &fence,
+                            code: &code,
+                            index: code_block_count,
+                            options: &plugin_options,
+                        };
+                        code_block_count += 1;
+                        if let Some(html) = self.render_with_plugins(&block)? {
+                            events.push(Event::Html(html.into()));
+                            continue;
+                        }
+                        let html = highlight::render_code_block(
+                            &code,
+                            &fence,
+                            self.options.highlight_theme.as_deref(),
+                            self.options.line_numbers,
+                        )?;
+                        events.push(Event::Html(html.into()));
+                        has_code_blocks = true;
+                    }
+                }
+                other => events.push(other),
+            }
+        }
+
+        for plugin in &self.plugins {
+            events = plugin.transform(events, &plugin_options)?;
+        }
+
+        let headings = anchor::collect_headings(&events);
+        let section_headings = if split_level > 0 { section_starts(&events, split_level) } else { Vec::new() };
+        let (events, has_toc, heading_positions) = self.link_headings(events, &headings);
+
         // Convert to HTML
-        let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
+        let mut sections = Vec::new();
+        let mut start = 0;
+        let ends = section_headings.iter().map(|&ordinal| heading_positions[ordinal]).chain([events.len()]);
+        for end in ends {
+            if end > start || sections.is_empty() {
+                let mut html_output = String::new();
+                html::push_html(&mut html_output, events[start..end].iter().cloned());
+                sections.push(html_output);
+            }
+            start = end;
+        }
+
+        let mut extra_css = Vec::new();
+        if has_code_blocks {
+            extra_css.push(highlight::CODE_BLOCK_CSS);
+        }
+        if has_toc || self.options.heading_permalinks {
+            extra_css.push(HEADING_CSS);
+        }
+        if !math_spans.is_empty() {
+            extra_css.push(math::MATH_CSS);
+        }
+        extra_css.extend(self.plugins.iter().filter_map(|p| p.css()));
+
+        Ok(RenderedBody { sections, headings, extra_css })
+    }
+
+    /// Render MDZ document to PDF
+    ///
+    /// Page size, margins, body font size and heading page breaks are read
+    /// from `css/print.css` unless set in [`RenderOptions::pdf`].
+    pub fn render_pdf(&self, document: &MdzDocument) -> Result<PdfOutput> {
+        let title = self.options.html_title.as_deref().or(document.metadata.title.as_deref());
+        pdf::render(document, self.dialect(document), &self.options.pdf, title)
+    }
+
+    /// Render MDZ document to an EPUB 3 book, one chapter per top-level H1/H2 section
+    pub fn render_epub(&self, document: &MdzDocument) -> Result<Vec<u8>> {
+        epub::render(self, document)
+    }
+
+    /// Render MDZ document as a static site
+    ///
+    /// Unlike [`render_html`](Self::render_html), images, fonts and stylesheets
+    /// are returned as separate files next to `index.html`.
+    pub fn render_site(&self, document: &MdzDocument) -> Result<StaticSite> {
+        site::render(self, document)
+    }
+
+    /// Render MDZ document as an email with inlined CSS and images as `cid:` attachments
+    pub fn render_email(&self, document: &MdzDocument) -> Result<EmailMessage> {
+        email::render(self, document)
+    }
+
+    /// Render MDZ document to a single-file MHTML web archive with images as `cid:` parts
+    pub fn render_mhtml(&self, document: &MdzDocument) -> Result<Vec<u8>> {
+        mhtml::render(self, document)
+    }
+
+    /// Render MDZ document to a Word (`.docx`) file with embedded images and footnotes
+    pub fn render_docx(&self, document: &MdzDocument) -> Result<Vec<u8>> {
+        let title = self.options.html_title.as_deref().or(document.metadata.title.as_deref());
+        docx::render(document, self.dialect(document), title)
+    }
+
+    /// Render `main.md` as one self-contained Markdown file with images embedded as data URLs
+    pub fn render_standalone_markdown(&self, document: &MdzDocument) -> Result<String> {
+        standalone::render(self, document, self.options.embedded_images)
+    }
 
-        // Wrap in complete HTML document
-        let full_html = self.create_html_document(&html_output, document)?;
+    /// Dialect used to read the document: the configured one, else the declared one
+    pub fn dialect(&self, document: &MdzDocument) -> MarkdownDialect {
+        self.options.dialect.or(document.metadata.dialect).unwrap_or_default()
+    }
+
+    /// Offer a code block to the plugins claiming its language
+    fn render_with_plugins(&self, block: &CodeBlock) -> Result<Option<String>> {
+        let Some(language) = block.fence.language.as_deref() else {
+            return Ok(None);
+        };
+
+        for plugin in &self.plugins {
+            if !plugin.languages().iter().any(|l| l.eq_ignore_ascii_case(language)) {
+                continue;
+            }
+            if let Some(html) = plugin.render_code_block(block)? {
+                return Ok(Some(html));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Give headings their anchor IDs and expand the table of contents
+    ///
+    /// Returns the events, whether a table of contents was inserted and the
+    /// position of each heading in the output.
+    fn link_headings<'a>(&self, events: Vec<Event<'a>>, headings: &[Heading]) -> (Vec<Event<'a>>, bool, Vec<usize>) {
+        let toc = || Event::Html(anchor::render_toc(headings, self.options.toc_depth).into());
+        let has_placeholder = (0..events.len()).any(|i| toc_placeholder(&events[i..]).is_some());
+        let mut output = Vec::with_capacity(events.len() + 1);
+        let mut headings = headings.iter();
+        let mut current: Option<&Heading> = None;
+        let mut has_toc = false;
+        let mut positions = Vec::with_capacity(headings.len());
+        let mut index = 0;
+
+        // Without a placeholder the table goes after a leading title, or at the very top
+        let auto_toc_after_title = self.options.toc
+            && !has_placeholder
+            && matches!(events.first(), Some(Event::Start(Tag::Heading(HeadingLevel::H1, ..))));
+        if self.options.toc && !has_placeholder && !auto_toc_after_title {
+            output.push(toc());
+            has_toc = true;
+        }
+
+        while index < events.len() {
+            if let Some(len) = toc_placeholder(&events[index..]) {
+                output.push(toc());
+                has_toc = true;
+                index += len;
+                continue;
+            }
 
-        Ok(full_html)
+            match &events[index] {
+                Event::Start(Tag::Heading(level, _, classes)) => {
+                    current = headings.next();
+                    positions.push(output.len());
+                    let mut tag = format!("<{}", level);
+                    if let Some(heading) = current {
+                        tag.push_str(" id=\"");
+                        escape_html(&mut tag, &heading.id).unwrap();
+                        tag.push('"');
+                    }
+                    if !classes.is_empty() {
+                        tag.push_str(" class=\"");
+                        escape_html(&mut tag, &classes.join(" ")).unwrap();
+                        tag.push('"');
+                    }
+                    tag.push('>');
+                    output.push(Event::Html(tag.into()));
+                }
+                Event::End(Tag::Heading(level, ..)) => {
+                    let mut tag = String::new();
+                    if let (true, Some(heading)) = (self.options.heading_permalinks, current.take()) {
+                        tag.push_str(" <a class=\"heading-anchor\" href=\"#");
+                        escape_href(&mut tag, &heading.id).unwrap();
+                        tag.push_str("\" aria-hidden=\"true\">#</a>");
+                    }
+                    tag.push_str(&format!("</{}>\n", level));
+                    output.push(Event::Html(tag.into()));
+
+                    if auto_toc_after_title && !has_toc {
+                        output.push(toc());
+                        has_toc = true;
+                    }
+                }
+                other => output.push(other.clone()),
+            }
+            index += 1;
+        }
+
+        (output, has_toc, positions)
+    }
+
+    /// Opening anchor tag with referrer policy, and noopener for external targets
+    fn hardened_link(kind: LinkType, dest: &str, title: &str) -> String {
+        let mut href = String::new();
+        if kind == LinkType::Email {
+            href.push_str("mailto:");
+        }
+        escape_href(&mut href, dest).unwrap();
+
+        let mut tag = format!("<a href=\"{}\"", href);
+        if !title.is_empty() {
+            tag.push_str(" title=\"");
+            escape_html(&mut tag, title).unwrap();
+            tag.push('"');
+        }
+
+        if sanitize::is_external_url(dest) {
+            tag.push_str(" rel=\"noopener noreferrer\"");
+        }
+        tag.push_str(" referrerpolicy=\"no-referrer\">");
+        tag
     }
 
     /// Process image links in markdown
-    fn process_image_links(&self, content: &str, images: &HashMap<String, Vec<u8>>) -> Result<String> {
-        let mut processed = content.to_string();
-
-        if self.options.base64_images {
-            // Convert image references to base64 data URLs
-            for (path, data) in images {
-                let mime_type = self.detect_mime_type(path);
-                let base64_data = base64_engine.encode(data);
-                let data_url = format!("data:{};base64,{}", mime_type, base64_data);
-                
-                // Replace all occurrences of the image path
-                processed = processed.replace(&format!("img/{}", path.strip_prefix("img/").unwrap_or(path)), &data_url);
-                processed = processed.replace(path, &data_url);
+    fn process_image_links(
+        &self,
+        content: &str,
+        images: &HashMap<String, Vec<u8>>,
+        dialect: MarkdownDialect,
+        links: ImageLinks,
+    ) -> Result<String> {
+        let mut urls: HashMap<&str, String> = HashMap::new();
+        match links {
+            ImageLinks::Archive => return Ok(content.to_string()),
+            ImageLinks::Embed => {
+                // Convert image references to base64 data URLs
+                for (path, data) in images {
+                    let (mime_type, data) = self.prepare_image(path, data)?;
+                    urls.insert(path, format!("data:{};base64,{}", mime_type, base64_engine.encode(data)));
+                }
+            }
+            ImageLinks::Rename(names) => {
+                for (path, name) in names {
+                    if path != name && images.contains_key(path) {
+                        urls.insert(path, name.clone());
+                    }
+                }
             }
         }
 
-        Ok(processed)
+        Ok(standalone::replace_image_paths(content, dialect.parser_options(), &urls))
+    }
+
+    /// Apply SVG processing, returning the MIME type and bytes to embed
+    pub(crate) fn prepare_image<'a>(&self, path: &str, data: &'a [u8]) -> Result<(&'static str, std::borrow::Cow<'a, [u8]>)> {
+        let mime_type = self.detect_mime_type(path);
+        if mime_type != "image/svg+xml" {
+            return Ok((mime_type, data.into()));
+        }
+
+        match self.options.svg_mode {
+            SvgMode::Embed => Ok((mime_type, data.into())),
+            SvgMode::Sanitize => {
+                let markup = String::from_utf8_lossy(data);
+                Ok((mime_type, svg::sanitize(&markup).into_bytes().into()))
+            }
+            SvgMode::Rasterize => Ok(("image/png", svg::rasterize(data)?.into())),
+        }
     }
 
     /// Detect MIME type from file extension
     fn detect_mime_type(&self, path: &str) -> &'static str {
-        match path.split('.').last().unwrap_or("").to_lowercase().as_str() {
+        match path.rsplit('.').next().unwrap_or("").to_lowercase().as_str() {
             "jpg" | "jpeg" => "image/jpeg",
             "png" => "image/png",
             "gif" => "image/gif",
             "svg" => "image/svg+xml",
             "webp" => "image/webp",
+            "woff" => "font/woff",
+            "woff2" => "font/woff2",
+            "ttf" => "font/ttf",
+            "otf" => "font/otf",
             _ => "application/octet-stream",
         }
     }
 
     /// Create complete HTML document
-    fn create_html_document(&self, body: &str, document: &MdzDocument) -> Result<String> {
+    fn create_html_document(&self, body: &str, document: &MdzDocument, extra_css: &[&str]) -> Result<String> {
+        let mut shell = self.html_shell(document, HARDENED_CSP);
+
+        if self.options.include_css {
+            shell.style(&self.get_css_content(document));
+            for css in extra_css {
+                shell.style(css);
+            }
+            if let Some(print_css) = &document.print_css {
+                shell.style(&format!("@media print {{\n{}\n}}", print_css));
+            }
+        }
+
+        Ok(shell.render(body))
+    }
+
+    /// HTML shell with the document's title, language and meta tags
+    ///
+    /// `csp` is the Content-Security-Policy used in hardened mode.
+    pub(crate) fn html_shell(&self, document: &MdzDocument, csp: &str) -> HtmlShell {
+        let metadata = &document.metadata;
         let title = self.options.html_title.as_ref()
-            .or(document.metadata.title.as_ref())
+            .or(metadata.title.as_ref())
             .map(|s| s.as_str())
             .unwrap_or("MDZ Document");
+        let lang = metadata.language.as_deref().unwrap_or("en");
 
-        let css = if self.options.include_css {
-            self.get_css_content(document)
-        } else {
-            String::new()
-        };
-
-        let html = format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>{}</title>
-    <style>
-{}
-    </style>
-</head>
-<body>
-{}
-</body>
-</html>"#,
-            title,
-            css,
-            body
-        );
-
-        Ok(html)
+        let mut shell = HtmlShell::new(lang, title);
+        if self.options.hardened {
+            shell.http_equiv("Content-Security-Policy", csp);
+            shell.meta("referrer", "no-referrer");
+        }
+        shell.meta("viewport", "width=device-width, initial-scale=1.0");
+        if let Some(description) = &metadata.description {
+            shell.meta("description", description);
+        }
+        if let Some(author) = &metadata.author {
+            shell.meta("author", author);
+        }
+        shell.meta("generator", concat!("mdz-core ", env!("CARGO_PKG_VERSION")));
+        shell
     }
 
     /// Get CSS content (custom, document, or default)
-    fn get_css_content(&self, document: &MdzDocument) -> String {
+    pub(crate) fn get_css_content(&self, document: &MdzDocument) -> String {
         if let Some(custom_css) = &self.options.custom_css {
             custom_css.clone()
         } else if let Some(doc_css) = &document.css {
-            doc_css.clone()
+            if self.options.inline_css_urls {
+                self.inline_css_urls(doc_css, document)
+            } else {
+                doc_css.clone()
+            }
         } else {
             self.default_css().to_string()
         }
     }
 
+    /// Replace archive-relative `url()` values with data URLs
+    fn inline_css_urls(&self, css: &str, document: &MdzDocument) -> String {
+        css::rewrite_urls(css, |reference| match reference.target("css/style.css") {
+            CssTarget::Archive(path) => {
+                let data = document.images.get(&path).or_else(|| document.fonts.get(&path))?;
+                Some(format!("data:{};base64,{}", self.detect_mime_type(&path), base64_engine.encode(data)))
+            }
+            _ => None,
+        })
+    }
+
     /// Default CSS styles
     fn default_css(&self) -> &'static str {
         r#"
@@ -210,6 +653,52 @@ blockquote {
     font-style: italic;
 }
 
+.admonition {
+    --admonition-color: #0969da;
+    border-left: 4px solid var(--admonition-color);
+    margin: 1em 0;
+    padding: 0.5em 1em;
+    border-radius: 0 4px 4px 0;
+    background-color: #f8f9fa;
+}
+
+.admonition > :last-child {
+    margin-bottom: 0.25em;
+}
+
+.admonition-title {
+    display: flex;
+    align-items: center;
+    gap: 0.5em;
+    margin: 0.25em 0 0.5em;
+    font-weight: 600;
+    color: var(--admonition-color);
+}
+
+.admonition-note { --admonition-color: #0969da; }
+.admonition-tip { --admonition-color: #1a7f37; }
+.admonition-important { --admonition-color: #8250df; }
+.admonition-warning { --admonition-color: #9a6700; }
+.admonition-caution { --admonition-color: #d1242f; }
+
+figure {
+    margin: 1.5em 0;
+}
+
+figure.figure {
+    text-align: center;
+}
+
+figcaption {
+    font-size: 0.9em;
+    color: #555;
+    margin: 0.5em 0;
+}
+
+.caption-label {
+    font-weight: 600;
+}
+
 table {
     border-collapse: collapse;
     width: 100%;
@@ -252,26 +741,83 @@ hr {
         "#
     }
 
-    /// Render to plain text (strip HTML)
+    /// Render to plain text, laid out according to [`RenderOptions::text`]
     pub fn render_text(&self, document: &MdzDocument) -> Result<String> {
-        // Simple implementation - remove markdown formatting
-        let mut text = document.content.clone();
-        
-        // Remove headers
-        text = regex::Regex::new(r"^#+\s*").unwrap().replace_all(&text, "").to_string();
-        
-        // Remove emphasis
-        text = regex::Regex::new(r"\*\*([^*]+)\*\*").unwrap().replace_all(&text, "$1").to_string();
-        text = regex::Regex::new(r"\*([^*]+)\*").unwrap().replace_all(&text, "$1").to_string();
-        
-        // Remove links
-        text = regex::Regex::new(r"\[([^\]]+)\]\([^)]+\)").unwrap().replace_all(&text, "$1").to_string();
-        
-        // Remove images
-        text = regex::Regex::new(r"!\[[^\]]*\]\([^)]+\)").unwrap().replace_all(&text, "").to_string();
-        
-        Ok(text)
+        Ok(text::render(document, self.dialect(document), &self.options.text))
+    }
+
+    /// Render for display in a terminal, styled with ANSI escape sequences
+    /// according to [`RenderOptions::terminal`]
+    pub fn render_terminal(&self, document: &MdzDocument) -> Result<String> {
+        self.options.terminal.validate()?;
+        Ok(text::render_terminal(document, self.dialect(document), &self.options.terminal))
+    }
+}
+
+/// How image references in the Markdown source are rewritten before parsing
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ImageLinks<'m> {
+    /// Keep archive paths
+    Archive,
+    /// Replace archive paths with data URLs
+    Embed,
+    /// Replace archive paths with the mapped output paths
+    Rename(&'m HashMap<String, String>),
+}
+
+/// Rendered document body
+pub(crate) struct RenderedBody<'r> {
+    /// HTML of each section; all but the first start with a heading
+    pub sections: Vec<String>,
+    pub headings: Vec<Heading>,
+    /// Stylesheets needed by the rendered content
+    pub extra_css: Vec<&'r str>,
+}
+
+/// Ordinals of the headings up to `max_level` that are not nested in another block
+fn section_starts(events: &[Event], max_level: u32) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut depth = 0;
+    let mut ordinal = 0;
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading(level, ..)) => {
+                if depth == 0 && *level as u32 <= max_level {
+                    starts.push(ordinal);
+                }
+                ordinal += 1;
+            }
+            Event::End(Tag::Heading(..)) => {}
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            // Containers emitted as markup by plugins, such as admonitions
+            Event::Html(html) => {
+                for tag in ["div", "figure", "section", "aside", "details"] {
+                    depth += html.matches(&format!("<{}", tag)).count() as i32;
+                    depth -= html.matches(&format!("</{}", tag)).count() as i32;
+                }
+            }
+            _ => {}
+        }
+    }
+    starts
+}
+
+/// Length of a `[TOC]` placeholder paragraph at the start of `events`
+fn toc_placeholder(events: &[Event]) -> Option<usize> {
+    if !matches!(events.first(), Some(Event::Start(Tag::Paragraph))) {
+        return None;
+    }
+
+    let mut text = String::new();
+    for (i, event) in events.iter().enumerate().skip(1) {
+        match event {
+            Event::Text(t) => text.push_str(t),
+            Event::End(Tag::Paragraph) if text.trim() == "[TOC]" => return Some(i + 1),
+            _ => return None,
+        }
     }
+    None
 }
 
 impl Default for MdzRenderer {
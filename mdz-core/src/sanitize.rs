@@ -0,0 +1,365 @@
+use crate::markup::{tokenize, Attribute, Token};
+use pulldown_cmark::{CowStr, Event, Tag};
+use regex::{Captures, Regex};
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+/// Policy applied to raw HTML in `main.md`
+#[derive(Debug, Clone, Default)]
+pub enum HtmlPolicy {
+    /// Pass HTML through unchanged
+    #[default]
+    AllowAll,
+    /// Keep only allowlisted tags and attributes, drop scripts and handlers
+    SafeSubset(HtmlAllowlist),
+    /// Render HTML as literal text
+    EscapeAll,
+}
+
+/// Tags, attributes and URL schemes permitted by [`HtmlPolicy::SafeSubset`]
+#[derive(Debug, Clone)]
+pub struct HtmlAllowlist {
+    pub tags: BTreeSet<String>,
+    pub attributes: BTreeSet<String>,
+    pub url_schemes: BTreeSet<String>,
+}
+
+/// Elements whose content is dropped along with the tag
+const DROP_CONTENT_TAGS: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "applet", "template", "noscript", "textarea", "title",
+    "frame", "frameset",
+];
+
+/// Attributes holding URLs that must use an allowed scheme
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "cite", "action", "formaction", "poster", "background", "xlink:href"];
+
+impl HtmlPolicy {
+    /// Safe subset with the default allowlist
+    pub fn safe() -> Self {
+        Self::SafeSubset(HtmlAllowlist::default())
+    }
+}
+
+impl Default for HtmlAllowlist {
+    fn default() -> Self {
+        let tags = [
+            "a", "abbr", "b", "blockquote", "br", "caption", "cite", "code", "col", "colgroup", "dd", "del",
+            "details", "dfn", "div", "dl", "dt", "em", "figcaption", "figure", "h1", "h2", "h3", "h4", "h5", "h6",
+            "hr", "i", "img", "ins", "kbd", "li", "mark", "ol", "p", "pre", "q", "s", "samp", "small", "span",
+            "strong", "sub", "summary", "sup", "table", "tbody", "td", "tfoot", "th", "thead", "tr", "u", "ul",
+            "var",
+        ];
+        let attributes = [
+            "abbr", "align", "alt", "cite", "class", "colspan", "datetime", "dir", "height", "href", "id", "lang",
+            "name", "open", "reversed", "rowspan", "scope", "span", "src", "start", "title", "type", "width",
+        ];
+        let url_schemes = ["http", "https", "mailto"];
+
+        Self {
+            tags: tags.iter().map(|s| s.to_string()).collect(),
+            attributes: attributes.iter().map(|s| s.to_string()).collect(),
+            url_schemes: url_schemes.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+impl HtmlAllowlist {
+    /// Permit an additional tag
+    pub fn allow_tag(&mut self, tag: &str) -> &mut Self {
+        self.tags.insert(tag.to_ascii_lowercase());
+        self
+    }
+
+    /// Permit an additional attribute on all allowed tags
+    pub fn allow_attribute(&mut self, attribute: &str) -> &mut Self {
+        self.attributes.insert(attribute.to_ascii_lowercase());
+        self
+    }
+
+    /// Check if a URL is relative, a fragment, an inline image or uses an allowed scheme
+    pub fn is_safe_url(&self, url: &str) -> bool {
+        // Browsers ignore whitespace and control characters inside the scheme
+        let url: String = url.chars().filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control()).collect();
+        let lower = url.to_ascii_lowercase();
+
+        match url.find([':', '/', '?', '#']) {
+            Some(i) if url.as_bytes()[i] == b':' => {
+                lower.starts_with("data:image/") || self.url_schemes.contains(&lower[..i])
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Check if a link leaves the document's origin, which hardened output opens with `noopener`
+pub(crate) fn is_external_url(url: &str) -> bool {
+    let lower = url.trim_start().to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("//")
+}
+
+/// Applies an [`HtmlPolicy`] to a pulldown-cmark event stream
+pub(crate) struct HtmlSanitizer<'p> {
+    policy: &'p HtmlPolicy,
+    /// Add `rel` and `referrerpolicy` to `<a>` tags in raw HTML
+    hardened: bool,
+    /// Element whose content is being dropped, with its nesting depth
+    skip: Option<(String, usize)>,
+    /// Incomplete tag carried over to the next HTML event
+    pending: String,
+}
+
+impl<'p> HtmlSanitizer<'p> {
+    pub(crate) fn new(policy: &'p HtmlPolicy, hardened: bool) -> Self {
+        Self {
+            policy,
+            hardened,
+            skip: None,
+            pending: String::new(),
+        }
+    }
+
+    /// Transform a single event, returning `None` to drop it
+    pub(crate) fn process<'a>(&mut self, event: Event<'a>) -> Option<Event<'a>> {
+        // `None` keeps all HTML and only hardens links
+        let allowlist = match self.policy {
+            HtmlPolicy::AllowAll if !self.hardened => return Some(event),
+            HtmlPolicy::AllowAll => None,
+            HtmlPolicy::EscapeAll => {
+                return Some(match event {
+                    Event::Html(html) => Event::Text(html),
+                    other => other,
+                })
+            }
+            HtmlPolicy::SafeSubset(allowlist) => Some(allowlist),
+        };
+
+        match event {
+            Event::Html(html) => {
+                let output = self.sanitize_html(&html, allowlist);
+                (!output.is_empty()).then(|| Event::Html(output.into()))
+            }
+            Event::Text(_) | Event::Code(_) if self.skip.is_some() => None,
+            Event::Start(Tag::Link(kind, dest, title)) if allowlist.is_some_and(|a| !a.is_safe_url(&dest)) => {
+                Some(Event::Start(Tag::Link(kind, CowStr::Borrowed("#"), title)))
+            }
+            Event::Start(Tag::Image(kind, dest, title)) if allowlist.is_some_and(|a| !a.is_safe_url(&dest)) => {
+                Some(Event::Start(Tag::Image(kind, CowStr::Borrowed(""), title)))
+            }
+            other => Some(other),
+        }
+    }
+
+    /// Sanitize an HTML fragment, keeping state across fragments
+    fn sanitize_html(&mut self, html: &str, allowlist: Option<&HtmlAllowlist>) -> String {
+        let input = std::mem::take(&mut self.pending) + html;
+        let mut output = String::new();
+        let mut tokens = tokenize(&input);
+
+        if let Some(Token::Tag(tag)) = tokens.last() {
+            if !tag.is_complete() {
+                self.pending = tag.raw.to_string();
+                tokens.pop();
+            }
+        }
+
+        for token in tokens {
+            if let Some((name, depth)) = self.skip.as_mut() {
+                if let Token::Tag(tag) = &token {
+                    if tag.name.eq_ignore_ascii_case(name) && !tag.self_closing {
+                        if tag.closing {
+                            *depth -= 1;
+                        } else {
+                            *depth += 1;
+                        }
+                    }
+                }
+                if *depth == 0 {
+                    self.skip = None;
+                }
+                continue;
+            }
+
+            let Some(allowlist) = allowlist else {
+                match token {
+                    Token::Tag(tag) if self.hardened && !tag.closing && tag.name.eq_ignore_ascii_case("a") => {
+                        output.push_str("<a");
+                        for attr in tag.attrs.iter().filter(|a| !is_hardening_attribute(a.name)) {
+                            output.push(' ');
+                            output.push_str(attr.raw);
+                        }
+                        push_link_hardening(&mut output, &tag.attrs);
+                        output.push_str(if tag.self_closing { " />" } else { ">" });
+                    }
+                    Token::Tag(tag) => output.push_str(tag.raw),
+                    Token::Text(raw) | Token::Other(raw) => output.push_str(raw),
+                }
+                continue;
+            };
+
+            match token {
+                Token::Text(text) => output.push_str(text),
+                Token::Other(_) => {}
+                Token::Tag(tag) => {
+                    let name = tag.name.to_ascii_lowercase();
+                    let harden = self.hardened && name == "a";
+                    if DROP_CONTENT_TAGS.contains(&name.as_str()) {
+                        if !tag.closing && !tag.self_closing {
+                            self.skip = Some((name, 1));
+                        }
+                        continue;
+                    }
+                    if !allowlist.tags.contains(&name) {
+                        continue;
+                    }
+                    if tag.closing {
+                        output.push_str(&format!("</{}>", name));
+                        continue;
+                    }
+
+                    output.push('<');
+                    output.push_str(&name);
+                    for attr in &tag.attrs {
+                        let attr_name = attr.name.to_ascii_lowercase();
+                        if attr_name.starts_with("on") || !allowlist.attributes.contains(&attr_name) {
+                            continue;
+                        }
+                        if harden && is_hardening_attribute(&attr_name) {
+                            continue;
+                        }
+                        if URL_ATTRIBUTES.contains(&attr_name.as_str())
+                            && !allowlist.is_safe_url(&decode_entities(attr.value))
+                        {
+                            continue;
+                        }
+                        output.push_str(&format!(" {}=\"{}\"", attr_name, attr.value.replace('"', "&quot;")));
+                    }
+                    if harden {
+                        push_link_hardening(&mut output, &tag.attrs);
+                    }
+                    output.push_str(if tag.self_closing { " />" } else { ">" });
+                }
+            }
+        }
+
+        output
+    }
+}
+
+/// Attributes replaced on links in hardened output
+fn is_hardening_attribute(name: &str) -> bool {
+    name.eq_ignore_ascii_case("rel") || name.eq_ignore_ascii_case("referrerpolicy")
+}
+
+/// Append the attributes of a hardened link: no referrer, and no opener for external targets
+fn push_link_hardening(output: &mut String, attrs: &[Attribute]) {
+    let href = attrs.iter().find(|a| a.name.eq_ignore_ascii_case("href"));
+    if href.is_some_and(|href| is_external_url(&decode_entities(href.value))) {
+        output.push_str(" rel=\"noopener noreferrer\"");
+    }
+    output.push_str(" referrerpolicy=\"no-referrer\"");
+}
+
+/// Decode numeric character references and `&colon;` for URL checks
+fn decode_entities(value: &str) -> String {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern =
+        PATTERN.get_or_init(|| Regex::new(r"(?i)&(?:#x([0-9a-f]+)|#([0-9]+)|(colon|tab|newline));?").unwrap());
+    pattern
+        .replace_all(value, |caps: &Captures| {
+            let code = if let Some(hex) = caps.get(1) {
+                u32::from_str_radix(hex.as_str(), 16).ok()
+            } else if let Some(dec) = caps.get(2) {
+                dec.as_str().parse().ok()
+            } else {
+                match caps[3].to_ascii_lowercase().as_str() {
+                    "colon" => Some(':' as u32),
+                    "tab" => Some('\t' as u32),
+                    _ => Some('\n' as u32),
+                }
+            };
+            code.and_then(char::from_u32).map(String::from).unwrap_or_default()
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{html, Parser};
+
+    fn render(markdown: &str, policy: &HtmlPolicy) -> String {
+        render_with(markdown, policy, false)
+    }
+
+    fn render_with(markdown: &str, policy: &HtmlPolicy, hardened: bool) -> String {
+        let mut sanitizer = HtmlSanitizer::new(policy, hardened);
+        let mut output = String::new();
+        html::push_html(&mut output, Parser::new(markdown).filter_map(|event| sanitizer.process(event)));
+        output
+    }
+
+    #[test]
+    fn drops_scripts_with_their_content() {
+        let html = render("<div>a<script>alert(1)</script>b</div>\n", &HtmlPolicy::safe());
+        assert_eq!(html, "<div>ab</div>\n");
+    }
+
+    #[test]
+    fn drops_handlers_and_unknown_attributes() {
+        let html = render("<p><img src=\"a.png\" onerror=\"x()\" style=\"color:red\" alt=\"A\"></p>\n", &HtmlPolicy::safe());
+        assert_eq!(html, "<p><img src=\"a.png\" alt=\"A\"></p>\n");
+    }
+
+    #[test]
+    fn drops_unsafe_urls_even_when_encoded() {
+        let html = render("<a href=\"java&#x73;cript&colon;x()\">x</a>\n", &HtmlPolicy::safe());
+        assert_eq!(html, "<p><a>x</a></p>\n");
+        let html = render("[x](javascript:alert(1)) ![y](vbscript:z)\n", &HtmlPolicy::safe());
+        assert_eq!(html, "<p><a href=\"#\">x</a> <img src=\"\" alt=\"y\" /></p>\n");
+    }
+
+    #[test]
+    fn keeps_tags_split_across_events() {
+        let html = render("<span title=\"a\nb\">x</span>\n", &HtmlPolicy::safe());
+        assert!(html.contains("<span title=\"a\nb\">x</span>"), "{}", html);
+    }
+
+    #[test]
+    fn escape_all_shows_html_as_text() {
+        assert_eq!(render("<b>x</b>\n", &HtmlPolicy::EscapeAll), "<p>&lt;b&gt;x&lt;/b&gt;</p>\n");
+    }
+
+    #[test]
+    fn custom_allowlist() {
+        let mut allowlist = HtmlAllowlist::default();
+        allowlist.allow_tag("VIDEO").allow_attribute("controls");
+        let html = render("<video controls src=\"v.mp4\"></video>\n", &HtmlPolicy::SafeSubset(allowlist));
+        assert_eq!(html, "<p><video controls=\"\" src=\"v.mp4\"></video></p>\n");
+    }
+
+    #[test]
+    fn hardens_raw_links() {
+        let markdown = "<a href=\"https://e.com\" rel=\"opener\" title=\"t\">x</a> <a href=\"#top\">y</a>\n";
+        let expected = "<p><a href=\"https://e.com\" title=\"t\" rel=\"noopener noreferrer\" referrerpolicy=\"no-referrer\">x</a> \
+                        <a href=\"#top\" referrerpolicy=\"no-referrer\">y</a></p>\n";
+        let mut allowlist = HtmlAllowlist::default();
+        allowlist.allow_attribute("rel");
+        assert_eq!(render_with(markdown, &HtmlPolicy::SafeSubset(allowlist), true), expected);
+        assert_eq!(render_with(markdown, &HtmlPolicy::AllowAll, true), expected);
+        assert_eq!(
+            render_with("<A HREF='//e.com'>x</A><b onclick=\"f()\">b</b>\n", &HtmlPolicy::AllowAll, true),
+            "<p><a HREF='//e.com' rel=\"noopener noreferrer\" referrerpolicy=\"no-referrer\">x</A><b onclick=\"f()\">b</b></p>\n"
+        );
+    }
+
+    #[test]
+    fn url_schemes() {
+        let allowlist = HtmlAllowlist::default();
+        for url in ["https://example.com", "mailto:a@b.c", "img/a.png", "#top", "?q=1", "data:image/png;base64,AA"] {
+            assert!(allowlist.is_safe_url(url), "{}", url);
+        }
+        for url in ["javascript:x", " JavaScript:x", "java\tscript:x", "data:text/html,x", "file:///etc/passwd"] {
+            assert!(!allowlist.is_safe_url(url), "{}", url);
+        }
+    }
+}
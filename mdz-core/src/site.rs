@@ -0,0 +1,113 @@
+use crate::css::{self, CssTarget};
+use crate::renderer::ImageLinks;
+use crate::{MdzDocument, MdzError, MdzRenderer, Result};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Component, Path};
+
+/// Content-Security-Policy for hardened sites, which load assets from their own origin
+const HARDENED_SITE_CSP: &str =
+    "default-src 'none'; style-src 'self' 'unsafe-inline'; img-src 'self'; font-src 'self'; base-uri 'none'; form-action 'none'";
+
+/// Files of a static site export, keyed by path relative to the site root
+#[derive(Debug, Clone, Default)]
+pub struct StaticSite {
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+impl StaticSite {
+    /// Write all files below `directory`, creating directories as needed
+    pub fn write_to<P: AsRef<Path>>(&self, directory: P) -> Result<()> {
+        let directory = directory.as_ref();
+        for (path, data) in &self.files {
+            // Archive entries may carry arbitrary names; never write outside the site
+            if !Path::new(path).components().all(|c| matches!(c, Component::Normal(_))) {
+                return Err(MdzError::InvalidFormat(format!("Unsafe asset path: {}", path)));
+            }
+            let target = directory.join(path);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(target, data)?;
+        }
+        Ok(())
+    }
+}
+
+/// Render `index.html` with the document's images, fonts and stylesheets as separate files
+pub(crate) fn render(renderer: &MdzRenderer, document: &MdzDocument) -> Result<StaticSite> {
+    let options = renderer.options();
+    let mut site = StaticSite::default();
+    // Archive path of each asset mapped to its path in the site
+    let mut names: HashMap<String, String> = HashMap::new();
+
+    for (path, data) in &document.images {
+        let (mime_type, data) = renderer.prepare_image(path, data)?;
+        let path_out = if mime_type == "image/png" && path.to_ascii_lowercase().ends_with(".svg") {
+            format!("{}.png", &path[..path.len() - 4])
+        } else {
+            path.clone()
+        };
+        let name = asset_name(&path_out, &data, options.hash_asset_names);
+        site.files.insert(name.clone(), data.into_owned());
+        names.insert(path.clone(), name);
+    }
+    for (path, data) in &document.fonts {
+        let name = asset_name(path, data, options.hash_asset_names);
+        site.files.insert(name.clone(), data.clone());
+        names.insert(path.clone(), name);
+    }
+
+    let body = renderer.render_body(document, ImageLinks::Rename(&names), 0)?;
+    let mut shell = renderer.html_shell(document, HARDENED_SITE_CSP);
+
+    if options.include_css {
+        let mut stylesheet = rewrite_css_urls(&renderer.get_css_content(document), &names);
+        for css in &body.extra_css {
+            stylesheet.push('\n');
+            stylesheet.push_str(css);
+        }
+        let name = asset_name("css/style.css", stylesheet.as_bytes(), options.hash_asset_names);
+        shell.stylesheet(&name, None);
+        site.files.insert(name, stylesheet.into_bytes());
+
+        if let Some(print_css) = &document.print_css {
+            let print_css = rewrite_css_urls(print_css, &names);
+            let name = asset_name("css/print.css", print_css.as_bytes(), options.hash_asset_names);
+            shell.stylesheet(&name, Some("print"));
+            site.files.insert(name, print_css.into_bytes());
+        }
+    }
+
+    site.files.insert("index.html".to_string(), shell.render(&body.sections.concat()).into_bytes());
+    Ok(site)
+}
+
+/// Output path for an asset, with the first 8 hex digits of its SHA-256 before the extension
+fn asset_name(path: &str, data: &[u8], hashed: bool) -> String {
+    if !hashed {
+        return path.to_string();
+    }
+    let digest = Sha256::digest(data);
+    let hash: String = digest[..4].iter().map(|byte| format!("{:02x}", byte)).collect();
+    let file_start = path.rfind('/').map_or(0, |slash| slash + 1);
+    match path[file_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = file_start + dot;
+            format!("{}.{}{}", &path[..dot], hash, &path[dot..])
+        }
+        _ => format!("{}.{}", path, hash),
+    }
+}
+
+/// Point archive-relative `url()` references in a stylesheet under `css/` at renamed assets
+fn rewrite_css_urls(css: &str, names: &HashMap<String, String>) -> String {
+    css::rewrite_urls(css, |reference| match reference.target("css/style.css") {
+        CssTarget::Archive(path) => names
+            .get(&path)
+            .filter(|name| **name != path)
+            .map(|name| format!("../{}", name)),
+        _ => None,
+    })
+}
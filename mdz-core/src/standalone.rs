@@ -0,0 +1,337 @@
+use crate::{MarkdownDialect, MdzDocument, MdzError, MdzRenderer, Result};
+use base64::engine::general_purpose::STANDARD as base64_engine;
+use base64::Engine;
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// How [`MdzRenderer::render_standalone_markdown`] embeds images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmbeddedImages {
+    /// Replace each image path with a data URL in place
+    #[default]
+    Inline,
+    /// Turn images into reference links, defined once at the end of the file
+    References,
+}
+
+/// Markdown with embedded images moved out to archive files
+#[derive(Debug, Clone, Default)]
+pub struct ExtractedImages {
+    /// Rewritten `main.md`
+    pub markdown: String,
+    /// `(archive path, data)` for every distinct image
+    pub images: Vec<(String, Vec<u8>)>,
+}
+
+/// Replacement of a range of the Markdown source
+struct Edit {
+    range: Range<usize>,
+    text: String,
+}
+
+/// Write `main.md` as a single file with its archive images embedded as data URLs
+///
+/// Images are found through the parsed document, so paths in code blocks are
+/// left alone. Reference definitions and `<img>` tags in raw HTML are rewritten
+/// in place; inline images become reference links when `embedding` asks for it.
+pub(crate) fn render(renderer: &MdzRenderer, document: &MdzDocument, embedding: EmbeddedImages) -> Result<String> {
+    let source = &document.content;
+    let mut data_urls: HashMap<&str, String> = HashMap::new();
+    for (path, data) in &document.images {
+        let (mime_type, data) = renderer.prepare_image(path, data)?;
+        data_urls.insert(path, format!("data:{};base64,{}", mime_type, base64_engine.encode(data)));
+    }
+    let archive_path = |dest: &str| {
+        let path = dest.strip_prefix("./").unwrap_or(dest);
+        data_urls.get_key_value(path).map(|(path, url)| (*path, url))
+    };
+
+    let mut edits = Vec::new();
+    // Reference definitions to append: (label, data URL, title)
+    let mut definitions: Vec<(String, &String, String)> = Vec::new();
+    let parser = Parser::new_ext(source, renderer.dialect(document).parser_options());
+    for (_, definition) in parser.reference_definitions().iter() {
+        if let Some((_, url)) = archive_path(&definition.dest) {
+            edits.extend(replace_within(source, definition.span.clone(), &definition.dest, url));
+        }
+    }
+
+    let events: Vec<(Event, Range<usize>)> = parser.into_offset_iter().collect();
+    for (index, (event, range)) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Image(LinkType::Inline, dest, title)) => {
+                let Some((path, url)) = archive_path(dest) else {
+                    continue;
+                };
+                let text_end = alt_text_end(&events[index + 1..], range);
+                if embedding == EmbeddedImages::Inline || !source[text_end..].starts_with(']') {
+                    edits.extend(replace_within(source, text_end..range.end, dest, url));
+                    continue;
+                }
+
+                let existing = definitions.iter().find(|(_, u, t)| *u == url && t == title.as_ref());
+                let label = match existing {
+                    Some((label, ..)) => label.clone(),
+                    None => {
+                        let taken = definitions.iter().filter(|(_, u, _)| *u == url).count();
+                        let label = if taken == 0 { path.to_string() } else { format!("{} {}", path, taken + 1) };
+                        definitions.push((label.clone(), url, title.to_string()));
+                        label
+                    }
+                };
+                edits.push(Edit {
+                    range: range.clone(),
+                    text: format!("{}[{}]", &source[range.start..=text_end], label),
+                });
+            }
+            Event::Html(_) => edits.extend(replace_quoted_paths(source, range.clone(), &data_urls)),
+            _ => {}
+        }
+    }
+
+    let mut markdown = apply_edits(source, edits);
+    if !definitions.is_empty() {
+        markdown.truncate(markdown.trim_end().len());
+        markdown.push_str("\n\n");
+        for (label, url, title) in &definitions {
+            markdown.push_str(&format!("[{}]: {}", label, url));
+            if !title.is_empty() {
+                markdown.push_str(&format!(" \"{}\"", title.replace('"', "\\\"")));
+            }
+            markdown.push('\n');
+        }
+    }
+    Ok(markdown)
+}
+
+/// Move images embedded as data URLs into `img/` files, reversing [`MdzRenderer::render_standalone_markdown`]
+///
+/// Images defined through a reference whose label is a file name keep that
+/// name; others are numbered. Identical images share one file.
+pub fn extract_images(markdown: &str, dialect: MarkdownDialect) -> Result<ExtractedImages> {
+    let mut result = ExtractedImages::default();
+    let mut edits = Vec::new();
+    let mut numbered = 0;
+    let mut extract = |url: &str, label: Option<&str>| -> Result<Option<String>> {
+        let Some((extension, data)) = decode_data_url(url)? else {
+            return Ok(None);
+        };
+        if let Some((path, _)) = result.images.iter().find(|(_, existing)| *existing == data) {
+            return Ok(Some(path.clone()));
+        }
+        let stem = label
+            .map(|label| label.rsplit('/').next().unwrap_or(label))
+            .map(|name| name.rsplit_once('.').map_or(name, |(stem, _)| stem))
+            .map(|stem| stem.chars().filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_')).collect::<String>())
+            .filter(|stem| !stem.is_empty())
+            .unwrap_or_else(|| {
+                numbered += 1;
+                format!("image-{}", numbered)
+            });
+        let mut path = format!("img/{}.{}", stem, extension);
+        let mut n = 2;
+        while result.images.iter().any(|(existing, _)| *existing == path) {
+            path = format!("img/{}-{}.{}", stem, n, extension);
+            n += 1;
+        }
+        result.images.push((path.clone(), data));
+        Ok(Some(path))
+    };
+
+    let parser = Parser::new_ext(markdown, dialect.parser_options());
+    let mut definitions: Vec<_> = parser.reference_definitions().iter().collect();
+    definitions.sort_by_key(|(_, definition)| definition.span.start);
+    for (label, definition) in definitions {
+        if let Some(path) = extract(&definition.dest, Some(label))? {
+            edits.extend(replace_within(markdown, definition.span.clone(), &definition.dest, &path));
+        }
+    }
+
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::Image(LinkType::Inline, dest, _)) => {
+                if let Some(path) = extract(&dest, None)? {
+                    edits.extend(replace_within(markdown, range, &dest, &path));
+                }
+            }
+            Event::Html(_) => {
+                let mut rest = &markdown[range.clone()];
+                let mut offset = range.start;
+                while let Some(start) = rest.find("data:") {
+                    let quote = rest[..start].chars().last().filter(|c| matches!(c, '"' | '\''));
+                    let end = match quote {
+                        Some(quote) => rest[start..].find(quote).map(|end| start + end),
+                        None => None,
+                    };
+                    let Some(end) = end else {
+                        offset += start + 5;
+                        rest = &rest[start + 5..];
+                        continue;
+                    };
+                    if let Some(path) = extract(&rest[start..end], None)? {
+                        edits.push(Edit {
+                            range: offset + start..offset + end,
+                            text: path,
+                        });
+                    }
+                    offset += end;
+                    rest = &rest[end..];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    result.markdown = apply_edits(markdown, edits);
+    Ok(result)
+}
+
+/// Replace archive image paths with `urls[path]` where the document uses them
+///
+/// Inline image and link destinations, reference definitions and quoted
+/// paths in raw HTML are rewritten; the same text in code, alt text or prose
+/// is left alone.
+pub(crate) fn replace_image_paths(source: &str, options: Options, urls: &HashMap<&str, String>) -> String {
+    let lookup = |dest: &str| urls.get(dest.strip_prefix("./").unwrap_or(dest));
+
+    let mut edits = Vec::new();
+    let parser = Parser::new_ext(source, options);
+    for (_, definition) in parser.reference_definitions().iter() {
+        if let Some(url) = lookup(&definition.dest) {
+            edits.extend(replace_within(source, definition.span.clone(), &definition.dest, url));
+        }
+    }
+
+    let events: Vec<(Event, Range<usize>)> = parser.into_offset_iter().collect();
+    for (index, (event, range)) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Image(LinkType::Inline, dest, _) | Tag::Link(LinkType::Inline, dest, _)) => {
+                if let Some(url) = lookup(dest) {
+                    let text_end = alt_text_end(&events[index + 1..], range);
+                    edits.extend(replace_within(source, text_end..range.end, dest, url));
+                }
+            }
+            Event::Html(_) => edits.extend(replace_quoted_paths(source, range.clone(), urls)),
+            _ => {}
+        }
+    }
+
+    apply_edits(source, edits)
+}
+
+/// Edits replacing quoted archive paths in `src`, `href` and `poster`
+/// attributes of raw HTML (`src="img/a.png"`, `href='./img/a.png'`)
+fn replace_quoted_paths(source: &str, range: Range<usize>, urls: &HashMap<&str, String>) -> Vec<Edit> {
+    let html = &source[range.clone()];
+    let is_url_attribute = |offset: usize| {
+        let name = html[..offset].trim_end().strip_suffix('=').unwrap_or_default().trim_end().to_ascii_lowercase();
+        ["src", "href", "poster"].iter().any(|attribute| name.ends_with(attribute))
+    };
+    let mut edits = Vec::new();
+    for (path, url) in urls {
+        for candidate in [format!("./{}", path), path.to_string()] {
+            for quote in ['"', '\''] {
+                let quoted = format!("{}{}{}", quote, candidate, quote);
+                for (offset, _) in html.match_indices(&quoted).filter(|(offset, _)| is_url_attribute(*offset)) {
+                    let start = range.start + offset + 1;
+                    edits.push(Edit {
+                        range: start..start + candidate.len(),
+                        text: url.clone(),
+                    });
+                }
+            }
+        }
+    }
+    edits
+}
+
+/// End of an image's alt text (or a link's text) in the source, where its `]` is
+fn alt_text_end(following: &[(Event, Range<usize>)], image: &Range<usize>) -> usize {
+    let mut end = image.start + 2;
+    let mut depth = 0;
+    for (event, range) in following {
+        match event {
+            Event::End(_) if depth == 0 => break,
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+        end = end.max(range.end);
+    }
+    end
+}
+
+/// Replace the first occurrence of a destination within a range of the source
+fn replace_within(source: &str, range: Range<usize>, dest: &str, replacement: &str) -> Option<Edit> {
+    let offset = source.get(range.clone())?.find(dest)?;
+    let start = range.start + offset;
+    Some(Edit {
+        range: start..start + dest.len(),
+        text: replacement.to_string(),
+    })
+}
+
+/// Apply edits in order, skipping any that overlap an earlier one
+fn apply_edits(source: &str, mut edits: Vec<Edit>) -> String {
+    edits.sort_by_key(|edit| edit.range.start);
+    let mut output = String::with_capacity(source.len());
+    let mut position = 0;
+    for edit in edits {
+        if edit.range.start < position {
+            continue;
+        }
+        output.push_str(&source[position..edit.range.start]);
+        output.push_str(&edit.text);
+        position = edit.range.end;
+    }
+    output.push_str(&source[position..]);
+    output
+}
+
+/// File extension and bytes of an image `data:` URL, or `None` for other URLs
+fn decode_data_url(url: &str) -> Result<Option<(&'static str, Vec<u8>)>> {
+    let Some((header, payload)) = url.strip_prefix("data:").and_then(|rest| rest.split_once(',')) else {
+        return Ok(None);
+    };
+    let mut parameters = header.split(';');
+    let extension = match parameters.next().unwrap_or("").trim().to_ascii_lowercase().as_str() {
+        "image/png" => "png",
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/gif" => "gif",
+        "image/svg+xml" => "svg",
+        "image/webp" => "webp",
+        "image/bmp" => "bmp",
+        _ => return Ok(None),
+    };
+
+    let data = if parameters.any(|parameter| parameter.trim().eq_ignore_ascii_case("base64")) {
+        let payload: String = payload.chars().filter(|c| !c.is_whitespace()).collect();
+        base64_engine
+            .decode(payload)
+            .map_err(|e| MdzError::InvalidFormat(format!("Invalid base64 image data: {}", e)))?
+    } else {
+        percent_decode(payload)
+    };
+    Ok(Some((extension, data)))
+}
+
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (bytes[i], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                output.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                output.push(byte);
+                i += 1;
+            }
+        }
+    }
+    output
+}
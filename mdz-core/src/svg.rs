@@ -0,0 +1,409 @@
+use crate::css::{self, CssTarget};
+use crate::markup::{tokenize, Attribute, Tag, Token};
+use crate::{MdzError, Result};
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+/// Unsafe construct found in an SVG image
+#[derive(Debug, Clone)]
+pub struct SvgIssue {
+    pub kind: SvgIssueKind,
+    pub detail: String,
+}
+
+/// Category of unsafe SVG construct
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SvgIssueKind {
+    /// `<script>` element or `javascript:` URL
+    Script,
+    /// `on*` event handler attribute
+    EventHandler,
+    /// `<foreignObject>` embedding arbitrary HTML
+    ForeignObject,
+    /// `href`, `xlink:href`, CSS `url()` or a processing instruction such as
+    /// `<?xml-stylesheet?>` pointing outside the image
+    ExternalReference,
+    /// DOCTYPE with entity declarations
+    EntityDeclaration,
+}
+
+impl std::fmt::Display for SvgIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            SvgIssueKind::Script => "script",
+            SvgIssueKind::EventHandler => "event handler",
+            SvgIssueKind::ForeignObject => "foreignObject",
+            SvgIssueKind::ExternalReference => "external reference",
+            SvgIssueKind::EntityDeclaration => "entity declaration",
+        };
+        write!(f, "{}: {}", kind, self.detail)
+    }
+}
+
+/// Scan SVG markup for scripts, event handlers, foreignObject and external references
+pub fn scan(svg: &str) -> Vec<SvgIssue> {
+    let mut issues = Vec::new();
+    let mut in_style = false;
+
+    for token in tokenize(svg) {
+        if let Some((_, css, _)) = stylesheet(&token).filter(|_| in_style) {
+            for reference in css::analyze(css).references {
+                if let CssTarget::External(url) = reference.target("") {
+                    issues.push(SvgIssue {
+                        kind: SvgIssueKind::ExternalReference,
+                        detail: format!("stylesheet loads {}", url),
+                    });
+                }
+            }
+            continue;
+        }
+
+        match token {
+            Token::Tag(tag) => {
+                let name = local_name(tag.name);
+                in_style = name == "style" && !tag.closing && !tag.self_closing;
+                if tag.closing {
+                    continue;
+                }
+                if name == "script" {
+                    issues.push(SvgIssue { kind: SvgIssueKind::Script, detail: format!("<{}> element", tag.name) });
+                }
+                if name == "foreignobject" {
+                    issues.push(SvgIssue { kind: SvgIssueKind::ForeignObject, detail: format!("<{}> element", tag.name) });
+                }
+                issues.extend(check_animation(&tag));
+                for attr in &tag.attrs {
+                    if let Some(issue) = check_attribute(tag.name, attr) {
+                        issues.push(issue);
+                    }
+                }
+            }
+            Token::Other(raw) if is_entity_doctype(raw) => {
+                issues.push(SvgIssue {
+                    kind: SvgIssueKind::EntityDeclaration,
+                    detail: "DOCTYPE declares entities".to_string(),
+                });
+            }
+            Token::Other(raw) if is_processing_instruction(raw) => {
+                issues.push(SvgIssue {
+                    kind: SvgIssueKind::ExternalReference,
+                    detail: format!("processing instruction {}", raw),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    issues
+}
+
+/// Remove scripts, event handlers, foreignObject, external references,
+/// processing instructions and DOCTYPE entities
+///
+/// References to entities other than XML's predefined ones are dropped along
+/// with the declarations, so the output stays well-formed.
+pub fn sanitize(svg: &str) -> String {
+    let mut output = String::with_capacity(svg.len());
+    let mut skip: Option<(String, usize)> = None;
+    let mut in_style = false;
+
+    for token in tokenize(svg) {
+        if let Some((name, depth)) = skip.as_mut() {
+            if let Token::Tag(tag) = &token {
+                if tag.name.eq_ignore_ascii_case(name) && !tag.self_closing {
+                    if tag.closing {
+                        *depth -= 1;
+                    } else {
+                        *depth += 1;
+                    }
+                }
+            }
+            if *depth == 0 {
+                skip = None;
+            }
+            continue;
+        }
+
+        if let Some((open, css, close)) = stylesheet(&token).filter(|_| in_style) {
+            let css = css::rewrite_references(css, |reference| match reference.target("") {
+                CssTarget::External(_) => Some(String::new()),
+                _ => None,
+            });
+            output.push_str(open);
+            // Entities are not expanded inside CDATA sections
+            if open.is_empty() {
+                output.push_str(&drop_entity_references(&css));
+            } else {
+                output.push_str(&css);
+            }
+            output.push_str(close);
+            continue;
+        }
+
+        match token {
+            Token::Tag(tag) => {
+                let name = local_name(tag.name);
+                let dropped = name == "script" || name == "foreignobject" || check_animation(&tag).is_some();
+                if !tag.closing && dropped {
+                    if !tag.self_closing {
+                        skip = Some((tag.name.to_string(), 1));
+                    }
+                    continue;
+                }
+                in_style = name == "style" && !tag.closing && !tag.self_closing;
+
+                if tag.closing || tag.attrs.iter().all(|a| check_attribute(tag.name, a).is_none()) {
+                    output.push_str(&drop_entity_references(tag.raw));
+                    continue;
+                }
+
+                output.push('<');
+                output.push_str(tag.name);
+                for attr in tag.attrs.iter().filter(|a| check_attribute(tag.name, a).is_none()) {
+                    output.push(' ');
+                    output.push_str(&drop_entity_references(attr.raw));
+                }
+                output.push_str(if tag.self_closing { "/>" } else { ">" });
+            }
+            Token::Other(raw) if is_entity_doctype(raw) || is_processing_instruction(raw) => {}
+            Token::Text(raw) => output.push_str(&drop_entity_references(raw)),
+            Token::Other(raw) => output.push_str(raw),
+        }
+    }
+
+    output
+}
+
+/// Render an SVG image to PNG
+///
+/// The image is sanitized first, and `<image>` elements may only embed data URLs,
+/// never files from the local file system.
+#[cfg(feature = "svg-raster")]
+pub fn rasterize(svg: &[u8]) -> Result<Vec<u8>> {
+    use resvg::usvg::{fontdb, ImageHrefResolver, Options};
+    use std::sync::{Arc, OnceLock};
+
+    static FONTS: OnceLock<Arc<fontdb::Database>> = OnceLock::new();
+    let fonts = FONTS.get_or_init(|| {
+        let mut database = fontdb::Database::new();
+        database.load_system_fonts();
+        Arc::new(database)
+    });
+
+    let svg = sanitize(&String::from_utf8_lossy(svg));
+    let options = Options {
+        fontdb: fonts.clone(),
+        image_href_resolver: ImageHrefResolver {
+            resolve_data: ImageHrefResolver::default_data_resolver(),
+            resolve_string: Box::new(|_, _| None),
+        },
+        ..Options::default()
+    };
+
+    let tree = resvg::usvg::Tree::from_data(svg.as_bytes(), &options)
+        .map_err(|e| MdzError::Render(format!("Invalid SVG: {}", e)))?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| MdzError::Render("SVG has zero size".to_string()))?;
+
+    resvg::render(&tree, resvg::tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap
+        .encode_png()
+        .map_err(|e| MdzError::Render(format!("PNG encoding failed: {}", e)))
+}
+
+/// Render an SVG image to PNG
+#[cfg(not(feature = "svg-raster"))]
+pub fn rasterize(_svg: &[u8]) -> Result<Vec<u8>> {
+    Err(MdzError::Render(
+        "SVG rasterization requires the `svg-raster` feature".to_string(),
+    ))
+}
+
+/// Check a single attribute, returning the issue it represents
+fn check_attribute(tag: &str, attr: &Attribute) -> Option<SvgIssue> {
+    let name = local_name(attr.name);
+    let value = attr.value.trim();
+
+    if name.starts_with("on") {
+        return Some(SvgIssue {
+            kind: SvgIssueKind::EventHandler,
+            detail: format!("{} on <{}>", attr.name, tag),
+        });
+    }
+
+    if name == "href" {
+        let lower = value.to_ascii_lowercase();
+        if lower.starts_with("javascript:") {
+            return Some(SvgIssue {
+                kind: SvgIssueKind::Script,
+                detail: format!("javascript: URL on <{}>", tag),
+            });
+        }
+        if !value.is_empty() && !value.starts_with('#') && !lower.starts_with("data:") {
+            return Some(SvgIssue {
+                kind: SvgIssueKind::ExternalReference,
+                detail: format!("{}=\"{}\" on <{}>", attr.name, value, tag),
+            });
+        }
+    }
+
+    if name == "style" || value.contains("url(") {
+        for reference in css::analyze(value).references {
+            if let CssTarget::External(url) = reference.target("") {
+                return Some(SvgIssue {
+                    kind: SvgIssueKind::ExternalReference,
+                    detail: format!("{} loads {} on <{}>", attr.name, url, tag),
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// Check an `<animate>` or `<set>` element, which could turn a link into a `javascript:` URL
+fn check_animation(tag: &Tag) -> Option<SvgIssue> {
+    if tag.closing || !matches!(local_name(tag.name).as_str(), "animate" | "set") {
+        return None;
+    }
+    let target = tag.attrs.iter().find(|a| a.name.eq_ignore_ascii_case("attributeName"))?;
+    if local_name(target.value.trim()) != "href" {
+        return None;
+    }
+    let script = tag
+        .attrs
+        .iter()
+        .filter(|a| matches!(a.name.to_ascii_lowercase().as_str(), "values" | "from" | "to" | "by"))
+        .any(|a| a.value.to_ascii_lowercase().contains("javascript:"));
+    Some(SvgIssue {
+        kind: if script { SvgIssueKind::Script } else { SvgIssueKind::ExternalReference },
+        detail: format!("<{}> changes {}", tag.name, target.value.trim()),
+    })
+}
+
+/// Lowercased name without its namespace prefix, so `svg:script` is `script`
+fn local_name(name: &str) -> String {
+    name.rsplit(':').next().unwrap_or(name).to_ascii_lowercase()
+}
+
+/// Stylesheet text of a `<style>` element's content token, as `(before, css, after)`,
+/// where a CDATA section keeps its delimiters outside the CSS
+fn stylesheet<'a>(token: &Token<'a>) -> Option<(&'a str, &'a str, &'a str)> {
+    match token {
+        Token::Text(text) => Some(("", text, "")),
+        Token::Other(raw) => {
+            let css = raw.strip_prefix("<![CDATA[")?;
+            match css.strip_suffix("]]>") {
+                Some(css) => Some(("<![CDATA[", css, "]]>")),
+                None => Some(("<![CDATA[", css, "")),
+            }
+        }
+        Token::Tag(_) => None,
+    }
+}
+
+fn is_entity_doctype(raw: &str) -> bool {
+    raw.len() > 9 && raw[..9].eq_ignore_ascii_case("<!doctype") && raw.contains("<!ENTITY")
+}
+
+/// Check for a processing instruction other than the `<?xml ...?>` declaration
+fn is_processing_instruction(raw: &str) -> bool {
+    let Some(rest) = raw.strip_prefix("<?") else {
+        return false;
+    };
+    let target = rest.split(|c: char| c.is_whitespace() || c == '?').next().unwrap_or("");
+    target != "xml"
+}
+
+/// Drop named entity references other than XML's predefined ones, which are
+/// undefined without the DOCTYPE that declared them
+fn drop_entity_references(text: &str) -> Cow<'_, str> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| Regex::new(r"&([A-Za-z_:][\w.:-]*);").unwrap());
+    pattern.replace_all(text, |caps: &Captures| match &caps[1] {
+        "amp" | "lt" | "gt" | "quot" | "apos" => caps[0].to_string(),
+        _ => String::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(svg: &str) -> Vec<SvgIssueKind> {
+        scan(svg).into_iter().map(|issue| issue.kind).collect()
+    }
+
+    #[test]
+    fn clean_images_have_no_issues() {
+        let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\"><defs><linearGradient id=\"g\"/></defs>\
+                   <rect fill=\"url(#g)\" width=\"10\" height=\"10\"/><use href=\"#g\"/></svg>";
+        assert!(scan(svg).is_empty());
+        assert_eq!(sanitize(svg), svg);
+    }
+
+    #[test]
+    fn finds_scripts_and_handlers() {
+        let svg = "<svg onload=\"x()\"><script>x()</script><svg:script>y()</svg:script>\
+                   <a xlink:href=\"javascript:z()\"><text>t</text></a></svg>";
+        assert_eq!(
+            kinds(svg),
+            [SvgIssueKind::EventHandler, SvgIssueKind::Script, SvgIssueKind::Script, SvgIssueKind::Script]
+        );
+    }
+
+    #[test]
+    fn finds_external_references() {
+        let svg = "<svg><image href=\"https://example.com/a.png\"/>\
+                   <style><![CDATA[rect { fill: url(https://example.com/p.svg#p) }]]></style></svg>";
+        assert_eq!(kinds(svg), [SvgIssueKind::ExternalReference, SvgIssueKind::ExternalReference]);
+    }
+
+    #[test]
+    fn finds_animated_links_and_entities() {
+        let svg = "<!DOCTYPE svg [<!ENTITY a \"b\">]><svg><a><set attributeName=\"href\" to=\"javascript:x()\"/></a>\
+                   <foreignObject><div/></foreignObject></svg>";
+        assert_eq!(
+            kinds(svg),
+            [SvgIssueKind::EntityDeclaration, SvgIssueKind::Script, SvgIssueKind::ForeignObject]
+        );
+    }
+
+    #[test]
+    fn finds_and_strips_processing_instructions() {
+        let svg = "<?xml version=\"1.0\"?><?xml-stylesheet href=\"http://example.com/s.css\"?><svg/>";
+        assert_eq!(kinds(svg), [SvgIssueKind::ExternalReference]);
+        assert_eq!(sanitize(svg), "<?xml version=\"1.0\"?><svg/>");
+    }
+
+    #[test]
+    fn sanitize_drops_undeclared_entity_references() {
+        let svg = "<!DOCTYPE svg [<!ENTITY a \"b\">]><svg><text x=\"&a;\">&a; &amp; &#65;</text>\
+                   <style>text { font: &a; }</style><style><![CDATA[a::after { content: \"&a;\" }]]></style></svg>";
+        assert_eq!(
+            sanitize(svg),
+            "<svg><text x=\"\"> &amp; &#65;</text><style>text { font:  }</style>\
+             <style><![CDATA[a::after { content: \"&a;\" }]]></style></svg>"
+        );
+    }
+
+    #[test]
+    fn sanitize_removes_every_issue() {
+        let svg = "<!DOCTYPE svg [<!ENTITY a \"b\">]><svg onload=\"x()\"><svg:script>y()</svg:script>\
+                   <a href=\"javascript:z()\"><animate attributeName=\"xlink:href\" values=\"https://e.com\"/>\
+                   <text>t</text></a><foreignObject><div/></foreignObject>\
+                   <style>rect { fill: url(https://example.com/p.svg#p) }</style><rect width=\"1\"/></svg>";
+        let clean = sanitize(svg);
+        assert!(scan(&clean).is_empty(), "{}", clean);
+        assert_eq!(clean, "<svg><a><text>t</text></a><style>rect { fill: url(\"\") }</style><rect width=\"1\"/></svg>");
+    }
+
+    #[cfg(feature = "svg-raster")]
+    #[test]
+    fn rasterizes_to_png() {
+        let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"4\" height=\"4\"><rect width=\"4\" height=\"4\"/></svg>";
+        assert!(rasterize(svg).unwrap().starts_with(b"\x89PNG"));
+    }
+}
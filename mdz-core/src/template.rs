@@ -0,0 +1,116 @@
+use pulldown_cmark::escape::escape_html;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// HTML document shell with escaped head content
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HtmlShell {
+    pub(crate) lang: String,
+    pub(crate) title: String,
+    pub(crate) meta: Vec<MetaTag>,
+    pub(crate) styles: Vec<String>,
+    /// Linked stylesheets as `(href, media)`
+    pub(crate) stylesheets: Vec<(String, Option<String>)>,
+}
+
+/// `<meta>` element in the document head
+#[derive(Debug, Clone)]
+pub(crate) enum MetaTag {
+    /// `<meta name="..." content="...">`
+    Name(String, String),
+    /// `<meta http-equiv="..." content="...">`
+    HttpEquiv(String, String),
+}
+
+impl HtmlShell {
+    /// Create a shell with the given language and title
+    pub(crate) fn new(lang: &str, title: &str) -> Self {
+        Self {
+            lang: lang.to_string(),
+            title: title.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Add a named meta tag
+    pub(crate) fn meta(&mut self, name: &str, content: &str) -> &mut Self {
+        self.meta.push(MetaTag::Name(name.to_string(), content.to_string()));
+        self
+    }
+
+    /// Add an http-equiv meta tag
+    pub(crate) fn http_equiv(&mut self, name: &str, content: &str) -> &mut Self {
+        self.meta.push(MetaTag::HttpEquiv(name.to_string(), content.to_string()));
+        self
+    }
+
+    /// Add a stylesheet to embed in a `<style>` element
+    pub(crate) fn style(&mut self, css: &str) -> &mut Self {
+        self.styles.push(css.to_string());
+        self
+    }
+
+    /// Link an external stylesheet, optionally restricted to a media query
+    pub(crate) fn stylesheet(&mut self, href: &str, media: Option<&str>) -> &mut Self {
+        self.stylesheets.push((href.to_string(), media.map(str::to_string)));
+        self
+    }
+
+    /// Render the complete document around an already-rendered body
+    pub(crate) fn render(&self, body: &str) -> String {
+        let mut html = String::with_capacity(body.len() + 1024);
+
+        html.push_str("<!DOCTYPE html>\n<html lang=\"");
+        push_escaped(&mut html, &self.lang);
+        html.push_str("\">\n<head>\n    <meta charset=\"UTF-8\">\n");
+
+        for meta in &self.meta {
+            let (attr, name, content) = match meta {
+                MetaTag::Name(name, content) => ("name", name, content),
+                MetaTag::HttpEquiv(name, content) => ("http-equiv", name, content),
+            };
+            html.push_str(&format!("    <meta {}=\"", attr));
+            push_escaped(&mut html, name);
+            html.push_str("\" content=\"");
+            push_escaped(&mut html, content);
+            html.push_str("\">\n");
+        }
+
+        html.push_str("    <title>");
+        push_escaped(&mut html, &self.title);
+        html.push_str("</title>\n");
+
+        for (href, media) in &self.stylesheets {
+            html.push_str("    <link rel=\"stylesheet\" href=\"");
+            push_escaped(&mut html, href);
+            if let Some(media) = media {
+                html.push_str("\" media=\"");
+                push_escaped(&mut html, media);
+            }
+            html.push_str("\">\n");
+        }
+
+        for css in &self.styles {
+            html.push_str("    <style>\n");
+            html.push_str(&escape_style(css));
+            html.push_str("\n    </style>\n");
+        }
+
+        html.push_str("</head>\n<body>\n");
+        html.push_str(body);
+        html.push_str("\n</body>\n</html>");
+        html
+    }
+}
+
+/// Append text escaped for HTML content and attribute values
+pub(crate) fn push_escaped(output: &mut String, text: &str) {
+    escape_html(output, text).expect("writing to a String cannot fail");
+}
+
+/// Prevent stylesheet content from closing its `<style>` element
+pub(crate) fn escape_style(css: &str) -> String {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| Regex::new(r"(?i)</(style)").unwrap());
+    pattern.replace_all(css, "<\\/$1").into_owned()
+}
@@ -0,0 +1,136 @@
+use crate::docx::pixel_size;
+use crate::highlight::available_themes;
+use crate::markup::{tokenize, Token};
+use crate::{MdzError, Result};
+
+/// Layout of terminal output produced by [`MdzRenderer::render_terminal`](crate::MdzRenderer::render_terminal)
+#[derive(Debug, Clone)]
+pub struct TerminalOptions {
+    /// Columns to wrap at, usually the width of the terminal
+    pub width: usize,
+    /// Make links clickable with OSC 8 escape sequences; otherwise number
+    /// them and list their targets at the end
+    pub hyperlinks: bool,
+    /// Highlighting theme for code blocks, `None` to leave code uncoloured
+    pub theme: Option<String>,
+}
+
+impl Default for TerminalOptions {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            hyperlinks: true,
+            theme: Some("base16-ocean.dark".to_string()),
+        }
+    }
+}
+
+impl TerminalOptions {
+    /// Check that the highlighting theme exists
+    pub(crate) fn validate(&self) -> Result<()> {
+        match &self.theme {
+            Some(theme) if cfg!(feature = "highlight") && !available_themes().contains(theme) => {
+                Err(MdzError::Render(format!("Unknown highlight theme: {}", theme)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Text styles of terminal output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Style {
+    Strong,
+    Emphasis,
+    Strikethrough,
+    Code,
+    Title,
+    Heading,
+    TableHeader,
+    Link,
+    Image,
+    Rule,
+}
+
+impl Style {
+    pub(crate) const ALL: [Style; 10] = [
+        Style::Strong,
+        Style::Emphasis,
+        Style::Strikethrough,
+        Style::Code,
+        Style::Title,
+        Style::Heading,
+        Style::TableHeader,
+        Style::Link,
+        Style::Image,
+        Style::Rule,
+    ];
+
+    /// SGR escape sequence that turns the style on
+    pub(crate) fn sgr(self) -> &'static str {
+        match self {
+            Style::Strong | Style::TableHeader => "\x1b[1m",
+            Style::Emphasis => "\x1b[3m",
+            Style::Strikethrough => "\x1b[9m",
+            Style::Code => "\x1b[36m",
+            Style::Title => "\x1b[1;4;35m",
+            Style::Heading => "\x1b[1;35m",
+            Style::Link => "\x1b[4;34m",
+            Style::Image | Style::Rule => "\x1b[2m",
+        }
+    }
+}
+
+/// Turn all styles off
+pub(crate) const RESET: &str = "\x1b[0m";
+
+/// Start of an OSC 8 hyperlink
+pub(crate) fn hyperlink_start(url: &str) -> String {
+    // Control characters would end the escape sequence early
+    let url: String = url.chars().filter(|c| !c.is_control()).collect();
+    format!("\x1b]8;;{}\x1b\\", url)
+}
+
+/// End of an OSC 8 hyperlink
+pub(crate) const HYPERLINK_END: &str = "\x1b]8;;\x1b\\";
+
+/// Format and pixel size of an image, such as `PNG 640×480`
+pub(crate) fn describe_image(path: &str, data: &[u8]) -> String {
+    let format = path
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_uppercase())
+        .unwrap_or_default();
+    let size = if format == "SVG" {
+        svg_size(&String::from_utf8_lossy(data))
+    } else {
+        pixel_size(data).map(|(width, height)| (width as f64, height as f64))
+    };
+    match size {
+        Some((width, height)) => format!("{} {}×{}", format, width.round(), height.round()).trim().to_string(),
+        None => format,
+    }
+}
+
+/// Size of an SVG from the root element's `width` and `height`, else its `viewBox`
+fn svg_size(svg: &str) -> Option<(f64, f64)> {
+    let tokens = tokenize(svg);
+    let root = tokens.iter().find_map(|token| match token {
+        Token::Tag(tag) if tag.name.eq_ignore_ascii_case("svg") && !tag.closing => Some(tag),
+        _ => None,
+    })?;
+    let attribute = |name: &str| root.attrs.iter().find(|a| a.name.eq_ignore_ascii_case(name)).map(|a| a.value);
+    let length = |name: &str| attribute(name)?.trim().trim_end_matches("px").parse::<f64>().ok();
+
+    if let (Some(width), Some(height)) = (length("width"), length("height")) {
+        return Some((width, height));
+    }
+    let view_box: Vec<f64> = attribute("viewBox")?
+        .split([' ', ','])
+        .filter(|part| !part.is_empty())
+        .filter_map(|part| part.parse().ok())
+        .collect();
+    match view_box[..] {
+        [_, _, width, height] => Some((width, height)),
+        _ => None,
+    }
+}
@@ -0,0 +1,863 @@
+use crate::highlight::{highlight_ansi, FenceInfo};
+use crate::markup::{self, tokenize, Token};
+use crate::terminal::{self, Style, TerminalOptions};
+use crate::{MarkdownDialect, MdzDocument};
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, LinkType, Parser, Tag};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Inline text marks where terminal styles start and end with characters from
+/// the supplementary private use areas; they take no columns when wrapping and
+/// become escape sequences as each line is written.
+const STYLE_MARKER: u32 = 0xF0000;
+/// Marker of the OSC 8 hyperlink with index 0, followed by the others
+const HYPERLINK_MARKER: u32 = 0xF0100;
+const END_MARKER: char = '\u{10FFFD}';
+
+/// Elements that start a new paragraph when raw HTML is converted to text
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "dd", "details", "div", "dl", "dt", "figcaption", "figure",
+    "footer", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "nav", "ol", "p", "pre",
+    "section", "summary", "table", "tr", "ul",
+];
+
+/// Layout of plain text produced by [`MdzRenderer::render_text`](crate::MdzRenderer::render_text)
+#[derive(Debug, Clone)]
+pub struct TextOptions {
+    /// Wrap paragraphs at this many columns, `None` to keep each on one line
+    pub width: Option<usize>,
+    /// Number links as `[1]` and list their targets at the end; otherwise
+    /// targets follow the link text in parentheses
+    pub link_references: bool,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self {
+            width: Some(72),
+            link_references: true,
+        }
+    }
+}
+
+/// Render a document to plain text
+///
+/// Markup is dropped rather than stripped from the source: lists keep their
+/// markers, code blocks are indented, tables are laid out in aligned columns
+/// and images are replaced by their alt text. Raw HTML contributes its text.
+pub(crate) fn render(document: &MdzDocument, dialect: MarkdownDialect, options: &TextOptions) -> String {
+    let mut writer = Writer::new(options, None);
+    for event in Parser::new_ext(&document.content, dialect.parser_options()) {
+        writer.event(event);
+    }
+    writer.finish()
+}
+
+/// Render a document to text styled with ANSI escape sequences
+///
+/// The layout matches [`render`], with headings, emphasis, code and links
+/// styled instead of underlined or dropped, and image placeholders showing
+/// the format and size of the image.
+pub(crate) fn render_terminal(document: &MdzDocument, dialect: MarkdownDialect, options: &TerminalOptions) -> String {
+    let text_options = TextOptions {
+        width: Some(options.width),
+        link_references: !options.hyperlinks,
+    };
+    let mut writer = Writer::new(&text_options, Some((options, document)));
+    for event in Parser::new_ext(&document.content, dialect.parser_options()) {
+        writer.event(event);
+    }
+    writer.finish()
+}
+
+/// Block that prefixes the lines inside it
+struct Container {
+    /// Prefix of the first line, such as a list marker
+    first: Option<String>,
+    /// Prefix of the following lines
+    rest: String,
+    /// List item, as opposed to a quote or footnote
+    item: bool,
+}
+
+struct List {
+    next: Option<u64>,
+    /// Items are separated by blank lines
+    loose: bool,
+}
+
+struct Table {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<String>>,
+    row: Vec<String>,
+}
+
+/// Open link or image
+struct Span {
+    /// Offset in the inline text where it starts
+    start: usize,
+    /// Link target to reference, or image path
+    target: Option<String>,
+    /// A style marker was opened for it
+    styled: bool,
+}
+
+struct Writer<'o> {
+    options: &'o TextOptions,
+    output: String,
+    containers: Vec<Container>,
+    /// Inline text of the current block; `\n` marks hard breaks
+    inline: String,
+    /// Depth of paragraphs, headings and table cells
+    inline_blocks: usize,
+    /// Separate the next block with a blank line
+    blank: bool,
+    lists: Vec<List>,
+    /// Raw HTML blocks waiting to be converted
+    html: String,
+    code: Option<String>,
+    table: Option<Table>,
+    spans: Vec<Span>,
+    references: Vec<String>,
+    /// Terminal styling, `None` for plain text
+    terminal: Option<(&'o TerminalOptions, &'o MdzDocument)>,
+    /// Language of the current code block
+    language: Option<String>,
+    /// Targets of OSC 8 hyperlinks, by marker
+    hyperlinks: Vec<String>,
+    /// Style markers open at the end of the last line written
+    active: Vec<char>,
+}
+
+impl<'o> Writer<'o> {
+    fn new(options: &'o TextOptions, terminal: Option<(&'o TerminalOptions, &'o MdzDocument)>) -> Self {
+        Self {
+            options,
+            output: String::new(),
+            containers: Vec::new(),
+            inline: String::new(),
+            inline_blocks: 0,
+            blank: false,
+            lists: Vec::new(),
+            html: String::new(),
+            code: None,
+            table: None,
+            spans: Vec::new(),
+            references: Vec::new(),
+            terminal,
+            language: None,
+            hyperlinks: Vec::new(),
+            active: Vec::new(),
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        if !matches!(event, Event::Html(_)) && !self.html.is_empty() {
+            self.flush_html();
+        }
+
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                let text = clean(&text, self.terminal.is_some());
+                match &mut self.code {
+                    Some(code) => code.push_str(&text),
+                    None => self.inline.push_str(&text),
+                }
+            }
+            Event::Code(code) => {
+                self.open(Style::Code);
+                self.inline.push_str(&clean(&code, self.terminal.is_some()));
+                self.close();
+            }
+            Event::Html(html) => {
+                let html = clean(&html, self.terminal.is_some());
+                if self.inline_blocks > 0 || !self.inline.trim().is_empty() {
+                    self.inline_html(&html);
+                } else {
+                    self.html.push_str(&html);
+                }
+            }
+            Event::FootnoteReference(label) => {
+                let label = clean(&label, self.terminal.is_some());
+                self.inline.push_str(&format!("[^{}]", label));
+            }
+            Event::SoftBreak => self.inline.push(' '),
+            Event::HardBreak => self.inline.push('\n'),
+            Event::Rule => {
+                self.flush();
+                self.separate();
+                let width = self.available().unwrap_or(72).min(72);
+                if self.terminal.is_some() {
+                    let rule = format!("{}{}{}", style_marker(Style::Rule), "─".repeat(width), END_MARKER);
+                    self.line(&rule);
+                } else {
+                    self.line(&"-".repeat(width));
+                }
+                self.blank = true;
+            }
+            Event::TaskListMarker(checked) => self.inline.push_str(if checked { "[x] " } else { "[ ] " }),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                self.flush();
+                if self.containers.last().is_some_and(|c| c.item) {
+                    if let Some(list) = self.lists.last_mut() {
+                        list.loose = true;
+                    }
+                }
+                self.inline_blocks += 1;
+            }
+            Tag::Heading(level, ..) => {
+                self.flush();
+                self.inline_blocks += 1;
+                self.open(if level == HeadingLevel::H1 { Style::Title } else { Style::Heading });
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                let prefix = if self.terminal.is_some() { "│ " } else { "> " };
+                self.push_container(Container {
+                    first: None,
+                    rest: prefix.to_string(),
+                    item: false,
+                });
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush();
+                self.code = Some(String::new());
+                self.language = match kind {
+                    CodeBlockKind::Fenced(info) => FenceInfo::parse(&info).language,
+                    CodeBlockKind::Indented => None,
+                };
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.separate();
+                self.lists.push(List { next: start, loose: false });
+            }
+            Tag::Item => {
+                self.flush();
+                let loose = self.lists.last().is_some_and(|list| list.loose);
+                if !loose {
+                    self.blank = false;
+                }
+                let marker = match self.lists.last_mut().and_then(|list| list.next.as_mut()) {
+                    Some(number) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    None if self.terminal.is_some() => "• ".to_string(),
+                    None => "- ".to_string(),
+                };
+                self.push_container(Container {
+                    rest: " ".repeat(marker.width()),
+                    first: Some(marker),
+                    item: true,
+                });
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.flush();
+                self.push_container(Container {
+                    first: Some(format!("[^{}]: ", clean(&label, self.terminal.is_some()))),
+                    rest: "    ".to_string(),
+                    item: false,
+                });
+            }
+            Tag::Table(alignments) => {
+                self.flush();
+                self.table = Some(Table {
+                    alignments,
+                    rows: Vec::new(),
+                    row: Vec::new(),
+                });
+            }
+            Tag::TableCell => self.inline_blocks += 1,
+            Tag::Link(link_type, url, _) => {
+                let url = clean(&url, self.terminal.is_some());
+                let external = !url.is_empty() && !url.starts_with('#');
+                let hyperlink = external && self.terminal.is_some_and(|(options, _)| options.hyperlinks);
+                let start = self.inline.len();
+                if hyperlink {
+                    let marker = HYPERLINK_MARKER + self.hyperlinks.len() as u32;
+                    self.hyperlinks.push(url.clone());
+                    self.inline.push(char::from_u32(marker).unwrap_or(END_MARKER));
+                } else {
+                    self.open(Style::Link);
+                }
+                // Hyperlinks and autolinks already show where they lead
+                let autolink = matches!(link_type, LinkType::Autolink | LinkType::Email);
+                let target = (external && !autolink && !hyperlink).then(|| url.clone());
+                self.spans.push(Span {
+                    start,
+                    target,
+                    styled: self.terminal.is_some(),
+                });
+            }
+            Tag::Image(_, path, _) => {
+                self.spans.push(Span {
+                    start: self.inline.len(),
+                    target: Some(clean(&path, self.terminal.is_some())),
+                    styled: false,
+                });
+            }
+            Tag::Emphasis => self.open(Style::Emphasis),
+            Tag::Strong => self.open(Style::Strong),
+            Tag::Strikethrough => self.open(Style::Strikethrough),
+            Tag::TableHead | Tag::TableRow => {}
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                self.inline_blocks -= 1;
+                self.flush();
+                self.blank = true;
+            }
+            Tag::Heading(level, ..) => {
+                self.inline_blocks -= 1;
+                self.close();
+                let text = std::mem::take(&mut self.inline);
+                let lines = self.wrap(&text);
+                if !lines.is_empty() {
+                    self.separate();
+                    for line in &lines {
+                        self.line(line);
+                    }
+                    // Terminal headings stand out through their style instead
+                    let underline = match level {
+                        _ if self.terminal.is_some() => None,
+                        HeadingLevel::H1 => Some("="),
+                        HeadingLevel::H2 => Some("-"),
+                        _ => None,
+                    };
+                    if let Some(underline) = underline {
+                        let width = lines.iter().map(|line| visible_width(line, false)).max().unwrap_or(0);
+                        self.line(&underline.repeat(width));
+                    }
+                }
+                self.blank = true;
+            }
+            Tag::BlockQuote | Tag::FootnoteDefinition(_) => {
+                self.flush();
+                self.containers.pop();
+                self.blank = true;
+            }
+            Tag::CodeBlock(_) => {
+                let code = self.code.take().unwrap_or_default();
+                let code = code.trim_end_matches('\n');
+                let language = self.language.take();
+                let highlighted = match (self.terminal, language) {
+                    (Some((TerminalOptions { theme: Some(theme), .. }, _)), Some(language)) => {
+                        highlight_ansi(code, &language, theme).ok().flatten()
+                    }
+                    _ => None,
+                };
+                let lines = highlighted.unwrap_or_else(|| code.lines().map(String::from).collect());
+                self.separate();
+                for line in &lines {
+                    self.line(&format!("    {}", line));
+                }
+                self.blank = true;
+            }
+            Tag::List(_) => {
+                self.flush();
+                self.lists.pop();
+                self.blank = true;
+            }
+            Tag::Item => {
+                self.flush();
+                self.containers.pop();
+            }
+            Tag::Table(_) => {
+                if let Some(table) = self.table.take() {
+                    self.separate();
+                    for line in layout_table(&table, self.terminal.is_some()) {
+                        self.line(&line);
+                    }
+                }
+                self.blank = true;
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    let row = std::mem::take(&mut table.row);
+                    table.rows.push(row);
+                }
+            }
+            Tag::TableCell => {
+                self.inline_blocks -= 1;
+                let cell = std::mem::take(&mut self.inline);
+                if let Some(table) = &mut self.table {
+                    table.row.push(normalize_whitespace(&cell));
+                }
+            }
+            Tag::Link(..) => {
+                let Some(span) = self.spans.pop() else {
+                    return;
+                };
+                if span.styled {
+                    self.inline.push(END_MARKER);
+                }
+                let Some(target) = span.target else {
+                    return;
+                };
+                let text = strip_markers(&self.inline[span.start..], self.terminal.is_some());
+                let text = text.trim();
+                if text == target || target.strip_prefix("mailto:") == Some(text) {
+                    return;
+                }
+                if self.options.link_references {
+                    let number = match self.references.iter().position(|r| *r == target) {
+                        Some(index) => index + 1,
+                        None => {
+                            self.references.push(target);
+                            self.references.len()
+                        }
+                    };
+                    self.inline.push_str(&format!("[{}]", number));
+                } else {
+                    self.inline.push_str(&format!(" ({})", target));
+                }
+            }
+            Tag::Image(..) => {
+                let Some(span) = self.spans.pop() else {
+                    return;
+                };
+                let alt = normalize_whitespace(&strip_markers(&self.inline[span.start..], self.terminal.is_some()));
+                self.inline.truncate(span.start);
+                let Some((_, document)) = self.terminal else {
+                    self.image_placeholder(&alt);
+                    return;
+                };
+                let path = span.target.unwrap_or_default();
+                let path = path.strip_prefix("./").unwrap_or(&path);
+                let description = document.images.get(path).map(|data| terminal::describe_image(path, data));
+                let text = match (alt.is_empty(), description) {
+                    (false, Some(description)) => format!("[Image: {} ({})]", alt, description),
+                    (true, Some(description)) => format!("[Image: {}]", description),
+                    (false, None) => format!("[Image: {}]", alt),
+                    (true, None) => "[Image]".to_string(),
+                };
+                self.open(Style::Image);
+                self.inline.push_str(&text);
+                self.close();
+            }
+            Tag::Emphasis | Tag::Strong | Tag::Strikethrough => self.close(),
+        }
+    }
+
+    /// Finish the document, appending the link references
+    fn finish(mut self) -> String {
+        self.flush_html();
+        self.flush();
+        self.containers.clear();
+        if !self.references.is_empty() {
+            self.separate_always();
+            let references = std::mem::take(&mut self.references);
+            for (index, target) in references.iter().enumerate() {
+                self.line(&format!("[{}] {}", index + 1, target));
+            }
+        }
+        self.output
+    }
+
+    /// Write pending inline text as a wrapped paragraph
+    fn flush(&mut self) {
+        let text = std::mem::take(&mut self.inline);
+        let lines = self.wrap(&text);
+        if lines.is_empty() {
+            return;
+        }
+        self.separate();
+        for line in &lines {
+            self.line(line);
+        }
+    }
+
+    /// Convert pending raw HTML blocks to paragraphs of text
+    fn flush_html(&mut self) {
+        let html = std::mem::take(&mut self.html);
+        let mut skip: Option<&str> = None;
+        for token in tokenize(&html) {
+            match token {
+                Token::Tag(tag) if skip.is_some() => {
+                    if tag.closing && skip.is_some_and(|name| tag.name.eq_ignore_ascii_case(name)) {
+                        skip = None;
+                    }
+                }
+                Token::Tag(tag) => {
+                    let name = tag.name.to_ascii_lowercase();
+                    if !tag.closing && matches!(name.as_str(), "script" | "style" | "template") {
+                        skip = Some(tag.name);
+                    } else if name == "br" {
+                        self.inline.push('\n');
+                    } else if name == "img" {
+                        self.image_placeholder(&html_alt(&tag, self.terminal.is_some()));
+                    } else if BLOCK_ELEMENTS.contains(&name.as_str()) && !self.inline.trim().is_empty() {
+                        self.flush();
+                        self.blank = true;
+                    }
+                }
+                Token::Text(text) if skip.is_none() => {
+                    let text = decode_entities(&text.replace('\n', " "), self.terminal.is_some());
+                    self.inline.push_str(&text);
+                }
+                Token::Text(_) | Token::Other(_) => {}
+            }
+        }
+        if !self.inline.trim().is_empty() {
+            self.flush();
+            self.blank = true;
+        }
+    }
+
+    /// Append the text of inline HTML, such as `<br>` or `<kbd>` tags
+    fn inline_html(&mut self, html: &str) {
+        for token in tokenize(html) {
+            match token {
+                Token::Tag(tag) if tag.name.eq_ignore_ascii_case("br") => self.inline.push('\n'),
+                Token::Tag(tag) if tag.name.eq_ignore_ascii_case("img") => {
+                    self.image_placeholder(&html_alt(&tag, self.terminal.is_some()));
+                }
+                Token::Text(text) => {
+                    let text = decode_entities(text, self.terminal.is_some());
+                    self.inline.push_str(&text);
+                }
+                Token::Tag(_) | Token::Other(_) => {}
+            }
+        }
+    }
+
+    fn image_placeholder(&mut self, alt: &str) {
+        self.open(Style::Image);
+        if alt.trim().is_empty() {
+            self.inline.push_str("[Image]");
+        } else {
+            self.inline.push_str(&format!("[Image: {}]", alt.trim()));
+        }
+        self.close();
+    }
+
+    /// Start a terminal style
+    fn open(&mut self, style: Style) {
+        if self.terminal.is_some() {
+            self.inline.push(style_marker(style));
+        }
+    }
+
+    /// End the innermost terminal style
+    fn close(&mut self) {
+        if self.terminal.is_some() {
+            self.inline.push(END_MARKER);
+        }
+    }
+
+    fn push_container(&mut self, container: Container) {
+        self.separate();
+        self.containers.push(container);
+    }
+
+    /// Write the blank line owed to the previous block, if any
+    fn separate(&mut self) {
+        if self.blank {
+            self.separate_always();
+        }
+    }
+
+    fn separate_always(&mut self) {
+        self.blank = false;
+        if self.output.is_empty() {
+            return;
+        }
+        let prefix: String = self.containers.iter().map(|c| c.rest.as_str()).collect();
+        self.output.push_str(prefix.trim_end());
+        self.output.push('\n');
+    }
+
+    /// Write one line behind the prefixes of the enclosing blocks
+    fn line(&mut self, text: &str) {
+        let mut line = String::new();
+        for container in &mut self.containers {
+            match container.first.take() {
+                Some(first) => line.push_str(&first),
+                None => line.push_str(&container.rest),
+            }
+        }
+        if self.terminal.is_some() {
+            let styled = self.styled(text);
+            line.push_str(&styled);
+        } else {
+            line.push_str(text);
+        }
+        self.output.push_str(line.trim_end());
+        self.output.push('\n');
+    }
+
+    /// Replace style markers with escape sequences, ending the line with
+    /// every style turned off and reopening them on the next
+    fn styled(&mut self, text: &str) -> String {
+        let mut output = String::new();
+        for marker in self.active.clone() {
+            output.push_str(&self.escape(marker, true));
+        }
+        for c in text.chars() {
+            if c == END_MARKER {
+                let Some(marker) = self.active.pop() else {
+                    continue;
+                };
+                if marker as u32 >= HYPERLINK_MARKER {
+                    output.push_str(terminal::HYPERLINK_END);
+                }
+                output.push_str(terminal::RESET);
+                for marker in self.active.clone() {
+                    output.push_str(&self.escape(marker, false));
+                }
+            } else if is_marker(c) {
+                self.active.push(c);
+                output.push_str(&self.escape(c, true));
+            } else {
+                output.push(c);
+            }
+        }
+        if !self.active.is_empty() {
+            if self.active.iter().any(|&marker| marker as u32 >= HYPERLINK_MARKER) {
+                output.push_str(terminal::HYPERLINK_END);
+            }
+            output.push_str(terminal::RESET);
+        }
+        output
+    }
+
+    /// Escape sequence for a style marker; hyperlinks are only started when `link` is set
+    fn escape(&self, marker: char, link: bool) -> String {
+        let code = marker as u32;
+        if code >= HYPERLINK_MARKER {
+            let url = self.hyperlinks.get((code - HYPERLINK_MARKER) as usize);
+            let start = match url {
+                Some(url) if link => terminal::hyperlink_start(url),
+                _ => String::new(),
+            };
+            return format!("{}{}", start, Style::Link.sgr());
+        }
+        Style::ALL
+            .get((code - STYLE_MARKER) as usize)
+            .map(|style| style.sgr().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Columns left for text after the prefixes
+    fn available(&self) -> Option<usize> {
+        let prefix: usize = self.containers.iter().map(|c| c.rest.width()).sum();
+        self.options.width.map(|width| width.saturating_sub(prefix).max(20))
+    }
+
+    /// Break inline text into lines at hard breaks and, if set, the wrap width
+    fn wrap(&self, text: &str) -> Vec<String> {
+        let width = self.available();
+        let mut lines = Vec::new();
+        for segment in text.split('\n') {
+            let mut line = String::new();
+            let mut line_width = 0;
+            for word in segment.split([' ', '\t']).filter(|word| !word.is_empty()) {
+                let word_width = visible_width(word, self.terminal.is_some());
+                if !line.is_empty() {
+                    if width.is_some_and(|width| line_width + 1 + word_width > width) {
+                        lines.push(std::mem::take(&mut line));
+                        line_width = 0;
+                    } else {
+                        line.push(' ');
+                        line_width += 1;
+                    }
+                }
+                line.push_str(word);
+                line_width += word_width;
+            }
+            lines.push(line);
+        }
+        // Hard breaks at the edges of a paragraph leave empty lines behind
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        while lines.first().is_some_and(|line| line.is_empty()) {
+            lines.remove(0);
+        }
+        lines
+    }
+}
+
+/// Lay out a table in columns separated by `|`, with a rule under the header
+fn layout_table(table: &Table, styled: bool) -> Vec<String> {
+    let columns = table.rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut widths = vec![0; columns];
+    for row in &table.rows {
+        for (column, cell) in row.iter().enumerate() {
+            widths[column] = widths[column].max(visible_width(cell, styled));
+        }
+    }
+
+    let mut lines = Vec::new();
+    for (index, row) in table.rows.iter().enumerate() {
+        let cells: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(column, &width)| {
+                let cell = row.get(column).map(String::as_str).unwrap_or("");
+                let padding = width - visible_width(cell, styled);
+                let cell = if styled && index == 0 {
+                    format!("{}{}{}", style_marker(Style::TableHeader), cell, END_MARKER)
+                } else {
+                    cell.to_string()
+                };
+                match table.alignments.get(column) {
+                    Some(Alignment::Right) => format!("{}{}", " ".repeat(padding), cell),
+                    Some(Alignment::Center) => {
+                        format!("{}{}{}", " ".repeat(padding / 2), cell, " ".repeat(padding - padding / 2))
+                    }
+                    _ => format!("{}{}", cell, " ".repeat(padding)),
+                }
+            })
+            .collect();
+        lines.push(cells.join(" | "));
+        if index == 0 {
+            let rule: Vec<String> = widths.iter().map(|&width| "-".repeat(width.max(1))).collect();
+            lines.push(rule.join("-|-"));
+        }
+    }
+    lines
+}
+
+/// Alt text of an HTML `<img>` tag
+fn html_alt(tag: &markup::Tag, styled: bool) -> String {
+    tag.attrs
+        .iter()
+        .find(|attr| attr.name.eq_ignore_ascii_case("alt"))
+        .map(|attr| decode_entities(attr.value, styled))
+        .unwrap_or_default()
+}
+
+fn style_marker(style: Style) -> char {
+    let index = Style::ALL.iter().position(|&s| s == style).unwrap_or(0);
+    char::from_u32(STYLE_MARKER + index as u32).unwrap_or(END_MARKER)
+}
+
+fn is_marker(c: char) -> bool {
+    (STYLE_MARKER..=0xFFFFD).contains(&(c as u32)) || c == END_MARKER
+}
+
+/// Characters of the document that must not reach the output: control
+/// characters other than line breaks and tabs, which could send escape
+/// sequences to the terminal, and, in `styled` output, the code points used
+/// as style markers. Plain text keeps private use characters such as icon
+/// font glyphs.
+fn is_unsafe(c: char, styled: bool) -> bool {
+    (styled && is_marker(c)) || (c.is_control() && !matches!(c, '\n' | '\t'))
+}
+
+/// Document text without the characters rejected by [`is_unsafe`]
+fn clean(text: &str, styled: bool) -> String {
+    text.chars().filter(|&c| !is_unsafe(c, styled)).collect()
+}
+
+/// Text without style markers, which only `styled` output contains
+fn strip_markers(text: &str, styled: bool) -> String {
+    text.chars().filter(|&c| !(styled && is_marker(c))).collect()
+}
+
+/// Columns taken by text, not counting the style markers of `styled` output
+fn visible_width(text: &str, styled: bool) -> usize {
+    text.chars().filter(|&c| !(styled && is_marker(c))).map(|c| c.width().unwrap_or(0)).sum()
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Decode character references in HTML text
+fn decode_entities(text: &str, styled: bool) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let name = &rest[1..end];
+            let c = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => match name.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => {
+                        u32::from_str_radix(&hex[1..], 16).ok().and_then(char::from_u32)
+                    }
+                    Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                    None => None,
+                },
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                if !is_unsafe(c, styled) {
+                    output.push(c);
+                }
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(markdown: &str, options: &TextOptions) -> String {
+        render(&MdzDocument::new(markdown.to_string()), MarkdownDialect::default(), options)
+    }
+
+    fn terminal(markdown: &str) -> String {
+        let options = TerminalOptions { theme: None, ..Default::default() };
+        render_terminal(&MdzDocument::new(markdown.to_string()), MarkdownDialect::default(), &options)
+    }
+
+    #[test]
+    fn wraps_paragraphs_and_numbers_links() {
+        let options = TextOptions { width: Some(20), ..Default::default() };
+        assert_eq!(
+            plain("# Title\n\nSome words that need wrapping [here](https://example.com).\n", &options),
+            "Title\n=====\n\nSome words that need\nwrapping here[1].\n\n[1] https://example.com\n"
+        );
+        let options = TextOptions { width: None, link_references: false };
+        assert_eq!(plain("[a](https://example.com)\n", &options), "a (https://example.com)\n");
+    }
+
+    #[test]
+    fn lays_out_tables_and_lists() {
+        let text = plain("| a | bb |\n|---|---:|\n| ccc | d |\n\n- one\n- two\n", &TextOptions::default());
+        assert_eq!(text, "a   | bb\n----|---\nccc |  d\n\n- one\n- two\n");
+    }
+
+    #[test]
+    fn plain_text_keeps_private_use_characters() {
+        let markdown = "Icons \u{F0001} \u{F0100} \u{10FFFD}\n";
+        assert_eq!(plain(markdown, &TextOptions::default()), markdown);
+        assert_eq!(terminal(markdown).trim_end(), "Icons");
+    }
+
+    #[test]
+    fn drops_control_characters() {
+        let text = plain("a\u{1b}[31mb &#27;c\n\n<p>d&#x1b;e</p>\n", &TextOptions::default());
+        assert_eq!(text, "a[31mb c\n\nde\n");
+    }
+}
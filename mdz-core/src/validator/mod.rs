@@ -0,0 +1,427 @@
+mod profile;
+mod rules;
+
+pub use profile::{SpecFeature, ValidationProfile};
+pub use rules::{CrossReferenceRule, InternalAnchorRule, MathSyntaxRule, SvgSafetyRule};
+
+use crate::css::{self, CssReferenceKind, CssTarget};
+use crate::{MdzDocument, MdzError, MdzParser, Result};
+use std::io::Read;
+use zip::ZipArchive;
+
+/// MDZ format validator
+pub struct MdzValidator {
+    rules: Vec<Box<dyn ValidationRule>>,
+    profile: Option<ValidationProfile>,
+}
+
+/// A validation check run against a parsed MDZ document
+///
+/// Rules are registered on an [`MdzValidator`] and run after the built-in
+/// structural checks, whenever `main.md` could be parsed.
+pub trait ValidationRule: Send + Sync {
+    /// Short identifier for the rule
+    fn name(&self) -> &str;
+
+    /// Inspect the archive and document, reporting problems to `diagnostics`
+    fn check(&self, index: &ArchiveIndex, document: &MdzDocument, diagnostics: &mut Diagnostics);
+}
+
+/// Listing of the entries in an MDZ archive
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveIndex {
+    pub entries: Vec<ArchiveEntry>,
+}
+
+/// Single entry in an MDZ archive
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub is_dir: bool,
+}
+
+impl ArchiveIndex {
+    /// Check if the archive contains a file with the given path
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.iter().any(|e| !e.is_dir && e.name == name)
+    }
+
+    /// Iterate over file entries (directories excluded)
+    pub fn files(&self) -> impl Iterator<Item = &ArchiveEntry> {
+        self.entries.iter().filter(|e| !e.is_dir)
+    }
+}
+
+/// Sink for problems reported by validation rules
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Diagnostics {
+    /// Report an error
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.errors.push(message.into());
+    }
+
+    /// Report a warning
+    pub fn warning(&mut self, message: impl Into<String>) {
+        self.warnings.push(message.into());
+    }
+}
+
+impl MdzValidator {
+    /// Create validator with the built-in rules
+    pub fn new() -> Self {
+        Self {
+            rules: vec![
+                Box::new(InternalAnchorRule),
+                Box::new(SvgSafetyRule),
+                Box::new(MathSyntaxRule),
+                Box::new(CrossReferenceRule),
+            ],
+            profile: None,
+        }
+    }
+
+    /// Validate against a fixed profile instead of the document's declared version
+    pub fn set_profile(&mut self, profile: ValidationProfile) -> &mut Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Register an additional validation rule
+    pub fn add_rule<V: ValidationRule + 'static>(&mut self, rule: V) -> &mut Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Names of the registered rules, in execution order
+    pub fn rule_names(&self) -> Vec<&str> {
+        self.rules.iter().map(|r| r.name()).collect()
+    }
+
+    /// Validate MDZ file format
+    pub fn validate<R: Read + std::io::Seek>(&self, reader: R) -> Result<ValidationResult> {
+        let mut archive = ZipArchive::new(reader)?;
+        let mut result = ValidationResult::new();
+
+        // Check for main.md
+        let mut main_md_readable = false;
+        if let Ok(mut file) = archive.by_name("main.md") {
+            result.has_main_md = true;
+            let mut content = String::new();
+            if file.read_to_string(&mut content).is_ok() {
+                main_md_readable = true;
+            } else {
+                result.errors.push("main.md is not valid UTF-8".to_string());
+            }
+        } else {
+            result.errors.push("Missing required file: main.md".to_string());
+        }
+
+        // Check directory structure
+        let mut has_img_dir = false;
+        let mut has_css_dir = false;
+        let mut image_files = Vec::new();
+        let mut css_files = Vec::new();
+        let mut index = ArchiveIndex::default();
+
+        for i in 0..archive.len() {
+            let file = archive.by_index(i)?;
+            let name = file.name();
+
+            index.entries.push(ArchiveEntry {
+                name: name.to_string(),
+                size: file.size(),
+                compressed_size: file.compressed_size(),
+                is_dir: file.is_dir(),
+            });
+
+            if name.starts_with("img/") {
+                has_img_dir = true;
+                if !name.ends_with('/') {
+                    image_files.push(name.to_string());
+                }
+            } else if name.starts_with("css/") {
+                has_css_dir = true;
+                if !name.ends_with('/') {
+                    css_files.push(name.to_string());
+                }
+            }
+        }
+
+        result.has_img_dir = has_img_dir;
+        result.has_css_dir = has_css_dir;
+        result.image_files = image_files;
+        result.css_files = css_files;
+
+        // Select validation profile
+        match MdzParser::load_metadata_file(&mut archive) {
+            Ok(Some(metadata)) => result.declared_version = metadata.version,
+            Ok(None) => {}
+            Err(e) => result.errors.push(format!("Invalid metadata.json: {}", e)),
+        }
+        result.profile = self.select_profile(result.declared_version.as_deref(), &mut result.warnings);
+        let profile = result.profile;
+
+        // Findings that the strict profile treats as errors
+        let report = |result: &mut ValidationResult, message: String| {
+            if profile.is_strict() {
+                result.errors.push(message);
+            } else {
+                result.warnings.push(message);
+            }
+        };
+
+        // Validate file paths
+        for entry in &index.entries {
+            if let Err(e) = Self::validate_file_path(&entry.name) {
+                report(&mut result, format!("Invalid file path '{}': {}", entry.name, e));
+            }
+            if !entry.is_dir && !profile::is_defined_path(&entry.name) {
+                let message = format!("Unknown file not defined by spec v{}: {}", profile.spec_version(), entry.name);
+                report(&mut result, message);
+            }
+        }
+
+        // Validate image formats
+        for img_file in result.image_files.clone() {
+            if !Self::is_valid_image_format(&img_file) {
+                report(&mut result, format!("Unsupported image format: {}", img_file));
+            }
+        }
+
+        // Analyze stylesheets
+        for css_file in result.css_files.clone() {
+            let mut content = String::new();
+            if archive.by_name(&css_file)?.read_to_string(&mut content).is_err() {
+                report(&mut result, format!("Stylesheet is not valid UTF-8: {}", css_file));
+                continue;
+            }
+
+            let analysis = css::analyze(&content);
+            for error in &analysis.errors {
+                report(&mut result, format!("CSS syntax error in {}:{}: {}", css_file, error.line, error.message));
+            }
+            for reference in &analysis.references {
+                let what = match reference.kind {
+                    CssReferenceKind::Import => "@import",
+                    CssReferenceKind::Url => "url()",
+                };
+                match reference.target(&css_file) {
+                    CssTarget::External(url) => {
+                        let message = format!(
+                            "{}:{}: {} loads external resource {} (privacy leak, spec §8.2)",
+                            css_file, reference.line, what, url
+                        );
+                        report(&mut result, message);
+                    }
+                    CssTarget::Archive(path) if !index.contains(&path) => {
+                        let message = format!(
+                            "{}:{}: {} references missing file {}",
+                            css_file, reference.line, what, path
+                        );
+                        report(&mut result, message);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Check for features beyond the profile's spec version
+        for feature in SpecFeature::detect(&index) {
+            if !profile.supports(feature) {
+                let message = format!(
+                    "Uses {} (spec v{}), which v{} readers do not understand",
+                    feature,
+                    feature.since(),
+                    profile.spec_version()
+                );
+                report(&mut result, message);
+                result.unsupported_features.push(feature);
+            } else if feature == SpecFeature::Scripts {
+                result.warnings.push("Scripts in js/ are not executed by default (spec §8.1)".to_string());
+            }
+        }
+
+        // Check for style.css
+        if result.css_files.contains(&"css/style.css".to_string()) {
+            result.has_main_css = true;
+        }
+
+        // Run document rules
+        if main_md_readable {
+            match MdzParser::parse_archive(&mut archive) {
+                Ok(document) => {
+                    let mut diagnostics = Diagnostics::default();
+                    for rule in &self.rules {
+                        rule.check(&index, &document, &mut diagnostics);
+                    }
+                    result.errors.extend(diagnostics.errors);
+                    result.warnings.extend(diagnostics.warnings);
+                }
+                Err(e) => result.errors.push(format!("Failed to parse document: {}", e)),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Pick the configured profile, or the one matching the declared version
+    fn select_profile(&self, declared: Option<&str>, warnings: &mut Vec<String>) -> ValidationProfile {
+        let declared_profile = declared.map(|v| (v, ValidationProfile::for_version(v)));
+
+        match (self.profile, declared_profile) {
+            (Some(profile), Some((version, _))) => {
+                if ValidationProfile::for_version(version).map(|p| p.spec_version()) != Some(profile.spec_version()) {
+                    warnings.push(format!(
+                        "Document declares spec version {} but is validated with profile {}",
+                        version, profile
+                    ));
+                }
+                profile
+            }
+            (Some(profile), None) => profile,
+            (None, Some((_, Some(profile)))) => profile,
+            (None, Some((version, None))) => {
+                let latest = ValidationProfile::latest();
+                warnings.push(format!(
+                    "Unknown spec version {}, validating with profile {}",
+                    version, latest
+                ));
+                latest
+            }
+            (None, None) => ValidationProfile::default(),
+        }
+    }
+
+    /// Validate file path according to MDZ specification
+    fn validate_file_path(path: &str) -> Result<()> {
+        if path.contains("..") {
+            return Err(MdzError::Validation("Path traversal not allowed".to_string()));
+        }
+
+        if path.contains(' ') {
+            return Err(MdzError::Validation("Spaces in file paths not recommended".to_string()));
+        }
+
+        if !path.is_ascii() {
+            return Err(MdzError::Validation("Non-ASCII characters not recommended".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Check if file has valid image format
+    fn is_valid_image_format(path: &str) -> bool {
+        let valid_extensions = ["jpg", "jpeg", "png", "gif", "svg", "webp"];
+        if let Some(ext) = path.rsplit('.').next() {
+            valid_extensions.contains(&ext.to_lowercase().as_str())
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for MdzValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Validation result
+#[derive(Debug, Clone)]
+pub struct ValidationResult {
+    pub has_main_md: bool,
+    pub has_img_dir: bool,
+    pub has_css_dir: bool,
+    pub has_main_css: bool,
+    pub image_files: Vec<String>,
+    pub css_files: Vec<String>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    pub profile: ValidationProfile,
+    pub declared_version: Option<String>,
+    pub unsupported_features: Vec<SpecFeature>,
+}
+
+impl ValidationResult {
+    fn new() -> Self {
+        Self {
+            has_main_md: false,
+            has_img_dir: false,
+            has_css_dir: false,
+            has_main_css: false,
+            image_files: Vec::new(),
+            css_files: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            profile: ValidationProfile::default(),
+            declared_version: None,
+            unsupported_features: Vec::new(),
+        }
+    }
+
+    /// Check if validation passed
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty() && self.has_main_md
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    fn archive(files: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        Cursor::new(zip.finish().unwrap().into_inner())
+    }
+
+    fn validate(profile: Option<ValidationProfile>, files: &[(&str, &str)]) -> ValidationResult {
+        let mut validator = MdzValidator::new();
+        if let Some(profile) = profile {
+            validator.set_profile(profile);
+        }
+        validator.validate(archive(files)).unwrap()
+    }
+
+    #[test]
+    fn declared_version_selects_the_profile() {
+        let files = [("main.md", "# Title\n"), ("metadata.json", r#"{"version": "1.1"}"#), ("fonts/a.woff2", "")];
+        let result = validate(None, &files);
+        assert_eq!(result.profile, ValidationProfile::V1_1);
+        assert_eq!(result.declared_version.as_deref(), Some("1.1"));
+        assert!(result.unsupported_features.is_empty());
+
+        let result = validate(None, &[("main.md", "# Title\n"), ("metadata.json", r#"{"version": "9"}"#)]);
+        assert_eq!(result.profile, ValidationProfile::latest());
+        assert!(result.warnings.iter().any(|w| w.starts_with("Unknown spec version 9")));
+
+        assert_eq!(validate(None, &[("main.md", "# Title\n")]).profile, ValidationProfile::default());
+    }
+
+    #[test]
+    fn strict_profile_turns_findings_into_errors() {
+        let files = [("main.md", "# Title\n"), ("fonts/a.woff2", ""), ("notes.txt", "")];
+
+        let lenient = validate(Some(ValidationProfile::V1_0Lenient), &files);
+        assert!(lenient.is_valid());
+        assert_eq!(lenient.unsupported_features, [SpecFeature::Fonts]);
+        assert!(lenient.warnings.iter().any(|w| w.contains("Unknown file not defined by spec v1.0: notes.txt")));
+
+        let strict = validate(Some(ValidationProfile::V1_0Strict), &files);
+        assert!(!strict.is_valid());
+        assert!(strict.errors.iter().any(|e| e.starts_with("Uses fonts/ directory (spec v1.1)")));
+    }
+}
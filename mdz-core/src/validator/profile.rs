@@ -0,0 +1,222 @@
+use super::ArchiveIndex;
+use crate::MdzError;
+use std::fmt;
+use std::str::FromStr;
+
+/// Validation profile tied to an MDZ specification version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ValidationProfile {
+    /// Spec v1.0 with naming recommendations enforced and extensions rejected
+    V1_0Strict,
+    /// Spec v1.0 with extensions and naming issues reported as warnings
+    #[default]
+    V1_0Lenient,
+    /// Spec v1.1: multiple stylesheets, fonts/, js/ and metadata.json
+    V1_1,
+}
+
+/// Format feature introduced after spec v1.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpecFeature {
+    MultipleStylesheets,
+    Fonts,
+    Scripts,
+    MetadataFile,
+}
+
+impl ValidationProfile {
+    /// All known profiles
+    pub fn all() -> &'static [ValidationProfile] {
+        &[Self::V1_0Strict, Self::V1_0Lenient, Self::V1_1]
+    }
+
+    /// Profile name as used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::V1_0Strict => "v1.0-strict",
+            Self::V1_0Lenient => "v1.0-lenient",
+            Self::V1_1 => "v1.1",
+        }
+    }
+
+    /// Specification version the profile validates against
+    pub fn spec_version(&self) -> &'static str {
+        match self {
+            Self::V1_0Strict | Self::V1_0Lenient => "1.0",
+            Self::V1_1 => "1.1",
+        }
+    }
+
+    /// Whether recommendations and unknown content are reported as errors
+    pub fn is_strict(&self) -> bool {
+        matches!(self, Self::V1_0Strict)
+    }
+
+    /// Default profile for a declared spec version
+    pub fn for_version(version: &str) -> Option<Self> {
+        match version.trim().trim_start_matches('v') {
+            "1" | "1.0" => Some(Self::V1_0Lenient),
+            "1.1" => Some(Self::V1_1),
+            _ => None,
+        }
+    }
+
+    /// Newest profile known to this validator
+    pub fn latest() -> Self {
+        Self::V1_1
+    }
+
+    /// Check if the profile's spec version defines a feature
+    pub fn supports(&self, feature: SpecFeature) -> bool {
+        version_at_least(self.spec_version(), feature.since())
+    }
+}
+
+impl fmt::Display for ValidationProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ValidationProfile {
+    type Err = MdzError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|p| p.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::all().iter().map(|p| p.name()).collect();
+                MdzError::Validation(format!(
+                    "Unknown validation profile '{}' (expected one of: {})",
+                    s,
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+impl SpecFeature {
+    /// Human-readable feature description
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::MultipleStylesheets => "multiple CSS files",
+            Self::Fonts => "fonts/ directory",
+            Self::Scripts => "js/ directory",
+            Self::MetadataFile => "metadata.json",
+        }
+    }
+
+    /// Specification version that introduced the feature
+    pub fn since(&self) -> &'static str {
+        "1.1"
+    }
+
+    /// Detect features used by an archive
+    pub fn detect(index: &ArchiveIndex) -> Vec<SpecFeature> {
+        let mut features = Vec::new();
+
+        if index
+            .files()
+            .any(|e| e.name.starts_with("css/") && e.name != "css/style.css")
+        {
+            features.push(Self::MultipleStylesheets);
+        }
+        if index.entries.iter().any(|e| e.name.starts_with("fonts/")) {
+            features.push(Self::Fonts);
+        }
+        if index.entries.iter().any(|e| e.name.starts_with("js/")) {
+            features.push(Self::Scripts);
+        }
+        if index.contains("metadata.json") {
+            features.push(Self::MetadataFile);
+        }
+
+        features
+    }
+
+    /// Check if an archive path belongs to this feature
+    fn owns(&self, path: &str) -> bool {
+        match self {
+            Self::MultipleStylesheets => path.starts_with("css/"),
+            Self::Fonts => path.starts_with("fonts/"),
+            Self::Scripts => path.starts_with("js/"),
+            Self::MetadataFile => path == "metadata.json",
+        }
+    }
+}
+
+impl fmt::Display for SpecFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// Check if an archive path is defined by v1.0 or a later spec feature
+pub(crate) fn is_defined_path(path: &str) -> bool {
+    path == "main.md"
+        || path.starts_with("img/")
+        || [SpecFeature::MultipleStylesheets, SpecFeature::Fonts, SpecFeature::Scripts, SpecFeature::MetadataFile]
+            .iter()
+            .any(|f| f.owns(path))
+}
+
+/// Compare dotted version strings numerically
+fn version_at_least(version: &str, required: &str) -> bool {
+    let parse = |v: &str| -> Vec<u32> { v.split('.').map(|p| p.parse().unwrap_or(0)).collect() };
+    parse(version) >= parse(required)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validator::ArchiveEntry;
+
+    fn index(names: &[&str]) -> ArchiveIndex {
+        let entries = names
+            .iter()
+            .map(|name| ArchiveEntry {
+                name: name.to_string(),
+                size: 0,
+                compressed_size: 0,
+                is_dir: name.ends_with('/'),
+            })
+            .collect();
+        ArchiveIndex { entries }
+    }
+
+    #[test]
+    fn profiles_parse_and_follow_declared_versions() {
+        for profile in ValidationProfile::all() {
+            assert_eq!(profile.name().parse::<ValidationProfile>().unwrap(), *profile);
+        }
+        assert!("v2.0".parse::<ValidationProfile>().is_err());
+
+        assert_eq!(ValidationProfile::for_version("1"), Some(ValidationProfile::V1_0Lenient));
+        assert_eq!(ValidationProfile::for_version(" v1.1 "), Some(ValidationProfile::V1_1));
+        assert_eq!(ValidationProfile::for_version("1.2"), None);
+        assert!(ValidationProfile::V1_0Strict.is_strict() && !ValidationProfile::V1_0Lenient.is_strict());
+    }
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert!(version_at_least("1.10", "1.9"));
+        assert!(version_at_least("1.1", "1.1"));
+        assert!(!version_at_least("1", "1.1"));
+        assert!(!ValidationProfile::V1_0Lenient.supports(SpecFeature::Fonts));
+        assert!(ValidationProfile::V1_1.supports(SpecFeature::MetadataFile));
+    }
+
+    #[test]
+    fn detects_features_and_defined_paths() {
+        assert_eq!(SpecFeature::detect(&index(&["main.md", "css/", "css/style.css", "img/a.png"])), []);
+        assert_eq!(
+            SpecFeature::detect(&index(&["main.md", "css/print.css", "fonts/", "js/app.js", "metadata.json"])),
+            [SpecFeature::MultipleStylesheets, SpecFeature::Fonts, SpecFeature::Scripts, SpecFeature::MetadataFile]
+        );
+
+        assert!(is_defined_path("main.md") && is_defined_path("img/a.png") && is_defined_path("fonts/a.woff2"));
+        assert!(!is_defined_path("notes.txt") && !is_defined_path("metadata.json.bak"));
+    }
+}
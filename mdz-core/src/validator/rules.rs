@@ -0,0 +1,152 @@
+use super::{ArchiveIndex, Diagnostics, ValidationRule};
+use crate::anchor;
+use crate::figure;
+use crate::math::{self, MathExpander};
+use crate::{svg, MdzDocument};
+use pulldown_cmark::{Event, Parser, Tag};
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// Reports `#fragment` links that match no heading, footnote or HTML id
+pub struct InternalAnchorRule;
+
+impl ValidationRule for InternalAnchorRule {
+    fn name(&self) -> &str {
+        "internal-anchors"
+    }
+
+    fn check(&self, _index: &ArchiveIndex, document: &MdzDocument, diagnostics: &mut Diagnostics) {
+        let dialect = document.metadata.dialect.unwrap_or_default();
+        static ID_PATTERN: OnceLock<Regex> = OnceLock::new();
+        let id_pattern = ID_PATTERN.get_or_init(|| Regex::new(r#"\b(?:id|name)\s*=\s*["']([^"']+)["']"#).unwrap());
+        // Math is taken out first, as the renderer does, so heading slugs match
+        let (content, spans) = if dialect.math {
+            math::extract(&document.content)
+        } else {
+            (document.content.clone(), Vec::new())
+        };
+        let mut expander = MathExpander::new(&spans, |_| String::new());
+        let events: Vec<Event> = Parser::new_ext(&content, dialect.parser_options())
+            .flat_map(|event| expander.process(event))
+            .collect();
+        let mut anchors: HashSet<String> =
+            anchor::collect_headings(&events).into_iter().map(|heading| heading.id).collect();
+        if dialect.figures {
+            anchors.extend(figure::collect_labels(&events).labels().map(str::to_string));
+        }
+        let mut fragments: Vec<String> = Vec::new();
+
+        for event in events {
+            match event {
+                Event::Start(Tag::FootnoteDefinition(name)) => {
+                    anchors.insert(name.to_string());
+                }
+                Event::Start(Tag::Link(_, dest, _)) => {
+                    if let Some(fragment) = dest.strip_prefix('#') {
+                        if !fragments.iter().any(|f| f == fragment) {
+                            fragments.push(fragment.to_string());
+                        }
+                    }
+                }
+                Event::Html(html) => {
+                    for cap in id_pattern.captures_iter(&html) {
+                        anchors.insert(cap[1].to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for fragment in fragments {
+            if !fragment.is_empty() && !anchors.contains(&fragment) {
+                diagnostics.warning(format!("Broken internal link: #{}", fragment));
+            }
+        }
+    }
+}
+
+/// Reports scripts, event handlers, foreignObject and external references in SVG images
+pub struct SvgSafetyRule;
+
+impl ValidationRule for SvgSafetyRule {
+    fn name(&self) -> &str {
+        "svg-safety"
+    }
+
+    fn check(&self, _index: &ArchiveIndex, document: &MdzDocument, diagnostics: &mut Diagnostics) {
+        let mut paths: Vec<_> = document
+            .images
+            .keys()
+            .filter(|p| p.to_lowercase().ends_with(".svg"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let markup = String::from_utf8_lossy(&document.images[path]);
+            for issue in svg::scan(&markup) {
+                diagnostics.warning(format!("Unsafe SVG content in {}: {}", path, issue));
+            }
+        }
+    }
+}
+
+/// Reports `$...$` and `$$...$$` math that cannot be converted to MathML
+pub struct MathSyntaxRule;
+
+impl ValidationRule for MathSyntaxRule {
+    fn name(&self) -> &str {
+        "math-syntax"
+    }
+
+    fn check(&self, _index: &ArchiveIndex, document: &MdzDocument, diagnostics: &mut Diagnostics) {
+        if !document.metadata.dialect.unwrap_or_default().math {
+            return;
+        }
+        for span in math::find_math(&document.content) {
+            if let Err(e) = span.to_mathml() {
+                diagnostics.warning(format!("Invalid math {}: {}", span.source.trim(), e));
+            }
+        }
+    }
+}
+
+/// Reports `@fig:`, `@tbl:` and `@lst:` references to undefined labels, and labels defined twice
+pub struct CrossReferenceRule;
+
+impl ValidationRule for CrossReferenceRule {
+    fn name(&self) -> &str {
+        "cross-references"
+    }
+
+    fn check(&self, _index: &ArchiveIndex, document: &MdzDocument, diagnostics: &mut Diagnostics) {
+        let dialect = document.metadata.dialect.unwrap_or_default();
+        if !dialect.figures {
+            return;
+        }
+        let events = figure::merge_text(Parser::new_ext(&document.content, dialect.parser_options()).collect());
+        let labels = figure::collect_labels(&events);
+
+        for label in &labels.duplicates {
+            diagnostics.warning(format!("Duplicate label: {}", label));
+        }
+
+        let mut in_code_block = false;
+        let mut reported: Vec<&str> = Vec::new();
+        for event in &events {
+            match event {
+                Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+                Event::Text(text) if !in_code_block => {
+                    for reference in figure::find_references(text) {
+                        if labels.get(reference.label).is_none() && !reported.contains(&reference.label) {
+                            diagnostics.warning(format!("Unresolved cross-reference: @{}", reference.label));
+                            reported.push(reference.label);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}